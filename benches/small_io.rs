@@ -0,0 +1,92 @@
+//! Throughput of many small writes and reads, which is dominated by the per-call overhead of
+//! buffering rather than by the encryption itself
+//!
+//! Median times on a 256 KiB stream before and after inlining the buffering helpers and skipping
+//! the call into `fill_buffer` and `drain_pending` while there is nothing for them to do. The
+//! writes didn't change beyond the noise, as each one still goes through
+//! `aead::Buffer::extend_from_slice`:
+//!
+//! | bench             | before   | after    |
+//! |-------------------|----------|----------|
+//! | small_writes/1    | 3.54 ms  | 3.39 ms  |
+//! | small_writes/16   | 620 µs   | 635 µs   |
+//! | small_writes/64   | 374 µs   | 379 µs   |
+//! | small_reads/1     | 3.16 ms  | 2.74 ms  |
+//! | small_reads/16    | 656 µs   | 496 µs   |
+//! | small_reads/64    | 405 µs   | 388 µs   |
+
+use aead_io::{DecryptBE32BufReader, EncryptBE32BufWriter};
+use chacha20poly1305::ChaCha20Poly1305;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::io::{Read, Write};
+use std::time::Duration;
+
+const KEY: &[u8; 32] = b"my very super super secret key!!";
+const PLAINTEXT_LEN: usize = 256 << 10;
+const BUFFER_LEN: usize = 16 << 10;
+const IO_SIZES: [usize; 3] = [1, 16, 64];
+
+fn encrypt(plaintext: &[u8], io_size: usize) -> Vec<u8> {
+    let mut ciphertext = Vec::with_capacity(plaintext.len() * 2);
+    let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+        KEY.into(),
+        &Default::default(),
+        Vec::with_capacity(BUFFER_LEN),
+        &mut ciphertext,
+    )
+    .unwrap();
+    for part in plaintext.chunks(io_size) {
+        writer.write_all(part).unwrap();
+    }
+    writer.flush().unwrap();
+    drop(writer);
+    ciphertext
+}
+
+fn small_writes(c: &mut Criterion) {
+    let plaintext = vec![0x42; PLAINTEXT_LEN];
+    let mut group = c.benchmark_group("small_writes");
+    group.throughput(Throughput::Bytes(PLAINTEXT_LEN as u64));
+    for io_size in IO_SIZES {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(io_size),
+            &io_size,
+            |b, io_size| b.iter(|| encrypt(&plaintext, *io_size)),
+        );
+    }
+    group.finish();
+}
+
+fn small_reads(c: &mut Criterion) {
+    let ciphertext = encrypt(&vec![0x42; PLAINTEXT_LEN], BUFFER_LEN);
+    let mut group = c.benchmark_group("small_reads");
+    group.throughput(Throughput::Bytes(PLAINTEXT_LEN as u64));
+    for io_size in IO_SIZES {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(io_size),
+            &io_size,
+            |b, io_size| {
+                let mut out = vec![0u8; *io_size];
+                b.iter(|| {
+                    let mut reader = DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+                        KEY.into(),
+                        Vec::with_capacity(BUFFER_LEN),
+                        ciphertext.as_slice(),
+                    )
+                    .unwrap();
+                    while reader.read(&mut out).unwrap() > 0 {}
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default()
+        .sample_size(20)
+        .measurement_time(Duration::from_secs(3));
+    targets = small_writes, small_reads
+}
+criterion_main!(benches);
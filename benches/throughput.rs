@@ -0,0 +1,88 @@
+//! Encrypt and decrypt throughput for different AEADs, buffer sizes and message sizes
+
+use aead::generic_array::typenum::U12;
+use aead::stream::{Nonce, StreamBE32};
+use aead::{Key, NewAead};
+use aead_io::{aead, DecryptBE32BufReader, EncryptBE32BufWriter};
+use aes_gcm::Aes256Gcm;
+use chacha20poly1305::ChaCha20Poly1305;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::io::{Read, Write};
+use std::time::Duration;
+
+const BUFFER_SIZES: [usize; 4] = [1 << 10, 16 << 10, 64 << 10, 1 << 20];
+const MESSAGE_SIZES: [usize; 2] = [64 << 10, 4 << 20];
+
+fn encrypt<A>(buffer_size: usize, plaintext: &[u8]) -> Vec<u8>
+where
+    A: aead::AeadInPlace<NonceSize = U12> + NewAead,
+{
+    let mut ciphertext = Vec::with_capacity(plaintext.len() * 2);
+    let mut writer = EncryptBE32BufWriter::<A, _, _>::new(
+        &Key::<A>::default(),
+        &Nonce::<A, StreamBE32<A>>::default(),
+        Vec::with_capacity(buffer_size),
+        &mut ciphertext,
+    )
+    .unwrap();
+    writer.write_all(plaintext).unwrap();
+    writer.flush().unwrap();
+    drop(writer);
+    ciphertext
+}
+
+fn decrypt<A>(buffer_size: usize, ciphertext: &[u8], out: &mut [u8])
+where
+    A: aead::AeadInPlace<NonceSize = U12> + NewAead,
+{
+    let mut reader = DecryptBE32BufReader::<A, _, _>::new(
+        &Key::<A>::default(),
+        Vec::with_capacity(buffer_size),
+        ciphertext,
+    )
+    .unwrap();
+    while reader.read(out).unwrap() > 0 {}
+}
+
+fn bench_aead<A>(c: &mut Criterion, name: &str)
+where
+    A: aead::AeadInPlace<NonceSize = U12> + NewAead,
+{
+    for message_size in MESSAGE_SIZES {
+        let plaintext = vec![0x42; message_size];
+        let mut group = c.benchmark_group(format!("{}/{}KiB", name, message_size >> 10));
+        group.throughput(Throughput::Bytes(message_size as u64));
+        for buffer_size in BUFFER_SIZES {
+            if buffer_size > message_size {
+                continue;
+            }
+            group.bench_with_input(
+                BenchmarkId::new("encrypt", buffer_size),
+                &buffer_size,
+                |b, buffer_size| b.iter(|| encrypt::<A>(*buffer_size, &plaintext)),
+            );
+            let ciphertext = encrypt::<A>(buffer_size, &plaintext);
+            let mut out = vec![0; 64 << 10];
+            group.bench_with_input(
+                BenchmarkId::new("decrypt", buffer_size),
+                &buffer_size,
+                |b, buffer_size| b.iter(|| decrypt::<A>(*buffer_size, &ciphertext, &mut out)),
+            );
+        }
+        group.finish();
+    }
+}
+
+fn throughput(c: &mut Criterion) {
+    bench_aead::<ChaCha20Poly1305>(c, "chacha20poly1305");
+    bench_aead::<Aes256Gcm>(c, "aes256gcm");
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default()
+        .sample_size(10)
+        .measurement_time(Duration::from_secs(2));
+    targets = throughput
+}
+criterion_main!(benches);
@@ -0,0 +1,21 @@
+use std::env;
+use std::process::Command;
+
+/// Sets `cfg(nightly)` when building with a nightly compiler, which the `read-buf` feature
+/// needs for the unstable `std::io::Read::read_buf`
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rustc-check-cfg=cfg(nightly)");
+    let rustc = env::var_os("RUSTC").unwrap_or_else(|| "rustc".into());
+    let nightly = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .map(|output| {
+            let version = String::from_utf8_lossy(&output.stdout);
+            version.contains("nightly") || version.contains("-dev")
+        })
+        .unwrap_or(false);
+    if nightly {
+        println!("cargo:rustc-cfg=nightly");
+    }
+}
@@ -1,4 +1,4 @@
-use aead_io::{ArrayBuffer, DecryptBufReader, EncryptBufWriter};
+use aead_io::{ArrayBuffer, DecryptBufReader, EncryptBufWriter, FlushMode};
 use rand::prelude::*;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, Read, Write};
@@ -8,34 +8,33 @@ type StreamImpl = aead_io::aead::stream::StreamBE32<AeadImpl>;
 type Key = aead_io::aead::Key<AeadImpl>;
 type Nonce = aead_io::aead::stream::Nonce<AeadImpl, StreamImpl>;
 
+const CHUNK_SIZE: usize = aead_io::RECOMMENDED_CHUNK_SIZE;
+// room for the chunk and its 16 byte tag
+const CIPHERTEXT_CHUNK_SIZE: usize = CHUNK_SIZE + 16;
+
 fn encrypt(key: &Key, nonce: &Nonce, input: impl Read, mut output: impl Write) -> io::Result<()> {
     // create an encrypted writer
     let mut writer = EncryptBufWriter::<AeadImpl, _, _, StreamImpl>::new(
         key,
         nonce,
-        ArrayBuffer::<128>::new(),
+        ArrayBuffer::<CIPHERTEXT_CHUNK_SIZE>::new(),
         &mut output,
     )?;
-    // create a buf reader to read chunks
-    let mut reader = BufReader::with_capacity(64, input);
-    loop {
-        // read a chunk
-        let chunk = reader.fill_buf()?;
-        if chunk.is_empty() {
-            break;
-        }
-        // write encrypted output to output file
-        let written = writer.write(chunk)?;
-        reader.consume(written);
-    }
+    // encrypt everything from the input file into the output file
+    writer.write_from(input)?;
+    // complete the stream
+    writer.flush_with(FlushMode::Finalize)?;
     Ok(())
 }
 
 fn decrypt(key: &Key, input: impl Read, mut output: impl Write) -> io::Result<()> {
     // create a buf reader for reading decrypted chunks
-    let reader =
-        DecryptBufReader::<AeadImpl, _, _, StreamImpl>::new(key, ArrayBuffer::<256>::new(), input)?;
-    let mut buf_reader = BufReader::with_capacity(64, reader);
+    let reader = DecryptBufReader::<AeadImpl, _, _, StreamImpl>::new(
+        key,
+        ArrayBuffer::<CIPHERTEXT_CHUNK_SIZE>::new(),
+        input,
+    )?;
+    let mut buf_reader = BufReader::with_capacity(CHUNK_SIZE, reader);
     loop {
         // read a chunk
         let chunk = buf_reader.fill_buf()?;
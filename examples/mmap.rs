@@ -0,0 +1,103 @@
+use aead_io::{ArrayBuffer, DecryptBufReader, EncryptBufWriter, FlushMode};
+use memmap2::Mmap;
+use rand::prelude::*;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+
+type AeadImpl = chacha20poly1305::ChaCha20Poly1305;
+type StreamImpl = aead_io::aead::stream::StreamBE32<AeadImpl>;
+type Key = aead_io::aead::Key<AeadImpl>;
+type Nonce = aead_io::aead::stream::Nonce<AeadImpl, StreamImpl>;
+
+const CHUNK_SIZE: usize = aead_io::RECOMMENDED_CHUNK_SIZE;
+// room for the chunk and its 16 byte tag
+const CIPHERTEXT_CHUNK_SIZE: usize = CHUNK_SIZE + 16;
+
+fn encrypt(key: &Key, nonce: &Nonce, input: &Path, output: &Path) -> io::Result<()> {
+    let mut writer = EncryptBufWriter::<AeadImpl, _, _, StreamImpl>::new(
+        key,
+        nonce,
+        ArrayBuffer::<CIPHERTEXT_CHUNK_SIZE>::new(),
+        File::create(output)?,
+    )?;
+    writer.write_from(File::open(input)?)?;
+    writer.flush_with(FlushMode::Finalize)?;
+    Ok(())
+}
+
+fn decrypt_mapped(key: &Key, input: &Path, mut output: impl Write) -> io::Result<()> {
+    let file = File::open(input)?;
+    // safety: the file must not be modified while it is mapped
+    let map = unsafe { Mmap::map(&file)? };
+    // the mapped file is read as a plain byte slice, so reading a chunk is a single copy out of
+    // the page cache without any read syscalls
+    let mut reader = DecryptBufReader::<AeadImpl, _, _, StreamImpl>::new(
+        key,
+        ArrayBuffer::<CIPHERTEXT_CHUNK_SIZE>::new(),
+        &map[..],
+    )?;
+    // decrypt every chunk straight into a buffer of our own, skipping the copy out of the
+    // reader's internal buffer
+    let mut chunk = Vec::with_capacity(CIPHERTEXT_CHUNK_SIZE);
+    while reader.read_into(&mut chunk)? > 0 {
+        output.write_all(&chunk)?;
+    }
+    Ok(())
+}
+
+fn main() -> io::Result<()> {
+    // generate a random key and a random nonce
+    let mut rng = rand::thread_rng();
+    let key = {
+        let mut key = Key::default();
+        rng.fill(key.as_mut_slice());
+        key
+    };
+    let nonce = {
+        let mut nonce = Nonce::default();
+        rng.fill(nonce.as_mut_slice());
+        nonce
+    };
+
+    // create a directory for playing with
+    let dir = tempfile::tempdir()?;
+
+    // encrypt a file into a new file
+    let encrypted_path = dir.path().join("Cargo.toml.enc");
+    encrypt(&key, &nonce, Path::new("./Cargo.toml"), &encrypted_path)?;
+    println!("encrypted to: {:?}", encrypted_path.display());
+
+    // decrypt the memory-mapped encrypted file into a new file
+    let decrypted_path = dir.path().join("Cargo.toml");
+    decrypt_mapped(&key, &encrypted_path, File::create(&decrypted_path)?)?;
+    println!("decrypted to: {:?}", decrypted_path.display());
+
+    // keep files around to look at
+    std::mem::forget(dir);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mapped_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let plaintext = (0..3 * CHUNK_SIZE as u32 + 100)
+            .map(|i| i as u8)
+            .collect::<Vec<_>>();
+        let input = dir.path().join("plaintext");
+        std::fs::write(&input, &plaintext).unwrap();
+
+        let key = Key::from_slice(b"my very super super secret key!!");
+        let encrypted = dir.path().join("ciphertext");
+        encrypt(key, &Nonce::default(), &input, &encrypted).unwrap();
+
+        let mut out = Vec::new();
+        decrypt_mapped(key, &encrypted, &mut out).unwrap();
+        assert_eq!(out, plaintext);
+    }
+}
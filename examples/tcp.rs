@@ -0,0 +1,164 @@
+use aead_io::{DecryptBufReader, EncryptBufWriter, FlushMode, Progress};
+use rand::prelude::*;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+type AeadImpl = chacha20poly1305::ChaCha20Poly1305;
+type StreamImpl = aead_io::aead::stream::StreamBE32<AeadImpl>;
+type Key = aead_io::aead::Key<AeadImpl>;
+type Nonce = aead_io::aead::stream::Nonce<AeadImpl, StreamImpl>;
+
+// small chunks, so that the stream spans many TCP segments
+const CHUNK_SIZE: usize = 1024;
+// room for the chunk and its 16 byte tag
+const CIPHERTEXT_CHUNK_SIZE: usize = CHUNK_SIZE + 16;
+
+/// Encrypts `plaintext` to the connection. The stream ends with an end marker, so the server
+/// knows where it ends without the connection being closed, and can still reply
+fn send<W: Write>(key: &Key, nonce: &Nonce, plaintext: &[u8], output: W) -> io::Result<W> {
+    let mut writer = EncryptBufWriter::<AeadImpl, _, _, StreamImpl>::new(
+        key,
+        nonce,
+        Vec::with_capacity(CIPHERTEXT_CHUNK_SIZE),
+        output,
+    )?
+    .with_end_marker();
+    // write in odd sized pieces, as an application producing data bit by bit would
+    for part in plaintext.chunks(333) {
+        writer.write_all(part)?;
+    }
+    writer.flush_with(FlushMode::Finalize)?;
+    Ok(writer.into_inner()?)
+}
+
+/// Decrypts the stream from the connection into `output`, and hands the connection back once
+/// the stream has ended. The socket is non-blocking, as in an event loop: a read which would
+/// block leaves the chunk half read, and the next call picks up where it left off. Waiting a
+/// moment stands in for waiting on the poller
+fn receive(key: &Key, stream: TcpStream, mut output: impl Write) -> io::Result<TcpStream> {
+    stream.set_nonblocking(true)?;
+    let mut reader = DecryptBufReader::<AeadImpl, _, _, StreamImpl>::new(
+        key,
+        Vec::with_capacity(CIPHERTEXT_CHUNK_SIZE),
+        stream,
+    )?;
+    let mut buf = [0u8; 256];
+    loop {
+        match reader.try_read(&mut buf)? {
+            Progress::Pending => thread::sleep(Duration::from_millis(1)),
+            Progress::Buffered(0) | Progress::Flushed(0) if reader.is_at_eof() => break,
+            Progress::Buffered(read) | Progress::Flushed(read) => output.write_all(&buf[..read])?,
+        }
+    }
+    let stream = reader.into_inner();
+    stream.set_nonblocking(false)?;
+    Ok(stream)
+}
+
+/// Accepts a single connection, decrypts the stream sent over it and replies with the number of
+/// plaintext bytes received
+fn serve(key: Key, listener: TcpListener) -> io::Result<Vec<u8>> {
+    let (stream, _) = listener.accept()?;
+    let mut plaintext = Vec::new();
+    let mut stream = receive(&key, stream, &mut plaintext)?;
+    stream.write_all(&(plaintext.len() as u64).to_be_bytes())?;
+    Ok(plaintext)
+}
+
+/// Connects to the server, sends `plaintext` through `wrap` and returns the number of bytes the
+/// server acknowledged
+fn connect<W: Write>(
+    key: &Key,
+    nonce: &Nonce,
+    addr: std::net::SocketAddr,
+    plaintext: &[u8],
+    wrap: impl FnOnce(TcpStream) -> W,
+    unwrap: impl FnOnce(W) -> TcpStream,
+) -> io::Result<u64> {
+    let stream = TcpStream::connect(addr)?;
+    stream.set_nodelay(true)?;
+    let mut stream = unwrap(send(key, nonce, plaintext, wrap(stream))?);
+    let mut ack = [0u8; 8];
+    stream.read_exact(&mut ack)?;
+    stream.shutdown(Shutdown::Both)?;
+    Ok(u64::from_be_bytes(ack))
+}
+
+fn main() -> io::Result<()> {
+    // generate a random key and a random nonce
+    let mut rng = rand::thread_rng();
+    let key = {
+        let mut key = Key::default();
+        rng.fill(key.as_mut_slice());
+        key
+    };
+    let nonce = {
+        let mut nonce = Nonce::default();
+        rng.fill(nonce.as_mut_slice());
+        nonce
+    };
+
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let server = thread::spawn(move || serve(key, listener));
+
+    let plaintext = std::fs::read("./Cargo.toml")?;
+    let acked = connect(&key, &nonce, addr, &plaintext, |s| s, |s| s)?;
+    println!(
+        "sent {} bytes, the server acknowledged {}",
+        plaintext.len(),
+        acked
+    );
+
+    let received = server.join().expect("server panicked")?;
+    assert_eq!(received, plaintext);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes a few bytes at a time with pauses in between, so that chunk headers and chunks
+    /// arrive split across TCP segments, and the server runs out of data part way through them
+    struct Trickle(TcpStream);
+
+    impl Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            thread::sleep(Duration::from_micros(200));
+            let len = buf.len().min(5);
+            self.0.write(&buf[..len])
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            self.0.flush()
+        }
+    }
+
+    fn round_trip(plaintext: &[u8], trickle: bool) {
+        let key = Key::from_slice(b"my very super super secret key!!").to_owned();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || serve(key, listener));
+
+        let acked = if trickle {
+            connect(&key, &Nonce::default(), addr, plaintext, Trickle, |t| t.0)
+        } else {
+            connect(&key, &Nonce::default(), addr, plaintext, |s| s, |s| s)
+        }
+        .unwrap();
+        assert_eq!(acked, plaintext.len() as u64);
+        assert_eq!(server.join().unwrap().unwrap(), plaintext);
+    }
+
+    #[test]
+    fn loopback_round_trip() {
+        let plaintext = (0..20 * CHUNK_SIZE as u32 + 100)
+            .map(|i| i as u8)
+            .collect::<Vec<_>>();
+        round_trip(&plaintext, false);
+        round_trip(&plaintext[..2 * CHUNK_SIZE + 100], true);
+        round_trip(&[], false);
+    }
+}
@@ -1,11 +1,16 @@
 pub use crate::buffer::*;
-#[cfg(not(feature = "std"))]
+use crate::error::InvalidCapacity;
+#[cfg(not(any(feature = "std", feature = "core2", feature = "embedded-io")))]
 use crate::rw::IoError;
 use aead::Buffer;
 use arrayvec::ArrayVec;
 use core::ops::{Deref, DerefMut};
 
 /// A simple `no_std` compatible Capped Buffer implementation
+///
+/// `CAP` needs to be at least [`min_capacity_for`](crate::min_capacity_for) the AEAD, which can
+/// be checked in a `const` assertion rather than through the
+/// [`InvalidCapacity`](InvalidCapacity) returned on construction
 #[derive(Clone, Debug, Default)]
 pub struct ArrayBuffer<const CAP: usize>(ArrayVec<u8, CAP>);
 
@@ -18,6 +23,22 @@ impl<const CAP: usize> ArrayBuffer<CAP> {
     pub fn into_inner(self) -> ArrayVec<u8, CAP> {
         self.0
     }
+
+    /// Creates an ArrayBuffer holding a copy of `data`, failing if it doesn't fit into `CAP`
+    /// bytes
+    pub fn from_slice(data: &[u8]) -> Result<Self, InvalidCapacity> {
+        let mut inner = ArrayVec::new_const();
+        inner
+            .try_extend_from_slice(data)
+            .map_err(|_| InvalidCapacity)?;
+        Ok(Self(inner))
+    }
+
+    /// Copies the contents into a newly allocated `Vec`
+    #[cfg(feature = "alloc")]
+    pub fn to_vec(&self) -> alloc::vec::Vec<u8> {
+        self.0.to_vec()
+    }
 }
 
 impl<const CAP: usize> From<ArrayVec<u8, CAP>> for ArrayBuffer<CAP> {
@@ -96,7 +117,43 @@ impl<const CAP: usize> std::io::Write for ArrayBuffer<CAP> {
     }
 }
 
-#[cfg(not(feature = "std"))]
+#[cfg(all(feature = "core2", not(feature = "std")))]
+impl<const CAP: usize> core2::io::Write for ArrayBuffer<CAP> {
+    #[inline]
+    fn write(&mut self, data: &[u8]) -> core2::io::Result<usize> {
+        let amt = core::cmp::min(data.len(), self.0.remaining_capacity());
+        self.0.try_extend_from_slice(&data[..amt]).unwrap();
+        Ok(amt)
+    }
+    #[inline]
+    fn flush(&mut self) -> core2::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "embedded-io", not(any(feature = "std", feature = "core2"))))]
+impl<const CAP: usize> embedded_io::ErrorType for ArrayBuffer<CAP> {
+    type Error = embedded_io::ErrorKind;
+}
+
+#[cfg(all(feature = "embedded-io", not(any(feature = "std", feature = "core2"))))]
+impl<const CAP: usize> embedded_io::Write for ArrayBuffer<CAP> {
+    #[inline]
+    fn write(&mut self, data: &[u8]) -> Result<usize, Self::Error> {
+        let amt = core::cmp::min(data.len(), self.0.remaining_capacity());
+        if amt == 0 && !data.is_empty() {
+            return Err(embedded_io::ErrorKind::WriteZero);
+        }
+        self.0.try_extend_from_slice(&data[..amt]).unwrap();
+        Ok(amt)
+    }
+    #[inline]
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(not(any(feature = "std", feature = "core2", feature = "embedded-io")))]
 impl<const CAP: usize> crate::rw::Write for ArrayBuffer<CAP> {
     type Error = IoError;
     #[inline]
@@ -118,3 +175,25 @@ impl<const CAP: usize> crate::rw::Write for ArrayBuffer<CAP> {
         }
     }
 }
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let data = (0..100u8).collect::<Vec<_>>();
+        let buffer = ArrayBuffer::<128>::from_slice(&data).unwrap();
+        assert_eq!(buffer.as_ref(), data.as_slice());
+        assert_eq!(buffer.to_vec(), data);
+        let buffer = ArrayBuffer::<100>::from_slice(&buffer.to_vec()).unwrap();
+        assert_eq!(buffer.into_inner().as_slice(), data.as_slice());
+
+        assert!(ArrayBuffer::<128>::from_slice(&[]).unwrap().is_empty());
+        assert_eq!(
+            ArrayBuffer::<64>::from_slice(&data).unwrap_err(),
+            InvalidCapacity
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use crate::error::Error;
+use crate::stage::Stage;
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "alloc")]
+use core::convert::TryFrom;
+
+/// Derives associated data for a chunk from its index in the stream
+#[cfg(feature = "alloc")]
+pub(crate) type AadProvider = Box<dyn FnMut(u32) -> Vec<u8> + Send + Sync>;
+
+/// The associated data authenticated with each chunk, on top of the chunk's position in the
+/// stream which the STREAM construction already covers
+#[derive(Default)]
+pub(crate) struct AssociatedData {
+    /// The plaintext sealed so far, if the total length is authenticated with the last chunk
+    pub(crate) total_length: Option<u64>,
+    /// The stream header, if it is authenticated with the first chunk. Taken once the first chunk
+    /// is sealed or opened
+    pub(crate) header: Option<Stage>,
+    /// The metadata sent in the clear after the stream header, which is authenticated with the
+    /// first chunk
+    #[cfg(feature = "alloc")]
+    pub(crate) metadata: Option<Vec<u8>>,
+    /// Whether the metadata still has to be authenticated with the first chunk
+    #[cfg(feature = "alloc")]
+    pub(crate) metadata_pending: bool,
+    /// The caller's associated data for each chunk, which is authenticated after everything else
+    #[cfg(feature = "alloc")]
+    pub(crate) provider: Option<AadProvider>,
+}
+
+impl AssociatedData {
+    /// Accounts for the chunk at `index` holding `len` bytes of plaintext and hands the
+    /// associated data it is sealed with to `seal`
+    #[cfg_attr(not(feature = "alloc"), allow(unused_variables))]
+    pub(crate) fn next<Io, T>(
+        &mut self,
+        index: u32,
+        len: usize,
+        last: bool,
+        seal: impl FnOnce(&[u8]) -> T,
+    ) -> Result<T, Error<Io>> {
+        let mut aad = Stage::new();
+        if let Some(header) = self.header.take() {
+            Self::push(&mut aad, header.remaining())?;
+        }
+        if let Some(total) = self.total_length.as_mut() {
+            *total += len as u64;
+            if last {
+                Self::push(&mut aad, &total.to_be_bytes())?;
+            }
+        }
+        #[cfg(feature = "alloc")]
+        {
+            let metadata = match (self.metadata.as_ref(), self.metadata_pending) {
+                (Some(metadata), true) => Some(metadata),
+                _ => None,
+            };
+            let provided = self.provider.as_mut().map(|provider| provider(index));
+            if metadata.is_some() || provided.is_some() {
+                let mut joined = Vec::new();
+                if let Some(metadata) = metadata {
+                    self.metadata_pending = false;
+                    joined.extend_from_slice(&metadata_prefix(metadata)?);
+                    joined.extend_from_slice(metadata);
+                }
+                joined.extend_from_slice(aad.remaining());
+                joined.extend_from_slice(&provided.unwrap_or_default());
+                return Ok(seal(&joined));
+            }
+        }
+        Ok(seal(aad.remaining()))
+    }
+
+    fn push<Io>(aad: &mut Stage, bytes: &[u8]) -> Result<(), Error<Io>> {
+        if aad.push(bytes) < bytes.len() {
+            Err(Error::Aead)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// The length prefix the metadata is sent with, which is authenticated along with it
+#[cfg(feature = "alloc")]
+pub(crate) fn metadata_prefix<Io>(metadata: &[u8]) -> Result<[u8; 4], Error<Io>> {
+    u32::try_from(metadata.len())
+        .map(u32::to_be_bytes)
+        .map_err(|_| Error::Aead)
+}
@@ -0,0 +1,116 @@
+pub use crate::buffer::*;
+use aead::Buffer;
+use alloc::vec::Vec;
+use core::ops::Deref;
+
+/// A `Vec<u8>` backed buffer with a hard maximum length set at construction. Unlike a plain
+/// `Vec<u8>`, whose capacity only bounds a [`BufReader`](crate::DecryptBufReader) until it has
+/// grown, the buffer refuses to grow past its maximum, so a bogus length prefix can never cause
+/// a larger allocation. Memory is only allocated as it is needed
+#[derive(Clone, Debug, Default)]
+pub struct BoundedVecBuffer {
+    inner: Vec<u8>,
+    max_len: usize,
+}
+
+impl BoundedVecBuffer {
+    /// Creates a new empty buffer which never grows past `max_len` bytes
+    pub const fn new(max_len: usize) -> Self {
+        Self {
+            inner: Vec::new(),
+            max_len,
+        }
+    }
+
+    /// Creates a new empty buffer which never grows past `max_len` bytes, allocating `max_len`
+    /// bytes upfront
+    pub fn preallocated(max_len: usize) -> Self {
+        Self {
+            inner: Vec::with_capacity(max_len),
+            max_len,
+        }
+    }
+
+    /// The maximum length of the buffer
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// Returns the underlying `Vec`, which still holds whatever was last written to it
+    pub fn into_inner(self) -> Vec<u8> {
+        self.inner
+    }
+}
+
+impl Deref for BoundedVecBuffer {
+    type Target = Vec<u8>;
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl AsRef<[u8]> for BoundedVecBuffer {
+    fn as_ref(&self) -> &[u8] {
+        self.inner.as_ref()
+    }
+}
+
+impl AsMut<[u8]> for BoundedVecBuffer {
+    fn as_mut(&mut self) -> &mut [u8] {
+        self.inner.as_mut()
+    }
+}
+
+impl Buffer for BoundedVecBuffer {
+    fn extend_from_slice(&mut self, other: &[u8]) -> aead::Result<()> {
+        if other.len() > self.max_len - self.inner.len() {
+            return Err(aead::Error);
+        }
+        self.inner
+            .try_reserve(other.len())
+            .map_err(|_| aead::Error)?;
+        self.inner.extend_from_slice(other);
+        Ok(())
+    }
+    fn truncate(&mut self, len: usize) {
+        self.inner.truncate(len)
+    }
+}
+
+impl CappedBuffer for BoundedVecBuffer {
+    fn capacity(&self) -> usize {
+        self.max_len
+    }
+}
+
+impl ResizeBuffer for BoundedVecBuffer {
+    fn resize_zeroed(&mut self, new_len: usize) -> Result<(), aead::Error> {
+        if new_len > self.max_len {
+            return Err(aead::Error);
+        }
+        try_resize(&mut self.inner, new_len)
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resize_past_bound() {
+        let mut buffer = BoundedVecBuffer::new(64);
+        assert!(buffer.resize_zeroed(64).is_ok());
+        assert_eq!(buffer.len(), 64);
+        assert!(buffer.resize_zeroed(65).is_err());
+        assert_eq!(buffer.len(), 64);
+        assert!(buffer.extend_from_slice(&[0]).is_err());
+    }
+
+    #[test]
+    fn failed_allocation() {
+        let mut buffer = BoundedVecBuffer::new(usize::MAX);
+        assert!(buffer.resize_zeroed(usize::MAX).is_err());
+        assert!(buffer.is_empty());
+    }
+}
@@ -17,14 +17,48 @@ impl CappedBuffer for alloc::vec::Vec<u8> {
 /// A trait for describing a buffer which can be resized. Useful for `no_std` environments.
 /// Automatically implemented for `Vec<u8>` when `alloc` enabled
 pub trait ResizeBuffer: Buffer {
-    /// Resize to the specified size and fill with zeroes when necessary
+    /// Resize to the specified size and fill with zeroes when necessary. Fails rather than
+    /// panicking or aborting if the buffer can't grow, such as when allocating fails
     fn resize_zeroed(&mut self, new_len: usize) -> Result<(), aead::Error>;
+    /// Resize to the specified size like [`resize_zeroed`](ResizeBuffer::resize_zeroed), for
+    /// contents which are overwritten right after, such as when reading a chunk into the buffer.
+    /// Implementations may skip zeroing bytes which are known to be initialized. Defaults to
+    /// [`resize_zeroed`](ResizeBuffer::resize_zeroed), which `Vec<u8>` and `ArrayBuffer`
+    /// keep, as their spare capacity is uninitialized
+    fn resize_for_overwrite(&mut self, new_len: usize) -> Result<(), aead::Error> {
+        self.resize_zeroed(new_len)
+    }
 }
 
 #[cfg(feature = "alloc")]
 impl ResizeBuffer for alloc::vec::Vec<u8> {
     fn resize_zeroed(&mut self, new_len: usize) -> Result<(), aead::Error> {
-        self.resize(new_len, 0);
-        Ok(())
+        try_resize(self, new_len)
+    }
+}
+
+/// Resizes a `Vec<u8>`, reserving the memory with `try_reserve` first so that a failed
+/// allocation is returned as an error instead of aborting
+#[cfg(feature = "alloc")]
+pub(crate) fn try_resize(vec: &mut alloc::vec::Vec<u8>, new_len: usize) -> Result<(), aead::Error> {
+    if let Some(additional) = new_len.checked_sub(vec.len()) {
+        vec.try_reserve(additional).map_err(|_| aead::Error)?;
+    }
+    vec.resize(new_len, 0);
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_allocation() {
+        let mut buffer = vec![1u8; 16];
+        assert!(buffer.resize_zeroed(usize::MAX).is_err());
+        assert_eq!(buffer, [1; 16]);
+        assert!(buffer.resize_zeroed(32).is_ok());
+        assert_eq!(&buffer[16..], [0; 16]);
     }
 }
@@ -0,0 +1,99 @@
+use crate::buffer::{CappedBuffer, ResizeBuffer};
+use crate::error::Error;
+use crate::framing::{DefaultFraming, Framing};
+use crate::reader::DecryptBufReader;
+use crate::rw::Read;
+use aead::generic_array::ArrayLength;
+use aead::stream::{NewStream, NonceSize, StreamPrimitive};
+use aead::{AeadInPlace, NewAead};
+use core::ops::Sub;
+
+/// An iterator over the decrypted bytes of a [`DecryptBufReader`](DecryptBufReader), returned by
+/// [`DecryptBufReader::bytes`](DecryptBufReader::bytes). Every chunk is still decrypted and
+/// authenticated in full before its first byte is handed out, so this only saves byte-oriented
+/// parsers from managing a buffer of their own.
+///
+/// The iterator ends with the stream, or after the first error, so that a stream which failed to
+/// authenticate isn't read any further
+pub struct DecryptBytes<'a, A, B, R, S, F = DefaultFraming>
+where
+    A: AeadInPlace + NewAead,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    reader: &'a mut DecryptBufReader<A, B, R, S, F>,
+    failed: bool,
+}
+
+impl<'a, A, B, R, S, F> DecryptBytes<'a, A, B, R, S, F>
+where
+    A: AeadInPlace + NewAead,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    pub(crate) fn new(reader: &'a mut DecryptBufReader<A, B, R, S, F>) -> Self {
+        Self {
+            reader,
+            failed: false,
+        }
+    }
+}
+
+impl<A, B, R, S, F> Iterator for DecryptBytes<'_, A, B, R, S, F>
+where
+    A: AeadInPlace + NewAead,
+    B: ResizeBuffer + CappedBuffer,
+    R: Read,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    type Item = Result<u8, Error<R::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let next = self.reader.next_byte().transpose();
+        self.failed = matches!(next, Some(Err(_)));
+        next
+    }
+}
+
+#[cfg(all(
+    feature = "array-buffer",
+    not(any(feature = "std", feature = "core2", feature = "embedded-io"))
+))]
+#[cfg(test)]
+mod no_std_tests {
+    use crate::test_util::{reader, writer, PLAINTEXT};
+    use crate::{FlushMode, Write};
+    use arrayvec::ArrayVec;
+
+    #[test]
+    fn collect_bytes() {
+        let mut ciphertext = [0u8; 512];
+        let mut writer = writer(&mut ciphertext[..]);
+        writer.write_all(&PLAINTEXT).unwrap();
+        writer.flush_with(FlushMode::Finalize).unwrap();
+        let ciphertext_len = 512 - writer.into_inner().unwrap().len();
+
+        let mut decrypted_reader = reader(&ciphertext[..ciphertext_len]);
+        let decrypted = decrypted_reader
+            .bytes()
+            .collect::<Result<ArrayVec<u8, 300>, _>>()
+            .unwrap();
+        assert_eq!(decrypted.as_slice(), &PLAINTEXT[..]);
+        assert!(decrypted_reader.is_at_eof());
+
+        // a tampered chunk ends the iterator after its error
+        ciphertext[20] ^= 1;
+        let mut tampered = reader(&ciphertext[..ciphertext_len]);
+        let mut bytes = tampered.bytes();
+        assert!(matches!(bytes.next(), Some(Err(_))));
+        assert!(bytes.next().is_none());
+    }
+}
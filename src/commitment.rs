@@ -0,0 +1,170 @@
+/// The associated data of the chunk committing to the key, which tells it apart from a chunk of
+/// data
+pub(crate) const COMMITMENT_AAD: &[u8] = b"aead-io key commitment";
+
+/// The number of zeroes sealed in the chunk committing to the key
+pub(crate) const COMMITMENT_LEN: usize = 32;
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use crate::test_util::KEY;
+    use crate::{ArrayBuffer, DecryptBE32BufReader, EncryptBE32BufWriter, Error};
+    use aead::consts::{U0, U12, U16, U32};
+    use aead::{AeadCore, AeadInPlace, Key, NewAead, Nonce, Tag};
+    use chacha20poly1305::ChaCha20Poly1305;
+
+    const OTHER_KEY: &[u8; 32] = b"another key entirely of 32 bytes";
+
+    /// An AEAD whose tag doesn't depend on the key, so that every ciphertext authenticates under
+    /// any key and decrypts to something else under each
+    struct UncommittedAead(Key<Self>);
+
+    impl NewAead for UncommittedAead {
+        type KeySize = U32;
+
+        fn new(key: &Key<Self>) -> Self {
+            Self(*key)
+        }
+    }
+
+    impl AeadCore for UncommittedAead {
+        type NonceSize = U12;
+        type TagSize = U16;
+        type CiphertextOverhead = U0;
+    }
+
+    impl UncommittedAead {
+        fn apply_keystream(&self, nonce: &Nonce<Self>, buffer: &mut [u8]) {
+            for (i, byte) in buffer.iter_mut().enumerate() {
+                *byte ^= self.0[i % 32] ^ nonce[i % 12] ^ i as u8;
+            }
+        }
+
+        fn tag(nonce: &Nonce<Self>, associated_data: &[u8], ciphertext: &[u8]) -> Tag<Self> {
+            let mut tag = Tag::<Self>::default();
+            let input = nonce.iter().chain(associated_data).chain(ciphertext);
+            for (i, byte) in input.enumerate() {
+                tag[i % 16] = tag[i % 16].rotate_left(3) ^ byte;
+            }
+            tag
+        }
+    }
+
+    impl AeadInPlace for UncommittedAead {
+        fn encrypt_in_place_detached(
+            &self,
+            nonce: &Nonce<Self>,
+            associated_data: &[u8],
+            buffer: &mut [u8],
+        ) -> aead::Result<Tag<Self>> {
+            self.apply_keystream(nonce, buffer);
+            Ok(Self::tag(nonce, associated_data, buffer))
+        }
+
+        fn decrypt_in_place_detached(
+            &self,
+            nonce: &Nonce<Self>,
+            associated_data: &[u8],
+            buffer: &mut [u8],
+            tag: &Tag<Self>,
+        ) -> aead::Result<()> {
+            if Self::tag(nonce, associated_data, buffer) != *tag {
+                return Err(aead::Error);
+            }
+            self.apply_keystream(nonce, buffer);
+            Ok(())
+        }
+    }
+
+    /// The AEADs of the tests, with the key and nonce sizes of ChaCha20Poly1305
+    trait TestAead: AeadInPlace<NonceSize = U12> + NewAead<KeySize = U32> {}
+
+    impl<A> TestAead for A where A: AeadInPlace<NonceSize = U12> + NewAead<KeySize = U32> {}
+
+    fn encrypt<A: TestAead>(plaintext: &[u8], commitment: bool) -> Vec<u8> {
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::<A, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            &mut ciphertext,
+        )
+        .unwrap();
+        if commitment {
+            writer = writer.with_key_commitment();
+        }
+        std::io::Write::write_all(&mut writer, plaintext).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+        ciphertext
+    }
+
+    fn decrypt<A: TestAead>(
+        ciphertext: &[u8],
+        key: &[u8; 32],
+        commitment: bool,
+    ) -> Result<Vec<u8>, Error<std::io::Error>> {
+        let mut reader =
+            DecryptBE32BufReader::<A, _, _>::new(key.into(), ArrayBuffer::<128>::new(), ciphertext)
+                .unwrap();
+        if commitment {
+            reader = reader.with_key_commitment();
+        }
+        reader.decrypt_all()
+    }
+
+    #[test]
+    fn key_commitment() {
+        let plaintext = [0x42u8; 300];
+        let ciphertext = encrypt::<ChaCha20Poly1305>(&plaintext, true);
+        // the commitment chunk of 32 bytes and its tag comes first
+        assert_eq!(&ciphertext[7..11], &48u32.to_be_bytes());
+        assert_eq!(
+            decrypt::<ChaCha20Poly1305>(&ciphertext, KEY, true).unwrap(),
+            plaintext
+        );
+        assert!(decrypt::<ChaCha20Poly1305>(&ciphertext, OTHER_KEY, true).is_err());
+        // both ends have to agree on the commitment
+        assert!(decrypt::<ChaCha20Poly1305>(&ciphertext, KEY, false).is_err());
+        let uncommitted = encrypt::<ChaCha20Poly1305>(&plaintext, false);
+        assert!(decrypt::<ChaCha20Poly1305>(&uncommitted, KEY, true).is_err());
+        // a stream cut off right after the commitment is truncated
+        assert!(decrypt::<ChaCha20Poly1305>(&ciphertext[..7 + 4 + 48], KEY, true).is_err());
+
+        // the non-blocking reads check it as well
+        let mut reader = DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            OTHER_KEY.into(),
+            ArrayBuffer::<128>::new(),
+            ciphertext.as_slice(),
+        )
+        .unwrap()
+        .with_key_commitment();
+        assert!(reader.try_read(&mut [0u8; 64]).is_err());
+    }
+
+    #[test]
+    fn second_key_rejected() {
+        let plaintext = [0x42u8; 300];
+
+        // without a commitment the stream decrypts under another key, to garbage
+        let ciphertext = encrypt::<UncommittedAead>(&plaintext, false);
+        assert_eq!(
+            decrypt::<UncommittedAead>(&ciphertext, KEY, false).unwrap(),
+            plaintext
+        );
+        let garbage = decrypt::<UncommittedAead>(&ciphertext, OTHER_KEY, false).unwrap();
+        assert_ne!(garbage, plaintext);
+
+        // with one it only decrypts under the key it was written with
+        let ciphertext = encrypt::<UncommittedAead>(&plaintext, true);
+        assert_eq!(
+            decrypt::<UncommittedAead>(&ciphertext, KEY, true).unwrap(),
+            plaintext
+        );
+        assert!(matches!(
+            decrypt::<UncommittedAead>(&ciphertext, OTHER_KEY, true),
+            Err(Error::Aead)
+        ));
+    }
+}
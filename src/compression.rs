@@ -0,0 +1,128 @@
+//! Compress-then-encrypt adapters. Compressing ciphertext is pointless, so the compressor has to
+//! sit between the caller and the [`BufWriter`](EncryptBufWriter), and the decompressor between
+//! the [`BufReader`](DecryptBufReader) and the caller
+
+use crate::buffer::{CappedBuffer, ResizeBuffer};
+use crate::error::IntoInnerError;
+use crate::framing::Framing;
+use crate::reader::DecryptBufReader;
+use crate::rw::{Read, Write};
+use crate::writer::EncryptBufWriter;
+use aead::generic_array::ArrayLength;
+use aead::stream::{NewStream, NonceSize, StreamPrimitive};
+use aead::{AeadInPlace, NewAead};
+use core::ops::Sub;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+
+pub use flate2::Compression;
+
+/// A [`BufWriter`](EncryptBufWriter) which deflates the plaintext before encrypting it
+pub struct CompressEncryptWriter<A, B, W, S, F>
+where
+    A: AeadInPlace,
+    B: CappedBuffer,
+    W: Write,
+    W::Error: Into<std::io::Error>,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    encoder: DeflateEncoder<EncryptBufWriter<A, B, W, S, F>>,
+}
+
+impl<A, B, W, S, F> CompressEncryptWriter<A, B, W, S, F>
+where
+    A: AeadInPlace,
+    B: CappedBuffer,
+    W: Write,
+    W::Error: Into<std::io::Error>,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    /// Wraps a Writer so that everything written is compressed with the given level first
+    pub fn new(writer: EncryptBufWriter<A, B, W, S, F>, level: Compression) -> Self {
+        Self {
+            encoder: DeflateEncoder::new(writer, level),
+        }
+    }
+
+    /// Finishes the compressed stream, then finalizes the encrypted stream and returns the inner
+    /// writer
+    pub fn finish(self) -> std::io::Result<W> {
+        Ok(self
+            .encoder
+            .finish()?
+            .into_inner()
+            .map_err(IntoInnerError::into_error)?)
+    }
+}
+
+impl<A, B, W, S, F> std::io::Write for CompressEncryptWriter<A, B, W, S, F>
+where
+    A: AeadInPlace,
+    B: CappedBuffer,
+    W: Write,
+    W::Error: Into<std::io::Error>,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        std::io::Write::write(&mut self.encoder, buf)
+    }
+
+    /// Flushes the compressor and writes out everything compressed so far as a chunk. Use
+    /// [`finish`](Self::finish) to complete the stream
+    fn flush(&mut self) -> std::io::Result<()> {
+        std::io::Write::flush(&mut self.encoder)
+    }
+}
+
+/// A [`BufReader`](DecryptBufReader) which inflates the plaintext after decrypting it
+pub type DecryptDecompressReader<A, B, R, S, F> = DeflateDecoder<DecryptBufReader<A, B, R, S, F>>;
+
+/// Wraps a Reader so that the decrypted stream is decompressed when reading
+pub fn decompress<A, B, R, S, F>(
+    reader: DecryptBufReader<A, B, R, S, F>,
+) -> DecryptDecompressReader<A, B, R, S, F>
+where
+    A: AeadInPlace + NewAead,
+    B: ResizeBuffer + CappedBuffer,
+    R: Read,
+    R::Error: Into<std::io::Error>,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    DeflateDecoder::new(reader)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decompress, CompressEncryptWriter, Compression};
+    use crate::test_util::{reader, writer};
+    use std::io::{Read, Write};
+
+    #[test]
+    fn compress_round_trip() {
+        let plaintext = b"all work and no play makes jack a dull boy. ".repeat(100);
+
+        let mut writer = CompressEncryptWriter::new(writer(Vec::new()), Compression::default());
+        writer.write_all(&plaintext).unwrap();
+        writer.flush().unwrap();
+        let ciphertext = writer.finish().unwrap();
+        assert!(ciphertext.len() < plaintext.len() / 4);
+
+        let mut out = Vec::new();
+        decompress(reader(ciphertext.as_slice()))
+            .read_to_end(&mut out)
+            .unwrap();
+        assert_eq!(out, plaintext);
+    }
+}
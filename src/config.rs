@@ -0,0 +1,284 @@
+use crate::error::InvalidCapacity;
+use crate::framing::Endianness;
+use crate::reader::DecryptBufReader;
+use crate::rw::Write;
+use crate::writer::{EncryptBufWriter, PADDING_HEADER_LEN};
+use crate::RECOMMENDED_CHUNK_SIZE;
+use aead::generic_array::typenum::Unsigned;
+use aead::generic_array::ArrayLength;
+use aead::stream::{NewStream, Nonce, NonceSize, StreamPrimitive};
+use aead::{AeadCore, AeadInPlace, Key, NewAead};
+use alloc::vec::Vec;
+use core::ops::Sub;
+
+/// Settings shared by many [`BufWriter`](EncryptBufWriter)s and [`BufReader`](DecryptBufReader)s.
+/// Building the streams from a single `Config` allocates appropriately sized buffers and keeps
+/// the policy in one place
+///
+/// With the `serde` feature a Config can be stored in a configuration file. It only holds the
+/// non-secret parameters of a stream, the keys and nonces are always given when building. Fields
+/// left out are deserialized with their default values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+pub struct Config {
+    chunk_size: usize,
+    zeroize_read_buffer: bool,
+    endianness: Endianness,
+    padding: bool,
+    total_length: bool,
+    header_aad: bool,
+}
+
+impl Default for Config {
+    /// A Config using the [`RECOMMENDED_CHUNK_SIZE`](RECOMMENDED_CHUNK_SIZE)
+    fn default() -> Self {
+        Self::new(RECOMMENDED_CHUNK_SIZE)
+    }
+}
+
+impl Config {
+    /// Creates a new Config producing chunks with up to `chunk_size` bytes of plaintext each
+    pub const fn new(chunk_size: usize) -> Self {
+        Self {
+            chunk_size,
+            zeroize_read_buffer: true,
+            endianness: Endianness::Big,
+            padding: false,
+            total_length: false,
+            header_aad: false,
+        }
+    }
+
+    /// Sets the maximum amount of plaintext in each chunk
+    pub const fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Sets whether readers wipe decrypted bytes once they are read. See
+    /// [`DecryptBufReader::with_zeroize_read_buffer`](DecryptBufReader::with_zeroize_read_buffer)
+    pub const fn with_zeroize_read_buffer(mut self, zeroize: bool) -> Self {
+        self.zeroize_read_buffer = zeroize;
+        self
+    }
+
+    /// Sets the byte order of the chunk length prefixes
+    pub const fn with_endianness(mut self, endianness: Endianness) -> Self {
+        self.endianness = endianness;
+        self
+    }
+
+    /// Sets whether chunks are padded to a uniform size. See
+    /// [`EncryptBufWriter::with_padding`](EncryptBufWriter::with_padding). The buffers are
+    /// enlarged by the length stored in each chunk, so chunks still hold up to the configured
+    /// amount of plaintext
+    pub const fn with_padding(mut self, padding: bool) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Sets whether the total length of the plaintext is authenticated. See
+    /// [`EncryptBufWriter::with_total_length`](EncryptBufWriter::with_total_length)
+    pub const fn with_total_length(mut self, total_length: bool) -> Self {
+        self.total_length = total_length;
+        self
+    }
+
+    /// Sets whether the stream header is authenticated. See
+    /// [`EncryptBufWriter::with_header_aad`](EncryptBufWriter::with_header_aad)
+    pub const fn with_header_aad(mut self, header_aad: bool) -> Self {
+        self.header_aad = header_aad;
+        self
+    }
+
+    /// The maximum amount of plaintext in each chunk
+    pub const fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    /// The capacity of a buffer holding a chunk along with its tag and any padding header
+    fn buffer_capacity<A: AeadCore>(&self) -> Result<usize, InvalidCapacity> {
+        let padding = if self.padding { PADDING_HEADER_LEN } else { 0 };
+        let overhead = <A::TagSize as Unsigned>::to_usize() + padding;
+        match self.chunk_size.checked_add(overhead) {
+            Some(capacity) if self.chunk_size > 0 && capacity <= u32::MAX as usize => Ok(capacity),
+            _ => Err(InvalidCapacity),
+        }
+    }
+
+    /// Constructs a new Writer using an AEAD key, nonce and writer with a buffer sized for the
+    /// configured chunk size. Fails if the chunk size is zero, or too large for a chunk's length
+    /// to fit its `u32` prefix
+    pub fn build_writer<A, S, W>(
+        &self,
+        key: &Key<A>,
+        nonce: &Nonce<A, S>,
+        writer: W,
+    ) -> Result<EncryptBufWriter<A, Vec<u8>, W, S, Endianness>, InvalidCapacity>
+    where
+        A: AeadInPlace + NewAead,
+        W: Write,
+        S: StreamPrimitive<A> + NewStream<A>,
+        A::NonceSize: Sub<S::NonceOverhead>,
+        NonceSize<A, S>: ArrayLength<u8>,
+    {
+        let capacity = self.buffer_capacity::<A>()?;
+        let mut writer = EncryptBufWriter::from_aead_with_framing(
+            A::new(key),
+            nonce,
+            self.endianness,
+            Vec::with_capacity(capacity),
+            writer,
+        )?;
+        if self.padding {
+            writer = writer.with_padding()?;
+        }
+        if self.total_length {
+            writer = writer.with_total_length();
+        }
+        if self.header_aad {
+            writer = writer.with_header_aad();
+        }
+        Ok(writer)
+    }
+
+    /// Constructs a new Reader using an AEAD key and reader with a buffer sized for the
+    /// configured chunk size. Fails like [`build_writer`](Self::build_writer)
+    pub fn build_reader<A, S, R>(
+        &self,
+        key: &Key<A>,
+        reader: R,
+    ) -> Result<DecryptBufReader<A, Vec<u8>, R, S, Endianness>, InvalidCapacity>
+    where
+        A: AeadInPlace + NewAead,
+        S: StreamPrimitive<A> + NewStream<A>,
+        A::NonceSize: Sub<S::NonceOverhead>,
+        NonceSize<A, S>: ArrayLength<u8>,
+    {
+        let capacity = self.buffer_capacity::<A>()?;
+        let mut reader = DecryptBufReader::from_aead_with_framing(
+            A::new(key),
+            self.endianness,
+            Vec::with_capacity(capacity),
+            reader,
+        )?
+        .with_zeroize_read_buffer(self.zeroize_read_buffer);
+        if self.padding {
+            reader = reader.with_padding();
+        }
+        if self.total_length {
+            reader = reader.with_total_length();
+        }
+        if self.header_aad {
+            reader = reader.with_header_aad();
+        }
+        Ok(reader)
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{KEY, PLAINTEXT};
+    use aead::stream::StreamBE32;
+    use chacha20poly1305::ChaCha20Poly1305;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn shared_config() {
+        let config = Config::new(64);
+
+        let mut ciphertexts = [Vec::new(), Vec::new()];
+        for ciphertext in ciphertexts.iter_mut() {
+            let mut writer = config
+                .build_writer::<ChaCha20Poly1305, StreamBE32<_>, _>(
+                    KEY.into(),
+                    &Default::default(),
+                    ciphertext,
+                )
+                .unwrap();
+            writer.write_all(&PLAINTEXT).unwrap();
+            writer.flush().unwrap();
+        }
+        assert_eq!(ciphertexts[0], ciphertexts[1]);
+
+        let mut reader = config
+            .build_reader::<ChaCha20Poly1305, StreamBE32<_>, _>(
+                KEY.into(),
+                ciphertexts[0].as_slice(),
+            )
+            .unwrap();
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, PLAINTEXT);
+    }
+
+    #[test]
+    fn chunk_size_bounds() {
+        let build = |config: Config| {
+            let writer = config.build_writer::<ChaCha20Poly1305, StreamBE32<_>, _>(
+                KEY.into(),
+                &Default::default(),
+                Vec::new(),
+            );
+            let reader =
+                config.build_reader::<ChaCha20Poly1305, StreamBE32<_>, _>(KEY.into(), &b""[..]);
+            writer.is_ok() && reader.is_ok()
+        };
+        assert!(!build(Config::new(0)));
+        // the chunk and its 16 byte tag have to fit the u32 length prefix
+        assert!(!build(Config::new(usize::MAX / 2)));
+        assert!(!build(Config::new(u32::MAX as usize - 15)));
+        assert!(!build(
+            Config::new(u32::MAX as usize - 19).with_padding(true)
+        ));
+        assert!(build(Config::new(1)));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_config() {
+        let config = Config::new(64)
+            .with_endianness(Endianness::Little)
+            .with_padding(true)
+            .with_total_length(true);
+        let json = serde_json::to_string(&config).unwrap();
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"chunk_size":64,"zeroize_read_buffer":true,"endianness":"little","#,
+                r#""padding":true,"total_length":true,"header_aad":false}"#,
+            )
+        );
+        assert_eq!(serde_json::from_str::<Config>(&json).unwrap(), config);
+        // anything left out takes its default
+        assert_eq!(
+            serde_json::from_str::<Config>(r#"{"chunk_size":64}"#).unwrap(),
+            Config::new(64)
+        );
+
+        let config = serde_json::from_str::<Config>(&json).unwrap();
+        let mut ciphertext = Vec::new();
+        let mut writer = config
+            .build_writer::<ChaCha20Poly1305, StreamBE32<_>, _>(
+                KEY.into(),
+                &Default::default(),
+                &mut ciphertext,
+            )
+            .unwrap();
+        writer.write_all(&PLAINTEXT).unwrap();
+        writer.flush_with(crate::FlushMode::Finalize).unwrap();
+        drop(writer);
+        // padded chunks of 64 bytes of plaintext, with little endian length prefixes
+        assert_eq!(&ciphertext[7..11], &(4 + 64 + 16u32).to_le_bytes());
+
+        let mut reader = config
+            .build_reader::<ChaCha20Poly1305, StreamBE32<_>, _>(KEY.into(), ciphertext.as_slice())
+            .unwrap();
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+        assert_eq!(out, PLAINTEXT);
+    }
+}
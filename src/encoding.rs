@@ -0,0 +1,331 @@
+//! Text encoding adapters for embedding encrypted streams in text formats. An [`EncodeWriter`]
+//! sits between the [`BufWriter`](crate::EncryptBufWriter) and the final sink, so the nonce
+//! header and the chunk framing are encoded like any other bytes. To write into a
+//! [`fmt::Write`](core::fmt::Write) such as a `String`, wrap it in a [`FmtWriter`] first
+
+use crate::rw::Write;
+use core::fmt;
+
+/// Input bytes encoded per call into the inner writer. A multiple of 3 so that base64 never
+/// needs padding before the end of the stream
+const BLOCK_SIZE: usize = 48;
+
+/// The text encoding applied by an [`EncodeWriter`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Lowercase hexadecimal
+    #[cfg(feature = "hex")]
+    Hex,
+    /// Standard base64 with padding
+    #[cfg(feature = "base64")]
+    Base64,
+}
+
+/// A writer which encodes everything written to it as text before passing it on to the inner
+/// writer.
+///
+/// Base64 encodes groups of 3 bytes, so up to 2 bytes are held back until more input arrives.
+/// [`finish`](Self::finish) must be called once the stream is complete to write them out with
+/// padding: flushing does not, as padding in the middle of the text would make it undecodable
+pub struct EncodeWriter<W> {
+    encoding: Encoding,
+    writer: W,
+    #[cfg_attr(not(feature = "base64"), allow(dead_code))]
+    pending: [u8; 2],
+    pending_len: usize,
+}
+
+impl<W> EncodeWriter<W>
+where
+    W: Write,
+{
+    /// Creates a new encoding writer around the inner writer
+    pub fn new(encoding: Encoding, writer: W) -> Self {
+        Self {
+            encoding,
+            writer,
+            pending: [0; 2],
+            pending_len: 0,
+        }
+    }
+
+    /// Gets a reference to the inner writer
+    pub fn inner(&self) -> &W {
+        &self.writer
+    }
+
+    /// Writes out any bytes held back for encoding and returns the inner writer
+    pub fn finish(mut self) -> Result<W, W::Error> {
+        #[cfg(feature = "base64")]
+        if self.pending_len > 0 {
+            use base64::Engine;
+            let mut out = [0u8; 4];
+            let len = base64::engine::general_purpose::STANDARD
+                .encode_slice(&self.pending[..self.pending_len], &mut out)
+                .unwrap();
+            self.writer.write_all(&out[..len])?;
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, W::Error> {
+        let mut out = [0u8; BLOCK_SIZE * 2];
+        match self.encoding {
+            #[cfg(feature = "hex")]
+            Encoding::Hex => {
+                let amt = core::cmp::min(buf.len(), BLOCK_SIZE);
+                hex::encode_to_slice(&buf[..amt], &mut out[..amt * 2]).unwrap();
+                self.writer.write_all(&out[..amt * 2])?;
+                Ok(amt)
+            }
+            #[cfg(feature = "base64")]
+            Encoding::Base64 => {
+                use base64::Engine;
+                let mut block = [0u8; BLOCK_SIZE];
+                let pending_len = self.pending_len;
+                let amt = core::cmp::min(buf.len(), BLOCK_SIZE - pending_len);
+                let total = pending_len + amt;
+                block[..pending_len].copy_from_slice(&self.pending[..pending_len]);
+                block[pending_len..total].copy_from_slice(&buf[..amt]);
+
+                let whole = total - total % 3;
+                let len = base64::engine::general_purpose::STANDARD
+                    .encode_slice(&block[..whole], &mut out)
+                    .unwrap();
+                self.writer.write_all(&out[..len])?;
+
+                self.pending_len = total - whole;
+                self.pending[..self.pending_len].copy_from_slice(&block[whole..total]);
+                Ok(amt)
+            }
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), W::Error> {
+        self.writer.flush()
+    }
+}
+
+impl<W> fmt::Debug for EncodeWriter<W> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncodeWriter")
+            .field("encoding", &self.encoding)
+            .field("pending", &self.pending_len)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W> std::io::Write for EncodeWriter<W>
+where
+    W: Write,
+    W::Error: Into<std::io::Error>,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.write(buf).map_err(Into::into)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.flush().map_err(Into::into)
+    }
+}
+
+#[cfg(all(feature = "core2", not(feature = "std")))]
+impl<W> core2::io::Write for EncodeWriter<W>
+where
+    W: Write,
+    W::Error: Into<core2::io::Error>,
+{
+    fn write(&mut self, buf: &[u8]) -> core2::io::Result<usize> {
+        self.write(buf).map_err(Into::into)
+    }
+    fn flush(&mut self) -> core2::io::Result<()> {
+        self.flush().map_err(Into::into)
+    }
+}
+
+#[cfg(all(feature = "embedded-io", not(any(feature = "std", feature = "core2"))))]
+impl<W> embedded_io::ErrorType for EncodeWriter<W>
+where
+    W: Write,
+    W::Error: embedded_io::Error,
+{
+    type Error = W::Error;
+}
+
+#[cfg(all(feature = "embedded-io", not(any(feature = "std", feature = "core2"))))]
+impl<W> embedded_io::Write for EncodeWriter<W>
+where
+    W: Write,
+    W::Error: embedded_io::Error,
+{
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.write(buf)
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flush()
+    }
+}
+
+#[cfg(not(any(feature = "std", feature = "core2", feature = "embedded-io")))]
+impl<W> Write for EncodeWriter<W>
+where
+    W: Write,
+{
+    type Error = W::Error;
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.write(buf)
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flush()
+    }
+    fn write_all(&mut self, mut buf: &[u8]) -> Result<(), Self::Error> {
+        while !buf.is_empty() {
+            let amt = self.write(buf)?;
+            buf = &buf[amt..];
+        }
+        Ok(())
+    }
+}
+
+/// Adapts a [`fmt::Write`](core::fmt::Write) into a [`Write`](crate::Write). Only UTF-8 can be
+/// written, which the output of an [`EncodeWriter`] always is
+#[derive(Debug, Clone, Default)]
+pub struct FmtWriter<W>(W);
+
+impl<W> FmtWriter<W>
+where
+    W: fmt::Write,
+{
+    /// Wraps the formatter
+    pub fn new(writer: W) -> Self {
+        Self(writer)
+    }
+
+    /// Returns the wrapped formatter
+    pub fn into_inner(self) -> W {
+        self.0
+    }
+
+    fn write_utf8(&mut self, buf: &[u8]) -> Result<(), Option<fmt::Error>> {
+        let text = core::str::from_utf8(buf).map_err(|_| None)?;
+        self.0.write_str(text).map_err(Some)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W> std::io::Write for FmtWriter<W>
+where
+    W: fmt::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.write_utf8(buf).map_err(|err| match err {
+            Some(err) => std::io::Error::other(err),
+            None => std::io::Error::new(std::io::ErrorKind::InvalidData, "invalid UTF-8"),
+        })?;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "core2", not(feature = "std")))]
+impl<W> core2::io::Write for FmtWriter<W>
+where
+    W: fmt::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> core2::io::Result<usize> {
+        self.write_utf8(buf).map_err(|err| match err {
+            Some(_) => core2::io::Error::new(core2::io::ErrorKind::Other, "formatter error"),
+            None => core2::io::Error::new(core2::io::ErrorKind::InvalidData, "invalid UTF-8"),
+        })?;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> core2::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "embedded-io", not(any(feature = "std", feature = "core2"))))]
+impl<W> embedded_io::ErrorType for FmtWriter<W>
+where
+    W: fmt::Write,
+{
+    type Error = embedded_io::ErrorKind;
+}
+
+#[cfg(all(feature = "embedded-io", not(any(feature = "std", feature = "core2"))))]
+impl<W> embedded_io::Write for FmtWriter<W>
+where
+    W: fmt::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.write_utf8(buf).map_err(|err| match err {
+            Some(_) => embedded_io::ErrorKind::Other,
+            None => embedded_io::ErrorKind::InvalidData,
+        })?;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(not(any(feature = "std", feature = "core2", feature = "embedded-io")))]
+impl<W> Write for FmtWriter<W>
+where
+    W: fmt::Write,
+{
+    type Error = fmt::Error;
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.write_all(buf)?;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+        self.write_utf8(buf)
+            .map_err(|err| err.unwrap_or(fmt::Error))
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{reader, writer, PLAINTEXT};
+
+    fn encrypt_to_text(encoding: Encoding, plaintext: &[u8]) -> String {
+        let mut encoder = EncodeWriter::new(encoding, FmtWriter::new(String::new()));
+        let mut writer = writer(&mut encoder);
+        std::io::Write::write_all(&mut writer, plaintext).unwrap();
+        std::io::Write::flush(&mut writer).unwrap();
+        drop(writer);
+        encoder.finish().unwrap().into_inner()
+    }
+
+    fn decrypt(ciphertext: &[u8]) -> Vec<u8> {
+        reader(ciphertext).decrypt_all().unwrap()
+    }
+
+    #[cfg(feature = "base64")]
+    #[test]
+    fn base64_round_trip() {
+        use base64::Engine;
+        let text = encrypt_to_text(Encoding::Base64, &PLAINTEXT);
+        let ciphertext = base64::engine::general_purpose::STANDARD
+            .decode(&text)
+            .unwrap();
+        assert_eq!(decrypt(&ciphertext), PLAINTEXT);
+    }
+
+    #[cfg(feature = "hex")]
+    #[test]
+    fn hex_round_trip() {
+        let text = encrypt_to_text(Encoding::Hex, &PLAINTEXT);
+        let ciphertext = hex::decode(&text).unwrap();
+        assert_eq!(decrypt(&ciphertext), PLAINTEXT);
+    }
+}
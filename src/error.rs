@@ -2,12 +2,12 @@ use core::fmt;
 
 /// An error which occurs when providing an invalid buffer to a
 /// [`BufReader`](crate::DecryptBufReader) or [`BufWriter`](crate::EncryptBufWriter)
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct InvalidCapacity;
 
 impl fmt::Display for InvalidCapacity {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str("Invalid buffer capacity: capacity must be greater than 0")
+        f.write_str("Invalid buffer capacity: capacity must be greater than the AEAD tag size")
     }
 }
 
@@ -21,12 +21,282 @@ impl From<InvalidCapacity> for std::io::Error {
     }
 }
 
+#[cfg(all(feature = "core2", not(feature = "std")))]
+impl From<InvalidCapacity> for core2::io::Error {
+    fn from(_: InvalidCapacity) -> Self {
+        core2::io::Error::new(
+            core2::io::ErrorKind::InvalidInput,
+            "Invalid buffer capacity: capacity must be greater than the AEAD tag size",
+        )
+    }
+}
+
+#[cfg(all(feature = "embedded-io", not(any(feature = "std", feature = "core2"))))]
+impl embedded_io::Error for InvalidCapacity {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        embedded_io::ErrorKind::InvalidInput
+    }
+}
+
+/// An error which occurs when providing a buffer to
+/// [`DecryptBufReader::from_aead_with_buffer`](crate::DecryptBufReader::from_aead_with_buffer)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidBuffer {
+    /// The capacity is not greater than the AEAD tag size
+    Capacity,
+    /// The buffer still holds data, which the reader would have to discard
+    NotEmpty,
+}
+
+impl From<InvalidCapacity> for InvalidBuffer {
+    fn from(_: InvalidCapacity) -> Self {
+        Self::Capacity
+    }
+}
+
+impl InvalidBuffer {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Capacity => {
+                "Invalid buffer capacity: capacity must be greater than the AEAD tag size"
+            }
+            Self::NotEmpty => "Invalid buffer: buffer must be empty",
+        }
+    }
+}
+
+impl fmt::Display for InvalidBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InvalidBuffer {}
+
+#[cfg(feature = "std")]
+impl From<InvalidBuffer> for std::io::Error {
+    fn from(err: InvalidBuffer) -> Self {
+        match err {
+            InvalidBuffer::Capacity => InvalidCapacity.into(),
+            InvalidBuffer::NotEmpty => std::io::Error::new(std::io::ErrorKind::InvalidInput, err),
+        }
+    }
+}
+
+#[cfg(all(feature = "core2", not(feature = "std")))]
+impl From<InvalidBuffer> for core2::io::Error {
+    fn from(err: InvalidBuffer) -> Self {
+        core2::io::Error::new(core2::io::ErrorKind::InvalidInput, err.as_str())
+    }
+}
+
+#[cfg(all(feature = "embedded-io", not(any(feature = "std", feature = "core2"))))]
+impl embedded_io::Error for InvalidBuffer {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        embedded_io::ErrorKind::InvalidInput
+    }
+}
+
+/// An error which occurs when constructing a [`BufReader`](crate::DecryptBufReader) or
+/// [`BufWriter`](crate::EncryptBufWriter) from a [`Keyring`](crate::Keyring)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyringError<E> {
+    /// The keyring couldn't provide the AEAD for the key
+    Keyring(E),
+    /// The capacity is not greater than the AEAD tag size
+    Capacity,
+}
+
+impl<E> From<InvalidCapacity> for KeyringError<E> {
+    fn from(_: InvalidCapacity) -> Self {
+        Self::Capacity
+    }
+}
+
+impl<E> fmt::Display for KeyringError<E>
+where
+    E: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Keyring(err) => write!(f, "Keyring error: {}", err),
+            Self::Capacity => InvalidCapacity.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<E> std::error::Error for KeyringError<E> where E: fmt::Display + fmt::Debug {}
+
+/// An error which occurs when constructing a [`BufReader`](crate::DecryptBufReader) or
+/// [`BufWriter`](crate::EncryptBufWriter) with a self-test
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfTestError {
+    /// The AEAD failed to round-trip the known test vector
+    Failed,
+    /// The capacity is not greater than the AEAD tag size
+    Capacity,
+}
+
+impl From<InvalidCapacity> for SelfTestError {
+    fn from(_: InvalidCapacity) -> Self {
+        Self::Capacity
+    }
+}
+
+impl fmt::Display for SelfTestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Failed => f.write_str("AEAD self-test failed to round-trip a known vector"),
+            Self::Capacity => InvalidCapacity.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for SelfTestError {}
+
+/// An error which occurs when constructing a [`BufWriter`](crate::EncryptBufWriter) with a
+/// [`NonceTracker`](crate::NonceTracker)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NonceReuseError {
+    /// The tracker has seen the nonce before
+    Reused,
+    /// The capacity is not greater than the AEAD tag size
+    Capacity,
+}
+
+impl From<InvalidCapacity> for NonceReuseError {
+    fn from(_: InvalidCapacity) -> Self {
+        Self::Capacity
+    }
+}
+
+impl fmt::Display for NonceReuseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Reused => f.write_str("nonce was already used with this key"),
+            Self::Capacity => InvalidCapacity.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for NonceReuseError {}
+
+/// An error which occurs when constructing a [`BufReader`](crate::DecryptBufReader) or
+/// [`BufWriter`](crate::EncryptBufWriter) from a key given as raw bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySliceError {
+    /// The key isn't exactly as long as the AEAD's keys
+    KeyLength,
+    /// The capacity is not greater than the AEAD tag size
+    Capacity,
+}
+
+impl From<InvalidCapacity> for KeySliceError {
+    fn from(_: InvalidCapacity) -> Self {
+        Self::Capacity
+    }
+}
+
+impl fmt::Display for KeySliceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::KeyLength => f.write_str("Invalid key length: key must match the AEAD key size"),
+            Self::Capacity => InvalidCapacity.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for KeySliceError {}
+
+/// An error which occurs when constructing a nonce from raw bytes with
+/// [`nonce_from_slice`](crate::nonce_from_slice)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidNonceLength;
+
+impl fmt::Display for InvalidNonceLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Invalid nonce length: nonce must match the nonce size of the STREAM")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InvalidNonceLength {}
+
+/// An error which occurs when decoding a [`StreamState`](crate::StreamState) from bytes which
+/// weren't encoded by [`StreamState::to_bytes`](crate::StreamState::to_bytes)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidStreamState;
+
+impl fmt::Display for InvalidStreamState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Invalid stream state: bytes must be an encoded stream state")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for InvalidStreamState {}
+
+/// An error which occurs when constructing a [`BufWriter`](crate::EncryptBufWriter) or
+/// [`BufReader`](crate::DecryptBufReader) part way through a stream, with
+/// [`from_state`](crate::EncryptBufWriter::from_state) or
+/// [`from_aead_at_chunk`](crate::DecryptBufReader::from_aead_at_chunk)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkIndexError {
+    /// The stream can't hold as many chunks as the index skips
+    ChunkIndex,
+    /// The capacity is not greater than the AEAD tag size
+    Capacity,
+}
+
+impl From<InvalidCapacity> for ChunkIndexError {
+    fn from(_: InvalidCapacity) -> Self {
+        Self::Capacity
+    }
+}
+
+impl fmt::Display for ChunkIndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ChunkIndex => {
+                f.write_str("Invalid chunk index: the stream can't hold as many chunks")
+            }
+            Self::Capacity => InvalidCapacity.fmt(f),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ChunkIndexError {}
+
 /// An error for read/write operations with custom Error types. Mainly useful for `no_std`
-/// environments
-#[derive(Debug, Clone)]
+/// environments.
+///
+/// With `std`, `?` converts an `Error<std::io::Error>` into a `std::io::Error` and back. Going
+/// back with [`from_io_error`](Self::from_io_error) instead of `?` also recovers
+/// [`Error::Aead`] from a `std::io::Error` which was converted from one, which the blanket
+/// `From` conversion would wrap as [`Error::Io`]
+///
+/// More variants may be added, so matching on it needs a wildcard arm
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Error<Io> {
     Aead,
     Io(Io),
+    /// The stream header says the stream was encrypted for a different STREAM primitive, such as
+    /// `StreamLE31` instead of `StreamBE32`, as checked by
+    /// [`NonceLenFraming`](crate::NonceLenFraming)
+    StreamMismatch,
+    /// The encrypted stream ended part way through a chunk, as told apart from other errors of
+    /// the inner reader by the `read_exact` of the [`BufReader`](crate::DecryptBufReader) with
+    /// `std`, or part way through a chunk header read along with the chunk before it. A stream
+    /// cut off right after a chunk can't be told apart from a forged last chunk,
+    /// so it fails with [`Error::Aead`] instead
+    Truncated,
 }
 
 impl<Io> From<Io> for Error<Io> {
@@ -42,13 +312,104 @@ where
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Self::Aead => f.write_str("AEAD error occured"),
-            Self::Io(io) => io.fmt(f),
+            Self::Io(_) => f.write_str("I/O error"),
+            Self::StreamMismatch => f.write_str(STREAM_MISMATCH),
+            Self::Truncated => f.write_str(TRUNCATED),
+        }
+    }
+}
+
+/// [`Error::Io`] reports the error of the inner reader or writer as its source, so that error
+/// reporters can walk down to it. It only displays as a generic I/O error, so that they don't
+/// print the message twice
+#[cfg(feature = "std")]
+impl<Io> std::error::Error for Error<Io>
+where
+    Io: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+const STREAM_MISMATCH: &str = "stream was encrypted for a different STREAM primitive";
+
+const TRUNCATED: &str = "encrypted stream ended part way through a chunk";
+
+impl<Io> Error<Io> {
+    /// Converts an error from parsing bytes which were already read into an error of the inner
+    /// reader. The bytes ran out early, so any I/O error is reported as [`Error::Aead`]
+    pub(crate) fn without_io<T>(self) -> Error<T> {
+        match self {
+            Self::StreamMismatch => Error::StreamMismatch,
+            Self::Truncated => Error::Truncated,
+            _ => Error::Aead,
         }
     }
 }
 
+/// The payload of a `std::io::Error` converted from [`Error::Aead`], so that
+/// [`Error::from_io_error`] can tell it apart from other errors
+#[cfg(feature = "std")]
+#[derive(Debug)]
+struct AeadError;
+
+#[cfg(feature = "std")]
+impl fmt::Display for AeadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an AEAD error occured")
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AeadError {}
+
+/// The payload of a `std::io::Error` converted from [`Error::StreamMismatch`]
+#[cfg(feature = "std")]
+#[derive(Debug)]
+struct StreamMismatchError;
+
+#[cfg(feature = "std")]
+impl fmt::Display for StreamMismatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(STREAM_MISMATCH)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for StreamMismatchError {}
+
+/// The payload of a `std::io::Error` converted from [`Error::Truncated`]
+#[cfg(feature = "std")]
+#[derive(Debug)]
+struct TruncatedError;
+
+#[cfg(feature = "std")]
+impl fmt::Display for TruncatedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(TRUNCATED)
+    }
+}
+
 #[cfg(feature = "std")]
-impl<Io> std::error::Error for Error<Io> where Io: fmt::Display + fmt::Debug {}
+impl std::error::Error for TruncatedError {}
+
+#[cfg(feature = "std")]
+impl Error<std::io::Error> {
+    /// Converts a `std::io::Error` back into an `Error`, recovering [`Error::Aead`],
+    /// [`Error::StreamMismatch`] and [`Error::Truncated`] if it was converted from one of them
+    pub fn from_io_error(err: std::io::Error) -> Self {
+        match err.get_ref() {
+            Some(inner) if inner.is::<AeadError>() => Self::Aead,
+            Some(inner) if inner.is::<StreamMismatchError>() => Self::StreamMismatch,
+            Some(inner) if inner.is::<TruncatedError>() => Self::Truncated,
+            _ => Self::Io(err),
+        }
+    }
+}
 
 #[cfg(feature = "std")]
 impl<Io> From<Error<Io>> for std::io::Error
@@ -57,8 +418,53 @@ where
 {
     fn from(err: Error<Io>) -> Self {
         match err {
-            Error::Aead => std::io::Error::new(std::io::ErrorKind::Other, "an AEAD error occured"),
+            Error::Aead => std::io::Error::other(AeadError),
+            Error::Io(err) => err.into(),
+            Error::StreamMismatch => {
+                std::io::Error::new(std::io::ErrorKind::InvalidData, StreamMismatchError)
+            }
+            Error::Truncated => {
+                std::io::Error::new(std::io::ErrorKind::UnexpectedEof, TruncatedError)
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "core2", not(feature = "std")))]
+impl<Io> From<Error<Io>> for core2::io::Error
+where
+    Io: Into<core2::io::Error>,
+{
+    fn from(err: Error<Io>) -> Self {
+        match err {
+            Error::Aead => {
+                core2::io::Error::new(core2::io::ErrorKind::Other, "an AEAD error occured")
+            }
             Error::Io(err) => err.into(),
+            Error::StreamMismatch => {
+                core2::io::Error::new(core2::io::ErrorKind::InvalidData, STREAM_MISMATCH)
+            }
+            Error::Truncated => {
+                core2::io::Error::new(core2::io::ErrorKind::UnexpectedEof, TRUNCATED)
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "embedded-io", not(any(feature = "std", feature = "core2"))))]
+impl<Io> embedded_io::Error for Error<Io>
+where
+    Io: fmt::Debug,
+{
+    /// AEAD errors are reported as [`InvalidData`](embedded_io::ErrorKind::InvalidData). The
+    /// kind of I/O errors is not preserved, as the reader's errors also report reaching the end
+    /// of the stream early, which has no kind of its own
+    fn kind(&self) -> embedded_io::ErrorKind {
+        match self {
+            Self::Aead | Self::StreamMismatch | Self::Truncated => {
+                embedded_io::ErrorKind::InvalidData
+            }
+            Self::Io(_) => embedded_io::ErrorKind::Other,
         }
     }
 }
@@ -80,6 +486,12 @@ impl<W, Io> IntoInnerError<W, Io> {
         &self.1
     }
 
+    /// Returns a reference to the buffered writer instance which generated the error, for
+    /// inspecting it without taking it back
+    pub fn writer(&self) -> &W {
+        &self.0
+    }
+
     /// Returns the buffered writer instance which generated the error.
     /// The returned object can be used for error recovery, such as re-inspecting the buffer.
     pub fn into_writer(self) -> W {
@@ -111,12 +523,16 @@ where
     }
 }
 
+/// Displays as the error itself, so its source is that of the error
 #[cfg(feature = "std")]
 impl<W, Io> std::error::Error for IntoInnerError<W, Io>
 where
-    Io: fmt::Display + fmt::Debug,
+    Io: std::error::Error + 'static,
     W: fmt::Debug,
 {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        std::error::Error::source(self.error())
+    }
 }
 
 #[cfg(feature = "std")]
@@ -128,3 +544,88 @@ where
         err.into_error().into()
     }
 }
+
+#[cfg(all(feature = "core2", not(feature = "std")))]
+impl<W, Io> From<IntoInnerError<W, Io>> for core2::io::Error
+where
+    Io: Into<core2::io::Error>,
+{
+    fn from(err: IntoInnerError<W, Io>) -> Self {
+        err.into_error().into()
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::KEY;
+    use crate::{ArrayBuffer, DecryptBE32BufReader};
+    use chacha20poly1305::ChaCha20Poly1305;
+
+    fn decrypt(ciphertext: &[u8]) -> Result<Vec<u8>, Error<std::io::Error>> {
+        let mut reader = DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<128>::new(),
+            ciphertext,
+        )
+        .unwrap();
+        let plaintext = reader.decrypt_all()?;
+        Ok(plaintext)
+    }
+
+    fn decrypt_io(ciphertext: &[u8]) -> std::io::Result<Vec<u8>> {
+        Ok(decrypt(ciphertext)?)
+    }
+
+    fn open(path: &str) -> Result<std::fs::File, Error<std::io::Error>> {
+        Ok(std::fs::File::open(path)?)
+    }
+
+    #[test]
+    fn question_mark_conversions() {
+        assert!(matches!(open("/does/not/exist"), Err(Error::Io(_))));
+
+        let err = decrypt_io(&[0; 32]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Other);
+        assert_eq!(err.to_string(), "an AEAD error occured");
+        assert!(matches!(Error::from_io_error(err), Error::Aead));
+
+        let err = decrypt_io(&[0; 3]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+        assert!(matches!(Error::from_io_error(err), Error::Io(_)));
+    }
+
+    #[test]
+    fn equality() {
+        assert_eq!(Error::<u8>::Aead, Error::Aead);
+        assert_eq!(Error::from(3u8), Error::Io(3));
+        assert_ne!(Error::Io(3u8), Error::Aead);
+        assert_ne!(Error::Io(3u8), Error::Io(4));
+
+        let buffer = ArrayBuffer::<16>::new();
+        let writer = crate::EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            buffer,
+            Vec::new(),
+        );
+        assert_eq!(writer.err(), Some(InvalidCapacity));
+    }
+
+    #[test]
+    fn source_chain() {
+        let err = open("/does/not/exist").unwrap_err();
+        assert_eq!(err.to_string(), "I/O error");
+        let source = std::error::Error::source(&err).unwrap();
+        let io = source.downcast_ref::<std::io::Error>().unwrap();
+        assert_eq!(io.kind(), std::io::ErrorKind::NotFound);
+        assert!(source.source().is_none());
+
+        let into_inner = IntoInnerError::new((), err);
+        let source = std::error::Error::source(&into_inner).unwrap();
+        assert!(source.is::<std::io::Error>());
+
+        assert!(std::error::Error::source(&Error::<std::io::Error>::Aead).is_none());
+    }
+}
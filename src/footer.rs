@@ -0,0 +1,29 @@
+/// Size of the footer: a 4-byte CRC32 followed by an 8-byte length, both big endian
+pub(crate) const FOOTER_LEN: usize = 12;
+
+/// Accumulates the CRC32 and the total length of the encrypted chunks for the footer written
+/// after the last chunk. This is a cheap corruption check for failing fast, not a replacement
+/// for the authentication done by the AEAD
+#[derive(Clone, Default)]
+pub(crate) struct Footer {
+    hasher: crc32fast::Hasher,
+    len: u64,
+}
+
+impl Footer {
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.hasher.update(chunk);
+        self.len += chunk.len() as u64;
+    }
+
+    pub fn to_bytes(&self) -> [u8; FOOTER_LEN] {
+        let mut bytes = [0u8; FOOTER_LEN];
+        bytes[..4].copy_from_slice(&self.hasher.clone().finalize().to_be_bytes());
+        bytes[4..].copy_from_slice(&self.len.to_be_bytes());
+        bytes
+    }
+
+    pub fn matches(&self, bytes: &[u8; FOOTER_LEN]) -> bool {
+        &self.to_bytes() == bytes
+    }
+}
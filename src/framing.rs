@@ -0,0 +1,816 @@
+use crate::error::Error;
+use crate::rw::{Read, Write};
+
+/// Describes how the stream header and the chunk headers are laid out around the encrypted
+/// chunks. The [`BufWriter`](crate::EncryptBufWriter) and [`BufReader`](crate::DecryptBufReader)
+/// delegate all framing to this trait, so custom on-disk formats can be supported by providing
+/// an alternative implementation
+pub trait Framing {
+    /// Writes the stream header, which carries the nonce, returning the number of bytes written
+    fn write_header<W: Write>(&mut self, writer: &mut W, nonce: &[u8]) -> Result<usize, W::Error>;
+    /// Writes the header preceding an encrypted chunk of `len` bytes, returning the number of
+    /// bytes written
+    fn write_chunk_header<W: Write>(
+        &mut self,
+        writer: &mut W,
+        len: usize,
+    ) -> Result<usize, W::Error>;
+    /// Reads the stream header, filling in the nonce
+    fn read_header<R: Read>(
+        &mut self,
+        reader: &mut R,
+        nonce: &mut [u8],
+    ) -> Result<(), Error<R::Error>>;
+    /// Reads the header of the next encrypted chunk and returns its length, or `0` if the stream
+    /// has ended
+    fn read_chunk_header<R: Read>(&mut self, reader: &mut R) -> Result<usize, Error<R::Error>>;
+    /// The largest encrypted chunk, tag included, that a chunk header can describe. Buffers with
+    /// a larger capacity are clamped to this length. Defaults to `u32::MAX`
+    fn max_chunk_len(&self) -> usize {
+        u32::MAX as usize
+    }
+    /// The length of the stream header for a nonce of `nonce_len` bytes. Only used by
+    /// [`DecryptBufReader::try_read`](crate::DecryptBufReader::try_read), which has to know how
+    /// much to read before parsing the header. Defaults to `nonce_len`
+    fn header_len(&self, nonce_len: usize) -> usize {
+        nonce_len
+    }
+    /// The length of each chunk header. Only used by
+    /// [`DecryptBufReader::try_read`](crate::DecryptBufReader::try_read), like
+    /// [`header_len`](Framing::header_len), and by
+    /// [`SplittingWriter`](crate::SplittingWriter) to find the chunk boundaries. Defaults to `4`
+    fn chunk_header_len(&self) -> usize {
+        4
+    }
+    /// Whether the header of the last chunk marks it as such, so that readers stop after the
+    /// last chunk without looking for the end of the stream past it. Framings returning `true`
+    /// override [`write_last_chunk_header`](Framing::write_last_chunk_header) and
+    /// [`read_marked_chunk_header`](Framing::read_marked_chunk_header). Defaults to `false`
+    fn marks_last_chunk(&self) -> bool {
+        false
+    }
+    /// Writes the header preceding the last encrypted chunk of `len` bytes, returning the number
+    /// of bytes written. Defaults to [`write_chunk_header`](Framing::write_chunk_header)
+    fn write_last_chunk_header<W: Write>(
+        &mut self,
+        writer: &mut W,
+        len: usize,
+    ) -> Result<usize, W::Error> {
+        self.write_chunk_header(writer, len)
+    }
+    /// Reads the header of the next encrypted chunk like
+    /// [`read_chunk_header`](Framing::read_chunk_header), also returning whether it marks the
+    /// last chunk. Defaults to never marking one
+    fn read_marked_chunk_header<R: Read>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<(usize, bool), Error<R::Error>> {
+        Ok((self.read_chunk_header(reader)?, false))
+    }
+}
+
+/// The default framing: the nonce is written as is at the start of the stream and every chunk is
+/// prefixed with its length as a 4-byte big endian integer
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultFraming;
+
+impl Framing for DefaultFraming {
+    fn write_header<W: Write>(&mut self, writer: &mut W, nonce: &[u8]) -> Result<usize, W::Error> {
+        writer.write_all(nonce)?;
+        Ok(nonce.len())
+    }
+
+    fn write_chunk_header<W: Write>(
+        &mut self,
+        writer: &mut W,
+        len: usize,
+    ) -> Result<usize, W::Error> {
+        writer.write_all(&(len as u32).to_be_bytes())?;
+        Ok(4)
+    }
+
+    fn read_header<R: Read>(
+        &mut self,
+        reader: &mut R,
+        nonce: &mut [u8],
+    ) -> Result<(), Error<R::Error>> {
+        Ok(reader.read_exact(nonce)?)
+    }
+
+    fn read_chunk_header<R: Read>(&mut self, reader: &mut R) -> Result<usize, Error<R::Error>> {
+        Ok(read_length_prefix(reader)?.map_or(0, |len| u32::from_be_bytes(len) as usize))
+    }
+}
+
+/// The [`DefaultFraming`](DefaultFraming) with the chunk lengths written as 4-byte little endian
+/// integers, for interoperating with formats which use little endian framing
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LittleEndianFraming;
+
+impl Framing for LittleEndianFraming {
+    fn write_header<W: Write>(&mut self, writer: &mut W, nonce: &[u8]) -> Result<usize, W::Error> {
+        DefaultFraming.write_header(writer, nonce)
+    }
+
+    fn write_chunk_header<W: Write>(
+        &mut self,
+        writer: &mut W,
+        len: usize,
+    ) -> Result<usize, W::Error> {
+        writer.write_all(&(len as u32).to_le_bytes())?;
+        Ok(4)
+    }
+
+    fn read_header<R: Read>(
+        &mut self,
+        reader: &mut R,
+        nonce: &mut [u8],
+    ) -> Result<(), Error<R::Error>> {
+        DefaultFraming.read_header(reader, nonce)
+    }
+
+    fn read_chunk_header<R: Read>(&mut self, reader: &mut R) -> Result<usize, Error<R::Error>> {
+        Ok(read_length_prefix(reader)?.map_or(0, |len| u32::from_le_bytes(len) as usize))
+    }
+}
+
+/// Wraps another framing, by default the [`DefaultFraming`](DefaultFraming), and writes the
+/// length of the nonce as a single byte before its stream header. The nonce length tells apart
+/// the STREAM primitives of an AEAD, such as the 7-byte nonce of `StreamBE32` and the 8-byte one
+/// of `StreamLE31` with a 12-byte AEAD nonce, so a Reader configured for another primitive
+/// fails with [`Error::StreamMismatch`](Error::StreamMismatch) right away rather than reading a
+/// nonce of the wrong length and failing to authenticate the first chunk
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NonceLenFraming<F = DefaultFraming>(pub F);
+
+impl<F: Framing> Framing for NonceLenFraming<F> {
+    fn write_header<W: Write>(&mut self, writer: &mut W, nonce: &[u8]) -> Result<usize, W::Error> {
+        writer.write_all(&[nonce.len() as u8])?;
+        Ok(1 + self.0.write_header(writer, nonce)?)
+    }
+
+    fn write_chunk_header<W: Write>(
+        &mut self,
+        writer: &mut W,
+        len: usize,
+    ) -> Result<usize, W::Error> {
+        self.0.write_chunk_header(writer, len)
+    }
+
+    fn read_header<R: Read>(
+        &mut self,
+        reader: &mut R,
+        nonce: &mut [u8],
+    ) -> Result<(), Error<R::Error>> {
+        let mut nonce_len = [0u8; 1];
+        reader.read_exact(&mut nonce_len)?;
+        if nonce_len[0] as usize != nonce.len() {
+            return Err(Error::StreamMismatch);
+        }
+        self.0.read_header(reader, nonce)
+    }
+
+    fn read_chunk_header<R: Read>(&mut self, reader: &mut R) -> Result<usize, Error<R::Error>> {
+        self.0.read_chunk_header(reader)
+    }
+
+    fn max_chunk_len(&self) -> usize {
+        self.0.max_chunk_len()
+    }
+
+    fn header_len(&self, nonce_len: usize) -> usize {
+        1 + self.0.header_len(nonce_len)
+    }
+
+    fn chunk_header_len(&self) -> usize {
+        self.0.chunk_header_len()
+    }
+
+    fn marks_last_chunk(&self) -> bool {
+        self.0.marks_last_chunk()
+    }
+
+    fn write_last_chunk_header<W: Write>(
+        &mut self,
+        writer: &mut W,
+        len: usize,
+    ) -> Result<usize, W::Error> {
+        self.0.write_last_chunk_header(writer, len)
+    }
+
+    fn read_marked_chunk_header<R: Read>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<(usize, bool), Error<R::Error>> {
+        self.0.read_marked_chunk_header(reader)
+    }
+}
+
+/// The [`DefaultFraming`](DefaultFraming) with the most significant bit of the last chunk's length
+/// prefix set. The stream is self-delimiting: readers know where it ends from the last chunk's
+/// header alone, without an empty chunk header after it or the end of the inner reader, so it
+/// can be followed by other data. Chunks are limited to `2^31 - 1` bytes, tag included.
+///
+/// The mark isn't authenticated by itself, but the STREAM construction authenticates which chunk
+/// is the last one, so a moved or removed mark fails decryption
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LastChunkMarkFraming;
+
+const LAST_CHUNK_MARK: u32 = 1 << 31;
+
+impl Framing for LastChunkMarkFraming {
+    fn write_header<W: Write>(&mut self, writer: &mut W, nonce: &[u8]) -> Result<usize, W::Error> {
+        DefaultFraming.write_header(writer, nonce)
+    }
+
+    fn write_chunk_header<W: Write>(
+        &mut self,
+        writer: &mut W,
+        len: usize,
+    ) -> Result<usize, W::Error> {
+        DefaultFraming.write_chunk_header(writer, len)
+    }
+
+    fn read_header<R: Read>(
+        &mut self,
+        reader: &mut R,
+        nonce: &mut [u8],
+    ) -> Result<(), Error<R::Error>> {
+        DefaultFraming.read_header(reader, nonce)
+    }
+
+    fn read_chunk_header<R: Read>(&mut self, reader: &mut R) -> Result<usize, Error<R::Error>> {
+        Ok(self.read_marked_chunk_header(reader)?.0)
+    }
+
+    fn max_chunk_len(&self) -> usize {
+        (LAST_CHUNK_MARK - 1) as usize
+    }
+
+    fn marks_last_chunk(&self) -> bool {
+        true
+    }
+
+    fn write_last_chunk_header<W: Write>(
+        &mut self,
+        writer: &mut W,
+        len: usize,
+    ) -> Result<usize, W::Error> {
+        writer.write_all(&(len as u32 | LAST_CHUNK_MARK).to_be_bytes())?;
+        Ok(4)
+    }
+
+    fn read_marked_chunk_header<R: Read>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<(usize, bool), Error<R::Error>> {
+        Ok(read_length_prefix(reader)?.map_or((0, false), |len| {
+            let len = u32::from_be_bytes(len);
+            (
+                (len & !LAST_CHUNK_MARK) as usize,
+                len & LAST_CHUNK_MARK != 0,
+            )
+        }))
+    }
+}
+
+/// The byte order of the chunk length prefixes, as a [`Framing`](Framing) chosen at runtime, such
+/// as from a [`Config`](crate::Config). `Big` is the [`DefaultFraming`](DefaultFraming) and
+/// `Little` the [`LittleEndianFraming`](LittleEndianFraming)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Endianness {
+    #[default]
+    Big,
+    Little,
+}
+
+impl Framing for Endianness {
+    fn write_header<W: Write>(&mut self, writer: &mut W, nonce: &[u8]) -> Result<usize, W::Error> {
+        DefaultFraming.write_header(writer, nonce)
+    }
+
+    fn write_chunk_header<W: Write>(
+        &mut self,
+        writer: &mut W,
+        len: usize,
+    ) -> Result<usize, W::Error> {
+        match self {
+            Self::Big => DefaultFraming.write_chunk_header(writer, len),
+            Self::Little => LittleEndianFraming.write_chunk_header(writer, len),
+        }
+    }
+
+    fn read_header<R: Read>(
+        &mut self,
+        reader: &mut R,
+        nonce: &mut [u8],
+    ) -> Result<(), Error<R::Error>> {
+        DefaultFraming.read_header(reader, nonce)
+    }
+
+    fn read_chunk_header<R: Read>(&mut self, reader: &mut R) -> Result<usize, Error<R::Error>> {
+        match self {
+            Self::Big => DefaultFraming.read_chunk_header(reader),
+            Self::Little => LittleEndianFraming.read_chunk_header(reader),
+        }
+    }
+}
+
+/// Reads a 4-byte chunk length prefix, or returns `None` if the reader is already at its end
+fn read_length_prefix<R: Read>(reader: &mut R) -> Result<Option<[u8; 4]>, Error<R::Error>> {
+    let mut len = [0u8; 4];
+    let mut offset = 0;
+    while offset < 4 {
+        let read = reader.read(&mut len[offset..])?;
+        if read == 0 {
+            if offset == 0 {
+                return Ok(None);
+            } else {
+                return Err(Error::Aead);
+            }
+        }
+        offset += read;
+    }
+    Ok(Some(len))
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::KEY;
+    use crate::{ArrayBuffer, DecryptBufReader, EncryptBufWriter};
+    use aead::stream::StreamBE32;
+    use aead::NewAead;
+    use chacha20poly1305::ChaCha20Poly1305;
+
+    const MAGIC: &[u8; 4] = b"AIO1";
+
+    /// A magic string before the nonce and 2-byte little endian chunk lengths
+    struct MagicFraming;
+
+    impl Framing for MagicFraming {
+        fn write_header<W: Write>(
+            &mut self,
+            writer: &mut W,
+            nonce: &[u8],
+        ) -> Result<usize, W::Error> {
+            writer.write_all(MAGIC)?;
+            writer.write_all(nonce)?;
+            Ok(MAGIC.len() + nonce.len())
+        }
+
+        fn write_chunk_header<W: Write>(
+            &mut self,
+            writer: &mut W,
+            len: usize,
+        ) -> Result<usize, W::Error> {
+            writer.write_all(&(len as u16).to_le_bytes())?;
+            Ok(2)
+        }
+
+        fn read_header<R: Read>(
+            &mut self,
+            reader: &mut R,
+            nonce: &mut [u8],
+        ) -> Result<(), Error<R::Error>> {
+            let mut magic = [0u8; 4];
+            reader.read_exact(&mut magic)?;
+            if &magic != MAGIC {
+                return Err(Error::Aead);
+            }
+            Ok(reader.read_exact(nonce)?)
+        }
+
+        fn read_chunk_header<R: Read>(&mut self, reader: &mut R) -> Result<usize, Error<R::Error>> {
+            let mut len = [0u8; 2];
+            match reader.read(&mut len[..1])? {
+                0 => return Ok(0),
+                _ => reader.read_exact(&mut len[1..])?,
+            }
+            Ok(u16::from_le_bytes(len) as usize)
+        }
+
+        fn max_chunk_len(&self) -> usize {
+            u16::MAX as usize
+        }
+
+        fn header_len(&self, nonce_len: usize) -> usize {
+            MAGIC.len() + nonce_len
+        }
+
+        fn chunk_header_len(&self) -> usize {
+            2
+        }
+    }
+
+    #[test]
+    fn custom_framing() {
+        let aead = ChaCha20Poly1305::new(KEY.into());
+        let plaintext = [0x42u8; 300];
+
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBufWriter::<_, _, _, StreamBE32<_>, _>::from_aead_with_framing(
+            aead.clone(),
+            &Default::default(),
+            MagicFraming,
+            ArrayBuffer::<128>::new(),
+            &mut ciphertext,
+        )
+        .unwrap();
+        std::io::Write::write_all(&mut writer, &plaintext).unwrap();
+        std::io::Write::flush(&mut writer).unwrap();
+        drop(writer);
+
+        assert_eq!(&ciphertext[..4], MAGIC);
+
+        let mut reader = DecryptBufReader::<_, _, _, StreamBE32<_>, _>::from_aead_with_framing(
+            aead,
+            MagicFraming,
+            ArrayBuffer::<256>::new(),
+            ciphertext.as_slice(),
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, plaintext);
+    }
+
+    /// A buffer reporting a far larger capacity than its framing can describe
+    struct HugeBuffer(Vec<u8>);
+
+    impl AsRef<[u8]> for HugeBuffer {
+        fn as_ref(&self) -> &[u8] {
+            &self.0
+        }
+    }
+
+    impl AsMut<[u8]> for HugeBuffer {
+        fn as_mut(&mut self) -> &mut [u8] {
+            &mut self.0
+        }
+    }
+
+    impl aead::Buffer for HugeBuffer {
+        fn extend_from_slice(&mut self, other: &[u8]) -> aead::Result<()> {
+            self.0.extend_from_slice(other);
+            Ok(())
+        }
+        fn truncate(&mut self, len: usize) {
+            self.0.truncate(len);
+        }
+    }
+
+    impl crate::CappedBuffer for HugeBuffer {
+        fn capacity(&self) -> usize {
+            usize::MAX
+        }
+    }
+
+    impl crate::ResizeBuffer for HugeBuffer {
+        fn resize_zeroed(&mut self, new_len: usize) -> aead::Result<()> {
+            self.0.resize(new_len, 0);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn capacity_clamped_to_prefix_width() {
+        let aead = ChaCha20Poly1305::new(KEY.into());
+        let plaintext = vec![0x42u8; 100_000];
+
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBufWriter::<_, _, _, StreamBE32<_>, _>::from_aead_with_framing(
+            aead.clone(),
+            &Default::default(),
+            MagicFraming,
+            HugeBuffer(Vec::new()),
+            &mut ciphertext,
+        )
+        .unwrap();
+        let (first, rest) = plaintext.split_at(u16::MAX as usize - 16);
+        std::io::Write::write_all(&mut writer, first).unwrap();
+        std::io::Write::write_all(&mut writer, rest).unwrap();
+        writer.flush_with(crate::FlushMode::Finalize).unwrap();
+        drop(writer);
+
+        let header = MAGIC.len() + 7;
+        let first_chunk = u16::from_le_bytes([ciphertext[header], ciphertext[header + 1]]);
+        assert_eq!(first_chunk, u16::MAX);
+
+        let mut reader = DecryptBufReader::<_, _, _, StreamBE32<_>, _>::from_aead_with_framing(
+            aead,
+            MagicFraming,
+            HugeBuffer(Vec::new()),
+            ciphertext.as_slice(),
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, plaintext);
+    }
+
+    /// A version byte before the nonce, which is read without being checked
+    struct VersionedFraming;
+
+    impl Framing for VersionedFraming {
+        fn write_header<W: Write>(
+            &mut self,
+            writer: &mut W,
+            nonce: &[u8],
+        ) -> Result<usize, W::Error> {
+            writer.write_all(&[1])?;
+            writer.write_all(nonce)?;
+            Ok(1 + nonce.len())
+        }
+
+        fn write_chunk_header<W: Write>(
+            &mut self,
+            writer: &mut W,
+            len: usize,
+        ) -> Result<usize, W::Error> {
+            DefaultFraming.write_chunk_header(writer, len)
+        }
+
+        fn read_header<R: Read>(
+            &mut self,
+            reader: &mut R,
+            nonce: &mut [u8],
+        ) -> Result<(), Error<R::Error>> {
+            let mut version = [0u8; 1];
+            reader.read_exact(&mut version)?;
+            Ok(reader.read_exact(nonce)?)
+        }
+
+        fn read_chunk_header<R: Read>(&mut self, reader: &mut R) -> Result<usize, Error<R::Error>> {
+            DefaultFraming.read_chunk_header(reader)
+        }
+
+        fn header_len(&self, nonce_len: usize) -> usize {
+            1 + nonce_len
+        }
+    }
+
+    #[test]
+    fn header_aad() {
+        let aead = ChaCha20Poly1305::new(KEY.into());
+        let plaintext = [0x42u8; 300];
+        let encrypt = |header_aad: bool| {
+            let mut ciphertext = Vec::new();
+            let mut writer = EncryptBufWriter::<_, _, _, StreamBE32<_>, _>::from_aead_with_framing(
+                aead.clone(),
+                &Default::default(),
+                VersionedFraming,
+                ArrayBuffer::<128>::new(),
+                &mut ciphertext,
+            )
+            .unwrap();
+            if header_aad {
+                writer = writer.with_header_aad();
+            }
+            std::io::Write::write_all(&mut writer, &plaintext).unwrap();
+            drop(writer);
+            ciphertext
+        };
+        let decrypt = |ciphertext: &[u8], header_aad: bool| {
+            let mut reader = DecryptBufReader::<_, _, _, StreamBE32<_>, _>::from_aead_with_framing(
+                aead.clone(),
+                VersionedFraming,
+                ArrayBuffer::<256>::new(),
+                ciphertext,
+            )
+            .unwrap();
+            if header_aad {
+                reader = reader.with_header_aad();
+            }
+            let mut out = Vec::new();
+            std::io::Read::read_to_end(&mut reader, &mut out).map(|_| out)
+        };
+
+        let mut ciphertext = encrypt(false);
+        ciphertext[0] = 2;
+        assert_eq!(decrypt(&ciphertext, false).unwrap(), plaintext);
+
+        let mut ciphertext = encrypt(true);
+        assert_eq!(decrypt(&ciphertext, true).unwrap(), plaintext);
+        assert!(decrypt(&ciphertext, false).is_err());
+        ciphertext[0] = 2;
+        assert!(decrypt(&ciphertext, true).is_err());
+    }
+
+    #[test]
+    fn little_endian_framing() {
+        let aead = ChaCha20Poly1305::new(KEY.into());
+        let plaintext = [0x42u8; 300];
+        fn encrypt<F: Framing>(aead: &ChaCha20Poly1305, framing: F, plaintext: &[u8]) -> Vec<u8> {
+            let mut ciphertext = Vec::new();
+            let mut writer = EncryptBufWriter::<_, _, _, StreamBE32<_>, _>::from_aead_with_framing(
+                aead.clone(),
+                &Default::default(),
+                framing,
+                ArrayBuffer::<128>::new(),
+                &mut ciphertext,
+            )
+            .unwrap();
+            std::io::Write::write_all(&mut writer, plaintext).unwrap();
+            drop(writer);
+            ciphertext
+        }
+        let little = encrypt(&aead, LittleEndianFraming, &plaintext);
+        let big = encrypt(&aead, DefaultFraming, &plaintext);
+
+        // only the length prefixes differ
+        let nonce_len = crate::nonce_len::<ChaCha20Poly1305, StreamBE32<_>>();
+        assert_eq!(little.len(), big.len());
+        assert_eq!(&little[..nonce_len], &big[..nonce_len]);
+        let prefix = nonce_len..nonce_len + 4;
+        assert_ne!(little[prefix.clone()], big[prefix.clone()]);
+        let mut swapped = big[prefix.clone()].to_vec();
+        swapped.reverse();
+        assert_eq!(little[prefix], swapped[..]);
+
+        let mut reader = DecryptBufReader::<_, _, _, StreamBE32<_>, _>::from_aead_with_framing(
+            aead.clone(),
+            LittleEndianFraming,
+            ArrayBuffer::<128>::new(),
+            little.as_slice(),
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, plaintext);
+
+        let mut reader = DecryptBufReader::<_, _, _, StreamBE32<_>, _>::from_aead_with_framing(
+            aead,
+            DefaultFraming,
+            ArrayBuffer::<128>::new(),
+            big.as_slice(),
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, plaintext);
+    }
+
+    #[test]
+    fn last_chunk_mark() {
+        let aead = ChaCha20Poly1305::new(KEY.into());
+        let plaintext = [0x42u8; 300];
+        let trailer = b"followed by other data";
+        let writer = |ciphertext| {
+            EncryptBufWriter::<_, _, _, StreamBE32<_>, _>::from_aead_with_framing(
+                aead.clone(),
+                &Default::default(),
+                LastChunkMarkFraming,
+                ArrayBuffer::<128>::new(),
+                ciphertext,
+            )
+            .unwrap()
+        };
+        let reader = |ciphertext| {
+            DecryptBufReader::<_, _, _, StreamBE32<_>, _>::from_aead_with_framing(
+                aead.clone(),
+                LastChunkMarkFraming,
+                ArrayBuffer::<256>::new(),
+                ciphertext,
+            )
+            .unwrap()
+        };
+
+        let mut ciphertext = Vec::new();
+        let mut encryptor = writer(&mut ciphertext);
+        // a chunk's worth at a time, so that the chunks are filled like `try_write` fills them
+        for chunk in plaintext.chunks(112) {
+            std::io::Write::write_all(&mut encryptor, chunk).unwrap();
+        }
+        encryptor.flush_with(crate::FlushMode::Finalize).unwrap();
+        drop(encryptor);
+        let mut nonblocking = Vec::new();
+        let mut encryptor = writer(&mut nonblocking);
+        let mut written = 0;
+        while written < plaintext.len() {
+            match encryptor.try_write(&plaintext[written..]).unwrap() {
+                crate::Progress::Buffered(len) | crate::Progress::Flushed(len) => written += len,
+                crate::Progress::Pending => unreachable!(),
+            }
+        }
+        encryptor.try_flush(crate::FlushMode::Finalize).unwrap();
+        drop(encryptor);
+        assert_eq!(nonblocking, ciphertext);
+        // the last chunk's header is marked and no end marker follows it
+        let last = 7 + 2 * (4 + 128);
+        assert_eq!(ciphertext[last] & 0x80, 0x80);
+        assert_eq!(ciphertext.len(), last + 4 + 300 - 2 * 112 + 16);
+        ciphertext.extend_from_slice(trailer);
+
+        let mut decryptor = reader(ciphertext.as_slice());
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut decryptor, &mut out).unwrap();
+        assert_eq!(out, plaintext);
+        assert_eq!(decryptor.into_inner(), trailer);
+
+        let mut decryptor = reader(ciphertext.as_slice());
+        let mut out = Vec::new();
+        let mut buf = [0u8; 64];
+        while !decryptor.is_at_eof() {
+            match decryptor.try_read(&mut buf).unwrap() {
+                crate::Progress::Buffered(len) | crate::Progress::Flushed(len) => {
+                    out.extend_from_slice(&buf[..len])
+                }
+                crate::Progress::Pending => unreachable!(),
+            }
+        }
+        assert_eq!(out, plaintext);
+        assert_eq!(decryptor.into_inner(), trailer);
+
+        // the last chunk without its mark runs into the data after it
+        let mut cleared = ciphertext.clone();
+        cleared[last] &= 0x7f;
+        let mut out = Vec::new();
+        assert!(std::io::Read::read_to_end(&mut reader(cleared.as_slice()), &mut out).is_err());
+        // a chunk marked before the last one fails to authenticate
+        let mut moved = ciphertext.clone();
+        moved[7] |= 0x80;
+        let mut out = Vec::new();
+        assert!(std::io::Read::read_to_end(&mut reader(moved.as_slice()), &mut out).is_err());
+    }
+
+    #[test]
+    fn nonce_len_mismatch() {
+        use aead::stream::StreamLE31;
+
+        let aead = ChaCha20Poly1305::new(KEY.into());
+        let plaintext = [0x42u8; 300];
+        let encrypt = |framing| {
+            let mut ciphertext = Vec::new();
+            let mut writer = EncryptBufWriter::<_, _, _, StreamBE32<_>, _>::from_aead_with_framing(
+                aead.clone(),
+                &Default::default(),
+                framing,
+                ArrayBuffer::<128>::new(),
+                &mut ciphertext,
+            )
+            .unwrap();
+            std::io::Write::write_all(&mut writer, &plaintext).unwrap();
+            drop(writer);
+            ciphertext
+        };
+        let ciphertext = encrypt(NonceLenFraming(DefaultFraming));
+        assert_eq!(ciphertext[0], 7);
+
+        let mut reader = DecryptBufReader::<_, _, _, StreamBE32<_>, _>::from_aead_with_framing(
+            aead.clone(),
+            NonceLenFraming(DefaultFraming),
+            ArrayBuffer::<256>::new(),
+            ciphertext.as_slice(),
+        )
+        .unwrap();
+        assert_eq!(reader.decrypt_all().unwrap(), plaintext);
+
+        // a Reader for another STREAM primitive rejects the header
+        let mut reader = DecryptBufReader::<_, _, _, StreamLE31<_>, _>::from_aead_with_framing(
+            aead.clone(),
+            NonceLenFraming(DefaultFraming),
+            ArrayBuffer::<256>::new(),
+            ciphertext.as_slice(),
+        )
+        .unwrap();
+        assert!(matches!(reader.decrypt_all(), Err(Error::StreamMismatch)));
+        let mut reader = DecryptBufReader::<_, _, _, StreamLE31<_>, _>::from_aead_with_framing(
+            aead.clone(),
+            NonceLenFraming(DefaultFraming),
+            ArrayBuffer::<256>::new(),
+            ciphertext.as_slice(),
+        )
+        .unwrap();
+        assert!(matches!(
+            reader.try_read(&mut [0u8; 64]),
+            Err(Error::StreamMismatch)
+        ));
+        let err = std::io::Read::read_to_end(
+            &mut DecryptBufReader::<_, _, _, StreamLE31<_>, _>::from_aead_with_framing(
+                aead.clone(),
+                NonceLenFraming(DefaultFraming),
+                ArrayBuffer::<256>::new(),
+                ciphertext.as_slice(),
+            )
+            .unwrap(),
+            &mut Vec::new(),
+        )
+        .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(matches!(Error::from_io_error(err), Error::StreamMismatch));
+
+        // without the nonce length the mismatch only shows as a chunk failing to authenticate
+        let ciphertext = encrypt(NonceLenFraming(DefaultFraming)).split_off(1);
+        let mut reader = DecryptBufReader::<_, _, _, StreamLE31<_>, _>::from_aead(
+            aead,
+            ArrayBuffer::<256>::new(),
+            ciphertext.as_slice(),
+        )
+        .unwrap();
+        assert!(matches!(reader.decrypt_all(), Err(Error::Aead)));
+    }
+}
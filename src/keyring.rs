@@ -0,0 +1,81 @@
+/// A source of AEAD primitives for keys which can't be handed out as raw bytes, such as keys held
+/// by an HSM or a TEE. Where [`BufWriter::new`](crate::EncryptBufWriter::new) builds the AEAD from
+/// a [`Key`](aead::Key), [`BufWriter::from_keyring`](crate::EncryptBufWriter::from_keyring) and
+/// [`BufReader::from_keyring`](crate::DecryptBufReader::from_keyring) ask the keyring for a
+/// ready-made AEAD which performs the operations inside the secure element.
+///
+/// The STREAM constructors of `aead` still require the AEAD to implement
+/// [`NewAead`](aead::NewAead), although the keyring's AEAD is never constructed from a key
+pub trait Keyring<A> {
+    /// Identifies a key within the keyring
+    type KeyId: ?Sized;
+    /// The error returned when the keyring can't provide an AEAD for a key
+    type Error;
+
+    /// Returns the AEAD primitive for the identified key
+    fn aead(&self, key_id: &Self::KeyId) -> Result<A, Self::Error>;
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::KEY;
+    use crate::{ArrayBuffer, DecryptBE32BufReader, EncryptBE32BufWriter, FlushMode, KeyringError};
+    use aead::NewAead;
+    use chacha20poly1305::ChaCha20Poly1305;
+
+    /// Stands in for a secure element by handing out software AEADs for named keys
+    struct MockKeyring;
+
+    impl Keyring<ChaCha20Poly1305> for MockKeyring {
+        type KeyId = str;
+        type Error = &'static str;
+
+        fn aead(&self, key_id: &str) -> Result<ChaCha20Poly1305, Self::Error> {
+            match key_id {
+                "signing" => Ok(ChaCha20Poly1305::new(KEY.into())),
+                _ => Err("unknown key"),
+            }
+        }
+    }
+
+    #[test]
+    fn keyring_round_trip() {
+        let plaintext = [0x42u8; 300];
+
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::from_keyring(
+            &MockKeyring,
+            "signing",
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            &mut ciphertext,
+        )
+        .unwrap();
+        std::io::Write::write_all(&mut writer, &plaintext).unwrap();
+        writer.flush_with(FlushMode::Finalize).unwrap();
+        drop(writer);
+
+        let mut reader = DecryptBE32BufReader::from_keyring(
+            &MockKeyring,
+            "signing",
+            ArrayBuffer::<128>::new(),
+            ciphertext.as_slice(),
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, plaintext);
+
+        assert!(matches!(
+            DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::from_keyring(
+                &MockKeyring,
+                "missing",
+                ArrayBuffer::<128>::new(),
+                ciphertext.as_slice(),
+            ),
+            Err(KeyringError::Keyring("unknown key"))
+        ));
+    }
+}
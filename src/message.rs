@@ -0,0 +1,183 @@
+use crate::error::Error;
+use crate::reader::DecryptBufReader;
+use crate::rw::Read;
+use crate::stream::MAX_STREAM_CHUNK_LEN;
+use crate::RECOMMENDED_CHUNK_SIZE;
+use aead::generic_array::typenum::Unsigned;
+use aead::generic_array::ArrayLength;
+use aead::stream::{NewStream, Nonce, NonceSize, StreamPrimitive};
+use aead::{AeadCore, AeadInPlace, Key, NewAead};
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::ops::Sub;
+
+/// Reads a sequence of independent encrypted messages stored back to back in one reader, each a
+/// complete stream with its own nonce as written by a
+/// [`BufWriter`](crate::EncryptBufWriter) with
+/// [`with_end_marker`](crate::EncryptBufWriter::with_end_marker). The end marker tells where one
+/// message ends and the nonce of the next one begins. Only the last message may go without one,
+/// as it ends with the reader.
+///
+/// Every message is decrypted in full before it is returned, with chunks of up to
+/// [`MAX_STREAM_CHUNK_LEN`](MAX_STREAM_CHUNK_LEN) bytes by default
+pub struct MessageReader<A, R, S>
+where
+    A: NewAead,
+{
+    key: Key<A>,
+    reader: R,
+    max_chunk_len: usize,
+    stream: PhantomData<S>,
+}
+
+impl<A, R, S> MessageReader<A, R, S>
+where
+    A: AeadInPlace + NewAead,
+    R: Read,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    /// Constructs a new MessageReader decrypting every message with the same AEAD key
+    pub fn new(key: &Key<A>, reader: R) -> Self {
+        Self {
+            key: key.clone(),
+            reader,
+            max_chunk_len: MAX_STREAM_CHUNK_LEN,
+            stream: PhantomData,
+        }
+    }
+
+    /// Accepts encrypted chunks, tag included, of up to `len` bytes instead of
+    /// [`MAX_STREAM_CHUNK_LEN`](MAX_STREAM_CHUNK_LEN). A length which leaves no room next to the
+    /// tag fails every message with [`Error::Aead`](Error::Aead)
+    pub fn with_max_chunk_len(mut self, len: usize) -> Self {
+        self.max_chunk_len = len;
+        self
+    }
+
+    /// Reads and decrypts the next message, or returns `None` if the reader ended right after the
+    /// previous one. A message which is cut off or fails to authenticate fails with
+    /// [`Error::Aead`](Error::Aead)
+    pub fn next_message(&mut self) -> Result<Option<Vec<u8>>, Error<R::Error>> {
+        let mut nonce = Nonce::<A, S>::default();
+        let mut filled = 0;
+        while filled < nonce.len() {
+            match self.reader.read(&mut nonce[filled..])? {
+                0 if filled == 0 => return Ok(None),
+                0 => return Err(Error::Aead),
+                read => filled += read,
+            }
+        }
+
+        let tag_len = <<A as AeadCore>::TagSize as Unsigned>::to_usize();
+        let max_chunk_len = self.max_chunk_len;
+        let buffer = Vec::with_capacity(RECOMMENDED_CHUNK_SIZE + tag_len);
+        let mut reader = DecryptBufReader::<A, _, _, S>::from_aead_with_nonce(
+            A::new(&self.key),
+            &nonce,
+            buffer,
+            ReadRef(&mut self.reader),
+        )
+        .and_then(|reader| reader.with_max_chunk_len(max_chunk_len))
+        .map_err(|_| Error::Aead)?;
+        reader.decrypt_all().map(Some)
+    }
+
+    /// Consumes the MessageReader and returns the inner reader
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<A, R, S> Iterator for MessageReader<A, R, S>
+where
+    A: AeadInPlace + NewAead,
+    R: Read,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    type Item = Result<Vec<u8>, Error<R::Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_message().transpose()
+    }
+}
+
+/// Lends the inner reader to the Reader of each message. A `&mut R` only implements
+/// [`Read`](Read) without any of the IO backends
+struct ReadRef<'a, R>(&'a mut R);
+
+impl<R> Read for ReadRef<'_, R>
+where
+    R: Read,
+{
+    type Error = R::Error;
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.0.read(buf)
+    }
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
+        self.0.read_exact(buf)
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::KEY;
+    use crate::{EncryptBE32BufWriter, FlushMode};
+    use aead::stream::StreamBE32;
+    use chacha20poly1305::ChaCha20Poly1305;
+
+    fn write_message(out: &mut Vec<u8>, nonce: &[u8; 7], plaintext: &[u8]) {
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            nonce.into(),
+            Vec::with_capacity(32),
+            out,
+        )
+        .unwrap()
+        .with_end_marker();
+        for part in plaintext.chunks(16) {
+            std::io::Write::write_all(&mut writer, part).unwrap();
+        }
+        writer.flush_with(FlushMode::Finalize).unwrap();
+    }
+
+    #[test]
+    fn read_messages() {
+        let messages: [&[u8]; 3] = [
+            b"the first message spanning a few chunks",
+            b"",
+            b"and the third one",
+        ];
+        let mut ciphertext = Vec::new();
+        for (i, message) in messages.iter().enumerate() {
+            write_message(&mut ciphertext, &[i as u8; 7], message);
+        }
+
+        let mut reader =
+            MessageReader::<ChaCha20Poly1305, _, StreamBE32<_>>::new(KEY.into(), &ciphertext[..]);
+        for message in messages {
+            assert_eq!(reader.next_message().unwrap().unwrap(), message);
+        }
+        assert!(reader.next_message().unwrap().is_none());
+
+        let read =
+            MessageReader::<ChaCha20Poly1305, _, StreamBE32<_>>::new(KEY.into(), &ciphertext[..])
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap();
+        assert_eq!(read, messages);
+
+        // a message cut off part way through is rejected
+        let mut reader = MessageReader::<ChaCha20Poly1305, _, StreamBE32<_>>::new(
+            KEY.into(),
+            &ciphertext[..ciphertext.len() - 10],
+        );
+        assert!(reader.next_message().unwrap().is_some());
+        assert!(reader.next_message().unwrap().is_some());
+        assert!(reader.next_message().is_err());
+    }
+}
@@ -0,0 +1,191 @@
+use crate::buffer::CappedBuffer;
+use crate::error::Error;
+use crate::framing::{DefaultFraming, Framing};
+use crate::rw::Write;
+use crate::writer::{EncryptBufWriter, FlushMode};
+use aead::generic_array::ArrayLength;
+use aead::stream::{NonceSize, StreamPrimitive};
+use aead::AeadInPlace;
+use core::ops::Sub;
+
+#[cfg(feature = "alloc")]
+use crate::buffer::ResizeBuffer;
+#[cfg(feature = "alloc")]
+use crate::reader::DecryptBufReader;
+#[cfg(feature = "alloc")]
+use crate::rw::Read;
+#[cfg(feature = "alloc")]
+use aead::stream::NewStream;
+#[cfg(feature = "alloc")]
+use aead::NewAead;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "alloc")]
+use core::convert::TryInto;
+
+/// Length of the substream ID at the start of each chunk's plaintext
+const ID_LEN: usize = 4;
+
+/// The ID of a substream and the data of one of its chunks
+#[cfg(feature = "alloc")]
+type SubstreamChunk<'a> = (u32, &'a [u8]);
+
+/// Interleaves several independent substreams, such as the channels of a connection, in one
+/// encrypted stream. Every chunk carries data of a single substream, with the substream's ID at
+/// the start of its plaintext, so the ID is encrypted and authenticated along with the data and
+/// a chunk can't be moved to another substream. With `alloc`, a
+/// [`DemuxReader`](crate::DemuxReader) hands the chunks back with their IDs.
+///
+/// Each write goes out as chunks of its own right away, so small writes cost a chunk's overhead
+/// each
+pub struct MuxWriter<A, B, W, S, F = DefaultFraming>
+where
+    A: AeadInPlace,
+    B: CappedBuffer,
+    W: Write,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    writer: EncryptBufWriter<A, B, W, S, F>,
+}
+
+impl<A, B, W, S, F> MuxWriter<A, B, W, S, F>
+where
+    A: AeadInPlace,
+    B: CappedBuffer,
+    W: Write,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    /// Constructs a new MuxWriter over a Writer which hasn't had any data written to it
+    pub fn new(writer: EncryptBufWriter<A, B, W, S, F>) -> Self {
+        Self { writer }
+    }
+
+    /// Writes `data` to the substream `id`, split into as many chunks as it takes. Fails if the
+    /// chunks have no room for any data next to the ID
+    pub fn write(&mut self, id: u32, data: &[u8]) -> Result<(), Error<W::Error>> {
+        let room = self
+            .writer
+            .chunk_room()
+            .checked_sub(ID_LEN)
+            .filter(|room| *room > 0)
+            .ok_or(Error::Aead)?;
+        for part in data.chunks(room) {
+            self.writer.write_chunk(&[&id.to_be_bytes(), part])?;
+        }
+        Ok(())
+    }
+
+    /// Finalizes the stream, after which nothing more can be written
+    pub fn finish(&mut self) -> Result<(), Error<W::Error>> {
+        self.writer.flush_with(FlushMode::Finalize)
+    }
+
+    /// Consumes the MuxWriter and returns the Writer
+    pub fn into_inner(self) -> EncryptBufWriter<A, B, W, S, F> {
+        self.writer
+    }
+}
+
+/// Reads the substreams interleaved by a [`MuxWriter`](MuxWriter) back out of the encrypted
+/// stream, one chunk at a time along with the ID of its substream
+#[cfg(feature = "alloc")]
+pub struct DemuxReader<A, B, R, S, F = DefaultFraming>
+where
+    A: AeadInPlace + NewAead,
+    R: Read,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    reader: DecryptBufReader<A, B, R, S, F>,
+    chunk: Vec<u8>,
+}
+
+#[cfg(feature = "alloc")]
+impl<A, B, R, S, F> DemuxReader<A, B, R, S, F>
+where
+    A: AeadInPlace + NewAead,
+    B: ResizeBuffer + CappedBuffer,
+    R: Read,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    /// Constructs a new DemuxReader over a Reader which hasn't had any data read from it
+    pub fn new(reader: DecryptBufReader<A, B, R, S, F>) -> Self {
+        Self {
+            reader,
+            chunk: Vec::new(),
+        }
+    }
+
+    /// Decrypts the next chunk and returns the ID of its substream with the data it carries, or
+    /// `None` once the stream has ended. A chunk without an ID fails with
+    /// [`Error::Aead`](Error::Aead)
+    pub fn next_chunk(&mut self) -> Result<Option<SubstreamChunk<'_>>, Error<R::Error>> {
+        if self.reader.read_into(&mut self.chunk)? == 0 {
+            return Ok(None);
+        }
+        if self.chunk.len() < ID_LEN {
+            return Err(Error::Aead);
+        }
+        let (id, data) = self.chunk.split_at(ID_LEN);
+        let id = u32::from_be_bytes(id.try_into().map_err(|_| Error::Aead)?);
+        Ok(Some((id, data)))
+    }
+
+    /// Consumes the DemuxReader and returns the Reader
+    pub fn into_inner(self) -> DecryptBufReader<A, B, R, S, F> {
+        self.reader
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{reader_with_buffer, writer_with_buffer};
+    use crate::ArrayBuffer;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn interleaved_substreams() {
+        let first: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        let second = b"a second channel".repeat(10);
+
+        let mut ciphertext = Vec::new();
+        let mut writer = MuxWriter::new(writer_with_buffer(
+            ArrayBuffer::<64>::new(),
+            &mut ciphertext,
+        ));
+        for (a, b) in first.chunks(70).zip(second.chunks(50)) {
+            writer.write(1, a).unwrap();
+            writer.write(2, b).unwrap();
+        }
+        writer.write(1, &first[280..]).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+
+        let mut reader = DemuxReader::new(reader_with_buffer(
+            ArrayBuffer::<64>::new(),
+            &ciphertext[..],
+        ));
+        let mut substreams = BTreeMap::<u32, Vec<u8>>::new();
+        let mut ids = Vec::new();
+        while let Some((id, data)) = reader.next_chunk().unwrap() {
+            substreams.entry(id).or_default().extend_from_slice(data);
+            ids.push(id);
+        }
+        assert_eq!(substreams[&1], first);
+        assert_eq!(substreams[&2], second);
+        // 70 bytes need two chunks of up to 44 bytes, 50 bytes as well
+        assert_eq!(&ids[..4], &[1, 1, 2, 2]);
+    }
+}
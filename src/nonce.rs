@@ -0,0 +1,166 @@
+use crate::error::InvalidNonceLength;
+use crate::framing::{DefaultFraming, Framing};
+use aead::generic_array::typenum::Unsigned;
+use aead::generic_array::ArrayLength;
+use aead::stream::{Nonce, NonceSize, StreamPrimitive};
+use aead::{AeadCore, AeadInPlace};
+use core::ops::Sub;
+
+/// The length in bytes of the nonce expected by a [`BufWriter`](crate::EncryptBufWriter) using
+/// the AEAD `A` and the [`StreamPrimitive`](StreamPrimitive) `S`. This is the nonce size of the
+/// AEAD minus the bytes the stream uses for its counter and last block flag
+pub fn nonce_len<A, S>() -> usize
+where
+    A: AeadInPlace,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    NonceSize::<A, S>::to_usize()
+}
+
+/// The smallest amount of plaintext per chunk for which the length prefix and tag of every chunk
+/// written by a [`BufWriter`](crate::EncryptBufWriter) using the AEAD `A` add at most
+/// `max_overhead_ratio` of the plaintext length, such as `0.01` for 1%. The buffer handed to the
+/// Writer needs room for this many bytes plus the tag. The nonce at the start of the stream isn't
+/// counted, as it is written only once. The result is never less than 1, nor more than a chunk
+/// header of the [`DefaultFraming`](crate::DefaultFraming) can describe next to the tag. Panics
+/// unless `max_overhead_ratio` is greater than 0
+pub fn recommended_chunk_size<A>(max_overhead_ratio: f64) -> usize
+where
+    A: AeadCore,
+{
+    assert!(
+        max_overhead_ratio > 0.0,
+        "overhead ratio must be greater than 0"
+    );
+    let tag_len = <A::TagSize as Unsigned>::to_usize();
+    let exact = (4 + tag_len) as f64 / max_overhead_ratio;
+    // rounds up without `f64::ceil`, which isn't available in `core`. The cast saturates for
+    // tiny ratios, which the clamp below takes care of
+    let size = exact as usize;
+    let size = if (size as f64) < exact {
+        size.saturating_add(1)
+    } else {
+        size
+    };
+    let max = DefaultFraming.max_chunk_len() - tag_len;
+    // an infinite ratio allows chunks of any size, but a chunk holds at least a byte
+    size.clamp(1, max)
+}
+
+/// The smallest buffer capacity a [`BufWriter`](crate::EncryptBufWriter) or
+/// [`BufReader`](crate::DecryptBufReader) using the AEAD `A` accepts: room for the tag and a
+/// single byte of plaintext per chunk. Smaller buffers fail construction with
+/// [`InvalidCapacity`](crate::InvalidCapacity). Being a `const fn`, it can size or check an
+/// [`ArrayBuffer`](crate::ArrayBuffer) at compile time:
+///
+/// ```
+/// # use aead_io::min_capacity_for;
+/// # use chacha20poly1305::ChaCha20Poly1305;
+/// const CAP: usize = 64;
+/// const _: () = assert!(CAP >= min_capacity_for::<ChaCha20Poly1305>());
+/// ```
+///
+/// Padding, a stream overhead and the like take further room on top of this
+pub const fn min_capacity_for<A>() -> usize
+where
+    A: AeadCore,
+{
+    <A::TagSize as Unsigned>::USIZE + 1
+}
+
+/// Constructs a nonce for the AEAD `A` and the [`StreamPrimitive`](StreamPrimitive) `S` from
+/// raw bytes. Fails if `bytes` is not exactly [`nonce_len`](nonce_len) bytes long
+pub fn nonce_from_slice<A, S>(bytes: &[u8]) -> Result<Nonce<A, S>, InvalidNonceLength>
+where
+    A: AeadInPlace,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    if bytes.len() != nonce_len::<A, S>() {
+        return Err(InvalidNonceLength);
+    }
+    Ok(Nonce::<A, S>::clone_from_slice(bytes))
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::KEY;
+    use aead::stream::{StreamBE32, StreamLE31};
+    use chacha20poly1305::ChaCha20Poly1305;
+
+    type BE32 = StreamBE32<ChaCha20Poly1305>;
+    type LE31 = StreamLE31<ChaCha20Poly1305>;
+
+    #[test]
+    fn nonce_len_per_stream() {
+        assert_eq!(nonce_len::<ChaCha20Poly1305, BE32>(), 7);
+        assert_eq!(nonce_len::<ChaCha20Poly1305, LE31>(), 8);
+    }
+
+    #[test]
+    fn from_slice() {
+        let nonce = nonce_from_slice::<ChaCha20Poly1305, BE32>(&[0x42; 7]).unwrap();
+        assert_eq!(nonce.as_slice(), &[0x42; 7]);
+        assert_eq!(
+            nonce_from_slice::<ChaCha20Poly1305, BE32>(&[0x42; 6]),
+            Err(InvalidNonceLength)
+        );
+        assert_eq!(
+            nonce_from_slice::<ChaCha20Poly1305, BE32>(&[0x42; 8]),
+            Err(InvalidNonceLength)
+        );
+        assert!(nonce_from_slice::<ChaCha20Poly1305, LE31>(&[0x42; 8]).is_ok());
+    }
+
+    #[test]
+    fn chunk_size_for_overhead() {
+        let size = recommended_chunk_size::<ChaCha20Poly1305>(0.01);
+        assert_eq!(size, 2000);
+        let ciphertext_len = crate::predicted_ciphertext_len::<ChaCha20Poly1305, BE32>(size, size);
+        let overhead = (ciphertext_len - 7 - size) as f64 / size as f64;
+        assert!(overhead <= 0.01, "{}", overhead);
+        // one byte less goes over
+        assert!(20.0 / (size - 1) as f64 > 0.01);
+
+        assert_eq!(recommended_chunk_size::<ChaCha20Poly1305>(0.3), 67);
+        assert_eq!(recommended_chunk_size::<ChaCha20Poly1305>(1000.0), 1);
+        assert_eq!(recommended_chunk_size::<ChaCha20Poly1305>(f64::INFINITY), 1);
+        assert!(recommended_chunk_size::<ChaCha20Poly1305>(1e-9) > 1_000_000_000);
+        // ratios too small for any chunk a header can describe get the largest one
+        let max = u32::MAX as usize - 16;
+        assert_eq!(recommended_chunk_size::<ChaCha20Poly1305>(1e-300), max);
+        assert_eq!(
+            recommended_chunk_size::<ChaCha20Poly1305>(f64::MIN_POSITIVE),
+            max
+        );
+        assert_eq!(recommended_chunk_size::<ChaCha20Poly1305>(5e-324), max);
+    }
+
+    #[test]
+    fn min_capacity() {
+        use crate::InvalidCapacity;
+
+        const MIN: usize = min_capacity_for::<ChaCha20Poly1305>();
+        const _: () = assert!(MIN == 17);
+
+        fn new<const CAP: usize>(buffer: crate::ArrayBuffer<CAP>) -> Option<InvalidCapacity> {
+            crate::EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+                KEY.into(),
+                &Default::default(),
+                buffer,
+                Vec::new(),
+            )
+            .err()
+        }
+        assert_eq!(
+            new(crate::ArrayBuffer::<{ MIN - 1 }>::new()),
+            Some(InvalidCapacity)
+        );
+        assert_eq!(new(crate::ArrayBuffer::<MIN>::new()), None);
+    }
+}
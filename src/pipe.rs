@@ -0,0 +1,163 @@
+use alloc::collections::VecDeque;
+use alloc::rc::Rc;
+use core::cell::RefCell;
+
+/// An in-memory pipe: bytes written into one handle can be read back from any clone of it.
+/// Handing one clone to a [`BufWriter`](crate::EncryptBufWriter) as its sink and another to a
+/// [`BufReader`](crate::DecryptBufReader) as its source allows streaming round trips in tests
+/// and fuzzers without collecting the whole ciphertext first.
+///
+/// Reading from an empty pipe returns `0` like the end of a stream, and the reader needs to see
+/// the header of the following chunk before it decrypts a chunk. So a chunk can only be read once
+/// the next one has been written, or the stream has been finalized
+#[derive(Debug, Clone, Default)]
+pub struct Pipe(Rc<RefCell<VecDeque<u8>>>);
+
+impl Pipe {
+    /// Creates a new empty pipe
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of bytes written which haven't been read yet
+    pub fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+
+    /// Whether all bytes written have been read
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().is_empty()
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        let mut queue = self.0.borrow_mut();
+        let amt = buf.len().min(queue.len());
+        for (dst, src) in buf.iter_mut().zip(queue.drain(..amt)) {
+            *dst = src;
+        }
+        amt
+    }
+
+    fn write(&mut self, buf: &[u8]) -> usize {
+        self.0.borrow_mut().extend(buf);
+        buf.len()
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        Ok(self.read(buf))
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        Ok(self.write(buf))
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "core2", not(feature = "std")))]
+impl core2::io::Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> core2::io::Result<usize> {
+        Ok(self.read(buf))
+    }
+}
+
+#[cfg(all(feature = "core2", not(feature = "std")))]
+impl core2::io::Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> core2::io::Result<usize> {
+        Ok(self.write(buf))
+    }
+    fn flush(&mut self) -> core2::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "embedded-io", not(any(feature = "std", feature = "core2"))))]
+impl embedded_io::ErrorType for Pipe {
+    type Error = embedded_io::ErrorKind;
+}
+
+#[cfg(all(feature = "embedded-io", not(any(feature = "std", feature = "core2"))))]
+impl embedded_io::Read for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        Ok(self.read(buf))
+    }
+}
+
+#[cfg(all(feature = "embedded-io", not(any(feature = "std", feature = "core2"))))]
+impl embedded_io::Write for Pipe {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        Ok(self.write(buf))
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(not(any(feature = "std", feature = "core2", feature = "embedded-io")))]
+impl crate::Read for Pipe {
+    type Error = crate::rw::IoError;
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        Ok(self.read(buf))
+    }
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
+        if buf.len() > self.len() {
+            return Err(crate::rw::IoError::UnexpectedEof);
+        }
+        self.read(buf);
+        Ok(())
+    }
+}
+
+#[cfg(not(any(feature = "std", feature = "core2", feature = "embedded-io")))]
+impl crate::Write for Pipe {
+    type Error = crate::rw::IoError;
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        Ok(self.write(buf))
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+        self.write(buf);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{reader_with_buffer, writer_with_buffer};
+    use crate::{ArrayBuffer, FlushMode};
+
+    #[test]
+    fn interleaved_round_trip() {
+        let pipe = Pipe::new();
+        let mut writer = writer_with_buffer(ArrayBuffer::<64>::new(), pipe.clone());
+        let mut reader = reader_with_buffer(ArrayBuffer::<64>::new(), pipe.clone());
+
+        std::io::Write::write_all(&mut writer, b"first").unwrap();
+        writer.flush_with(FlushMode::Chunk).unwrap();
+        std::io::Write::write_all(&mut writer, b"second").unwrap();
+        writer.flush_with(FlushMode::Chunk).unwrap();
+
+        let mut out = [0u8; 5];
+        std::io::Read::read_exact(&mut reader, &mut out).unwrap();
+        assert_eq!(&out, b"first");
+
+        std::io::Write::write_all(&mut writer, b"third").unwrap();
+        writer.flush_with(FlushMode::Finalize).unwrap();
+
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, b"secondthird");
+        assert!(pipe.is_empty());
+    }
+}
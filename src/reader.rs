@@ -1,11 +1,38 @@
+use crate::associated::AssociatedData;
 use crate::buffer::{CappedBuffer, ResizeBuffer};
-use crate::error::{Error, InvalidCapacity};
-use crate::rw::Read;
+use crate::bytes::DecryptBytes;
+use crate::commitment::{COMMITMENT_AAD, COMMITMENT_LEN};
+use crate::error::{
+    ChunkIndexError, Error, InvalidBuffer, InvalidCapacity, KeySliceError, KeyringError,
+    SelfTestError,
+};
+#[cfg(feature = "crc-footer")]
+use crate::footer::{Footer, FOOTER_LEN};
+use crate::framing::{DefaultFraming, Framing};
+use crate::keyring::Keyring;
+#[cfg(feature = "alloc")]
+use crate::rekey::{Rekey, REKEY_AAD};
+use crate::rw::{Progress, Read, WouldBlock};
+use crate::self_test::self_test;
+#[cfg(feature = "alloc")]
+use crate::stage::STAGE_LEN;
+use crate::stage::{nonblocking, Stage};
+use crate::stats::Stats;
+use crate::writer::PADDING_HEADER_LEN;
+use aead::generic_array::typenum::Unsigned;
 use aead::generic_array::ArrayLength;
-use aead::stream::{Decryptor, NewStream, Nonce, NonceSize, StreamPrimitive};
-use aead::{AeadInPlace, Key, NewAead};
+use aead::stream::{NewStream, Nonce, NonceSize, StreamPrimitive};
+use aead::{AeadCore, AeadInPlace, Key, NewAead};
+use core::fmt;
 use core::ops::Sub;
 
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+/// The STREAM primitive and the position of the next chunk in it. The position is tracked here
+/// rather than in an `aead::stream::Decryptor`, so that a chunk can be skipped in recover mode
 pub enum MaybeUninitDecryptor<A, S>
 where
     A: AeadInPlace + NewAead,
@@ -14,7 +41,7 @@ where
     NonceSize<A, S>: ArrayLength<u8>,
 {
     Uninit(A),
-    Decryptor(Decryptor<A, S>),
+    Decryptor(S, S::Counter),
     Empty,
 }
 
@@ -28,10 +55,13 @@ where
     fn uninit(aead: A) -> Self {
         Self::Uninit(aead)
     }
+    fn with_nonce(aead: A, nonce: &Nonce<A, S>) -> Self {
+        Self::Decryptor(S::from_aead(aead, nonce), Default::default())
+    }
     fn init(&mut self, nonce: &Nonce<A, S>) -> Result<(), aead::Error> {
         match core::mem::replace(self, Self::Empty) {
-            Self::Uninit(aead) => *self = Self::Decryptor(Decryptor::from_aead(aead, nonce)),
-            Self::Decryptor(decryptor) => *self = Self::Decryptor(decryptor),
+            Self::Uninit(aead) => *self = Self::with_nonce(aead, nonce),
+            Self::Decryptor(stream, position) => *self = Self::Decryptor(stream, position),
             Self::Empty => return Err(aead::Error),
         }
         Ok(())
@@ -39,25 +69,112 @@ where
     fn is_uninit(&self) -> bool {
         matches!(self, Self::Uninit(_))
     }
-    fn as_mut(&mut self) -> Option<&mut Decryptor<A, S>> {
+    fn is_finished(&self) -> bool {
+        matches!(self, Self::Empty)
+    }
+    fn state(&self) -> &'static str {
+        match self {
+            Self::Uninit(_) => "Init",
+            Self::Decryptor(..) => "Reading",
+            Self::Empty => "Finished",
+        }
+    }
+    /// Decrypts the next chunk in place. The position only advances if it was authenticated,
+    /// unless `skip_failed` is set
+    fn decrypt_next(
+        &mut self,
+        associated_data: &[u8],
+        buffer: &mut dyn aead::Buffer,
+        skip_failed: bool,
+    ) -> Result<(), aead::Error> {
+        let (stream, position) = match self {
+            Self::Decryptor(stream, position) => (stream, position),
+            _ => return Err(aead::Error),
+        };
+        // The maximum position is reserved for the last chunk, like in `aead::stream`
+        if *position == S::COUNTER_MAX {
+            return Err(aead::Error);
+        }
+        let result = stream.decrypt_in_place(*position, false, associated_data, buffer);
+        if result.is_ok() || skip_failed {
+            *position += S::COUNTER_INCR;
+        }
+        result
+    }
+    /// Opens the control chunk announcing a key change, then starts over at the first position of
+    /// the STREAM with the AEAD `next` derives. Nothing changes if the chunk isn't one
+    #[cfg(feature = "alloc")]
+    fn decrypt_rekey(
+        &mut self,
+        buffer: &mut dyn aead::Buffer,
+        nonce: &Nonce<A, S>,
+        next: impl FnOnce() -> A,
+    ) -> Result<(), aead::Error> {
         match self {
-            Self::Decryptor(decryptor) => Some(decryptor),
-            _ => None,
+            Self::Decryptor(stream, position) => {
+                stream.decrypt_in_place(*position, true, REKEY_AAD, buffer)?
+            }
+            _ => return Err(aead::Error),
+        }
+        *self = Self::with_nonce(next(), nonce);
+        Ok(())
+    }
+    /// Moves to the position of the chunk at `index`, as if all chunks before it were decrypted
+    fn seek_chunk(&mut self, index: u32) -> Result<(), aead::Error> {
+        let position = match self {
+            Self::Decryptor(_, position) => position,
+            _ => return Err(aead::Error),
+        };
+        *position = Default::default();
+        for _ in 0..index {
+            if *position == S::COUNTER_MAX {
+                return Err(aead::Error);
+            }
+            *position += S::COUNTER_INCR;
         }
+        Ok(())
     }
-    fn take(&mut self) -> Option<Decryptor<A, S>> {
+    /// Decrypts the last chunk in place, after which nothing more can be decrypted
+    fn decrypt_last(
+        &mut self,
+        associated_data: &[u8],
+        buffer: &mut dyn aead::Buffer,
+    ) -> Result<(), aead::Error> {
         match core::mem::replace(self, Self::Empty) {
-            Self::Decryptor(decryptor) => Some(decryptor),
-            Self::Uninit(_) => None,
-            Self::Empty => None,
+            Self::Decryptor(stream, position) => {
+                stream.decrypt_in_place(position, true, associated_data, buffer)
+            }
+            _ => Err(aead::Error),
         }
     }
 }
 
+/// Where [`DecryptBufReader::try_read`](DecryptBufReader::try_read) is in reading a chunk. The
+/// blocking methods only pick up at chunk boundaries, so they finish a step in progress first
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Step {
+    /// Between chunks
+    Idle,
+    /// Staging the stream header
+    Header,
+    /// Staging the header of the first chunk
+    FirstChunkHeader,
+    /// Reading the ciphertext of a chunk into the buffer
+    Body,
+    /// Staging the header of the chunk after the one in the buffer
+    NextChunkHeader,
+    /// Staging the footer after the last chunk
+    #[cfg(feature = "crc-footer")]
+    Footer,
+}
+
 /// A wrapper around a [`Read`](Read) object and a [`StreamPrimitive`](`StreamPrimitive`)
 /// providing a [`Read`](Read) interface which automatically decrypts the underlying stream when
-/// reading
-pub struct DecryptBufReader<A, B, R, S>
+/// reading. With `std`, any `std::io::Read` can be the inner reader, trait objects such as a
+/// `Box<dyn std::io::Read>` included.
+///
+/// The Reader is `Send` and `Sync` whenever the AEAD, buffer, inner reader and framing are
+pub struct DecryptBufReader<A, B, R, S, F = DefaultFraming>
 where
     A: AeadInPlace + NewAead,
     S: StreamPrimitive<A> + NewStream<A>,
@@ -67,9 +184,39 @@ where
     decryptor: MaybeUninitDecryptor<A, S>,
     buffer: B,
     reader: R,
+    framing: F,
     bytes_to_read: usize,
     read_offset: usize,
     capacity: usize,
+    /// The bytes encryption adds to each chunk, the tag and any stream overhead
+    overhead: usize,
+    chunk_index: u32,
+    started: bool,
+    zeroize_read_buffer: bool,
+    padded: bool,
+    associated: AssociatedData,
+    recover: bool,
+    max_chunks: Option<u32>,
+    /// The largest metadata accepted, if the stream is expected to carry any
+    #[cfg(feature = "alloc")]
+    max_metadata_len: Option<usize>,
+    /// The ciphertext bytes left to read when the length of the stream is known upfront
+    ciphertext_left: Option<u64>,
+    #[cfg(feature = "alloc")]
+    failed_chunks: Vec<u32>,
+    #[cfg(feature = "alloc")]
+    rekey: Option<Rekey<A>>,
+    #[cfg(feature = "alloc")]
+    nonce: Nonce<A, S>,
+    step: Step,
+    stage: Stage,
+    filled: usize,
+    stats: Stats,
+    last_marked: bool,
+    /// Whether the chunk committing to the key is still to be checked
+    commitment_pending: bool,
+    #[cfg(feature = "crc-footer")]
+    footer: Option<Footer>,
 }
 
 impl<A, B, R, S> DecryptBufReader<A, B, R, S>
@@ -80,42 +227,356 @@ where
     A::NonceSize: Sub<S::NonceOverhead>,
     NonceSize<A, S>: ArrayLength<u8>,
 {
-    /// Constructs a new Reader using an AEAD key, buffer and reader
-    pub fn new(key: &Key<A>, mut buffer: B, reader: R) -> Result<Self, InvalidCapacity> {
-        buffer.truncate(0);
-        let capacity = buffer.capacity().min(u32::MAX as usize);
-        if capacity < 1 {
-            Err(InvalidCapacity)
-        } else {
-            Ok(Self {
-                decryptor: MaybeUninitDecryptor::uninit(A::new(key)),
-                reader,
-                buffer,
-                bytes_to_read: 0,
-                read_offset: 0,
-                capacity,
-            })
+    /// Constructs a new Reader using an AEAD key, buffer and reader. Any data already in the
+    /// buffer is discarded, use [`from_aead_with_buffer`](Self::from_aead_with_buffer) to reject
+    /// non-empty buffers instead
+    pub fn new(key: &Key<A>, buffer: B, reader: R) -> Result<Self, InvalidCapacity> {
+        Self::from_aead(A::new(key), buffer, reader)
+    }
+
+    /// Constructs a new Reader like [`new`](Self::new) from a key given as raw bytes. Fails with
+    /// [`KeySliceError::KeyLength`](KeySliceError::KeyLength) if `key` isn't exactly as long as
+    /// the AEAD's keys
+    pub fn from_key_slice(key: &[u8], buffer: B, reader: R) -> Result<Self, KeySliceError> {
+        if key.len() != A::KeySize::to_usize() {
+            return Err(KeySliceError::KeyLength);
+        }
+        Ok(Self::new(Key::<A>::from_slice(key), buffer, reader)?)
+    }
+
+    /// Constructs a new Reader like [`new`](Self::new), but first seals and opens a known test
+    /// vector with the key to check the AEAD round-trips, failing with
+    /// [`SelfTestError::Failed`](SelfTestError::Failed) if it doesn't
+    pub fn new_with_self_test(key: &Key<A>, buffer: B, reader: R) -> Result<Self, SelfTestError> {
+        self_test::<A, S>(key).map_err(|_| SelfTestError::Failed)?;
+        Ok(Self::new(key, buffer, reader)?)
+    }
+
+    /// Constructs a new Reader using an AEAD primitive, buffer and reader. Any data already in
+    /// the buffer is discarded, use [`from_aead_with_buffer`](Self::from_aead_with_buffer) to
+    /// reject non-empty buffers instead
+    pub fn from_aead(aead: A, buffer: B, reader: R) -> Result<Self, InvalidCapacity> {
+        Self::from_aead_with_framing(aead, DefaultFraming, buffer, reader)
+    }
+
+    /// Constructs a new Reader like [`from_aead`](Self::from_aead) from a shared AEAD primitive,
+    /// which is cloned for the Reader, for when one AEAD instance backs many streams
+    pub fn from_aead_ref(aead: &A, buffer: B, reader: R) -> Result<Self, InvalidCapacity>
+    where
+        A: Clone,
+    {
+        Self::from_aead(aead.clone(), buffer, reader)
+    }
+
+    /// Constructs a new Reader using an AEAD primitive, buffer and reader like
+    /// [`from_aead`](Self::from_aead), but fails with
+    /// [`InvalidBuffer::NotEmpty`](InvalidBuffer::NotEmpty) rather than discarding any data which
+    /// is still in the buffer
+    pub fn from_aead_with_buffer(aead: A, buffer: B, reader: R) -> Result<Self, InvalidBuffer> {
+        if !buffer.is_empty() {
+            return Err(InvalidBuffer::NotEmpty);
+        }
+        Ok(Self::from_aead(aead, buffer, reader)?)
+    }
+
+    /// Constructs a new Reader using the AEAD primitive a [`Keyring`](Keyring) provides for
+    /// `key_id`, for keys which never leave a secure element
+    pub fn from_keyring<K>(
+        keyring: &K,
+        key_id: &K::KeyId,
+        buffer: B,
+        reader: R,
+    ) -> Result<Self, KeyringError<K::Error>>
+    where
+        K: Keyring<A>,
+    {
+        let aead = keyring.aead(key_id).map_err(KeyringError::Keyring)?;
+        Ok(Self::from_aead(aead, buffer, reader)?)
+    }
+
+    /// Constructs a new Reader using an AEAD primitive and a nonce which was communicated out of
+    /// band. The stream is expected to start with the first chunk rather than a nonce, as written
+    /// by [`EncryptBufWriter::without_nonce_prefix`](crate::EncryptBufWriter::without_nonce_prefix)
+    pub fn from_aead_with_nonce(
+        aead: A,
+        nonce: &Nonce<A, S>,
+        buffer: B,
+        reader: R,
+    ) -> Result<Self, InvalidCapacity> {
+        #[cfg_attr(not(feature = "alloc"), allow(unused_mut))]
+        let mut reader = Self::from_decryptor(
+            MaybeUninitDecryptor::with_nonce(aead, nonce),
+            DefaultFraming,
+            buffer,
+            reader,
+        )?;
+        #[cfg(feature = "alloc")]
+        {
+            reader.nonce = nonce.clone();
         }
+        Ok(reader)
+    }
+
+    /// Constructs a new Reader for a part of a stream fetched on its own, such as with a range
+    /// request, which starts at the header of the chunk at `chunk_index` rather than with the
+    /// nonce. The part is decrypted as if all chunks before it were read, using the nonce of the
+    /// stream, which has to be known from elsewhere.
+    ///
+    /// A chunk followed by the end of the reader is taken to be the last one, so a part which ends
+    /// before the stream does should include the header of the chunk after it. Reading then
+    /// fails at that chunk, once the plaintext of the chunks before it was read. Fails with
+    /// [`ChunkIndexError::ChunkIndex`](ChunkIndexError::ChunkIndex) if the stream can't hold as
+    /// many chunks
+    pub fn from_aead_at_chunk(
+        aead: A,
+        nonce: &Nonce<A, S>,
+        chunk_index: u32,
+        buffer: B,
+        reader: R,
+    ) -> Result<Self, ChunkIndexError> {
+        let mut reader = Self::from_aead_with_nonce(aead, nonce, buffer, reader)?;
+        reader
+            .decryptor
+            .seek_chunk(chunk_index)
+            .map_err(|_| ChunkIndexError::ChunkIndex)?;
+        reader.chunk_index = chunk_index;
+        Ok(reader)
+    }
+}
+
+impl<A, B, R, S, F> DecryptBufReader<A, B, R, S, F>
+where
+    A: AeadInPlace + NewAead,
+    B: ResizeBuffer + CappedBuffer,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    /// Constructs a new Reader using an AEAD primitive, a custom [`Framing`](Framing), buffer and
+    /// reader
+    ///
+    /// Only as much of the buffer's capacity is used as the framing's chunk headers can describe,
+    /// see [`Framing::max_chunk_len`](Framing::max_chunk_len)
+    pub fn from_aead_with_framing(
+        aead: A,
+        framing: F,
+        buffer: B,
+        reader: R,
+    ) -> Result<Self, InvalidCapacity> {
+        Self::from_decryptor(MaybeUninitDecryptor::uninit(aead), framing, buffer, reader)
     }
 
-    /// Constructs a new Reader using an AEAD primitive, buffer and reader
-    pub fn from_aead(aead: A, mut buffer: B, reader: R) -> Result<Self, InvalidCapacity> {
+    fn from_decryptor(
+        decryptor: MaybeUninitDecryptor<A, S>,
+        framing: F,
+        mut buffer: B,
+        reader: R,
+    ) -> Result<Self, InvalidCapacity> {
         buffer.truncate(0);
-        let capacity = buffer.capacity().min(u32::MAX as usize);
-        if capacity < 1 {
+        let capacity = buffer.capacity().min(framing.max_chunk_len());
+        let overhead = <<A as AeadCore>::TagSize as Unsigned>::to_usize();
+        if capacity <= overhead {
             Err(InvalidCapacity)
         } else {
             Ok(Self {
-                decryptor: MaybeUninitDecryptor::uninit(aead),
+                decryptor,
                 reader,
+                framing,
                 buffer,
                 bytes_to_read: 0,
                 read_offset: 0,
                 capacity,
+                overhead,
+                chunk_index: 0,
+                started: false,
+                zeroize_read_buffer: true,
+                padded: false,
+                associated: AssociatedData::default(),
+                recover: false,
+                max_chunks: None,
+                #[cfg(feature = "alloc")]
+                max_metadata_len: None,
+                ciphertext_left: None,
+                #[cfg(feature = "alloc")]
+                failed_chunks: Vec::new(),
+                #[cfg(feature = "alloc")]
+                rekey: None,
+                #[cfg(feature = "alloc")]
+                nonce: Default::default(),
+                step: Step::Idle,
+                stage: Stage::new(),
+                filled: 0,
+                stats: Stats::default(),
+                last_marked: false,
+                commitment_pending: false,
+                #[cfg(feature = "crc-footer")]
+                footer: None,
             })
         }
     }
 
+    /// Sets whether decrypted bytes are wiped from the internal buffer once they have been copied
+    /// out to the caller (enabled by default). Disabling this trades some safety for throughput
+    /// when the plaintext is not sensitive
+    pub fn with_zeroize_read_buffer(mut self, zeroize: bool) -> Self {
+        self.zeroize_read_buffer = zeroize;
+        self
+    }
+
+    /// Accepts encrypted chunks, tag included, of up to `len` bytes regardless of the capacity
+    /// the buffer started out with, so that streams written with larger chunks than the buffer
+    /// was sized for can be read. The buffer is grown as needed, which only works for buffers
+    /// which can grow, such as a `Vec<u8>` or a [`BoundedVecBuffer`](crate::BoundedVecBuffer)
+    /// with a large enough maximum. `len` caps the memory a bogus chunk length can make the
+    /// Reader allocate, and is clamped to [`Framing::max_chunk_len`](Framing::max_chunk_len).
+    /// Fails if it leaves no room next to the tag and any stream overhead
+    pub fn with_max_chunk_len(mut self, len: usize) -> Result<Self, InvalidCapacity> {
+        let capacity = len.min(self.framing.max_chunk_len());
+        if capacity <= self.overhead {
+            return Err(InvalidCapacity);
+        }
+        self.capacity = capacity;
+        Ok(self)
+    }
+
+    /// The number of bytes encryption adds to each chunk: the AEAD tag, plus whatever was set
+    /// with [`with_stream_overhead`](Self::with_stream_overhead)
+    pub fn chunk_overhead(&self) -> usize {
+        self.overhead
+    }
+
+    /// Accounts for `len` bytes which a custom [`StreamPrimitive`](StreamPrimitive) appends to
+    /// the ciphertext of each chunk on top of the AEAD tag, matching
+    /// [`EncryptBufWriter::with_stream_overhead`](crate::EncryptBufWriter::with_stream_overhead).
+    /// Fails if the buffer is too small to hold any plaintext next to the overhead
+    pub fn with_stream_overhead(mut self, len: usize) -> Result<Self, InvalidCapacity> {
+        let overhead = <<A as AeadCore>::TagSize as Unsigned>::to_usize()
+            .checked_add(len)
+            .ok_or(InvalidCapacity)?;
+        if self.capacity <= overhead {
+            return Err(InvalidCapacity);
+        }
+        self.overhead = overhead;
+        Ok(self)
+    }
+
+    /// Fails with [`Error::Aead`](Error::Aead) before reading more than `max` chunks, including
+    /// the last one, bounding the work spent on a stream made of an enormous number of tiny
+    /// chunks
+    pub fn with_max_chunks(mut self, max: u32) -> Self {
+        self.max_chunks = Some(max);
+        self
+    }
+
+    /// Reads the metadata sent in the clear after the stream header, as written by
+    /// [`EncryptBufWriter::with_metadata`](crate::EncryptBufWriter::with_metadata), and
+    /// authenticates it with the first chunk. Metadata larger than `max_len` bytes is rejected.
+    /// The non-blocking [`try_read`](Self::try_read) doesn't support metadata
+    #[cfg(feature = "alloc")]
+    pub fn with_metadata(mut self, max_len: usize) -> Self {
+        self.max_metadata_len = Some(max_len);
+        self
+    }
+
+    /// Reads exactly `len` bytes of stream, the header included, for streams embedded in an outer
+    /// format which records their length instead of ending them. The chunk which ends at that
+    /// length is decrypted as the last one without looking any further, so the inner reader is
+    /// left right after the stream. Fails with [`Error::Aead`](Error::Aead) if a chunk runs past
+    /// the length or the inner reader ends before it
+    pub fn with_ciphertext_len(mut self, len: u64) -> Self {
+        self.ciphertext_left = Some(len);
+        self
+    }
+
+    /// Strips the padding added by
+    /// [`EncryptBufWriter::with_padding`](crate::EncryptBufWriter::with_padding) from each chunk
+    /// once it has been authenticated
+    pub fn with_padding(mut self) -> Self {
+        self.padded = true;
+        self
+    }
+
+    /// Checks the total length authenticated by
+    /// [`EncryptBufWriter::with_total_length`](crate::EncryptBufWriter::with_total_length) when
+    /// decrypting the last chunk, so the stream is rejected unless every chunk was read
+    pub fn with_total_length(mut self) -> Self {
+        self.associated.total_length.get_or_insert(0);
+        self
+    }
+
+    /// Authenticates the stream header as associated data of the first chunk, as written by
+    /// [`EncryptBufWriter::with_header_aad`](crate::EncryptBufWriter::with_header_aad). The header
+    /// is read in one piece, so this relies on [`Framing::header_len`](Framing::header_len) being
+    /// accurate
+    pub fn with_header_aad(mut self) -> Self {
+        self.associated.header.get_or_insert(Stage::new());
+        self
+    }
+
+    /// Enables or disables recover mode, for salvaging what is left of a damaged stream. In
+    /// recover mode a chunk which fails to authenticate is dropped instead of failing the read,
+    /// and reading carries on with the next chunk. The indices of the dropped chunks are listed by
+    /// [`failed_chunks`](Self::failed_chunks).
+    ///
+    /// This gives up the guarantee that the plaintext is complete: chunks can go missing without
+    /// an error, including the last one, so a truncated stream is not detected either. Only the
+    /// chunks which are handed out are authenticated. A damaged chunk header can't be recovered
+    /// from, as the size of the chunk is unknown
+    #[cfg(feature = "alloc")]
+    pub fn recover_mode(mut self, recover: bool) -> Self {
+        self.recover = recover;
+        self
+    }
+
+    /// The indices of the chunks dropped in [`recover_mode`](Self::recover_mode) so far
+    #[cfg(feature = "alloc")]
+    pub fn failed_chunks(&self) -> &[u32] {
+        &self.failed_chunks
+    }
+
+    /// Follows the key changes made with
+    /// [`EncryptBufWriter::rekey`](crate::EncryptBufWriter::rekey), deriving the AEAD of each new
+    /// epoch with the same KDF or ratchet as the one given to
+    /// [`EncryptBufWriter::with_rekey`](crate::EncryptBufWriter::with_rekey). Without it, a
+    /// stream containing a key change fails to decrypt at the change
+    #[cfg(feature = "alloc")]
+    pub fn with_rekey(mut self, kdf: impl FnMut(u32) -> A + Send + Sync + 'static) -> Self {
+        self.rekey = Some(Rekey::new(kdf));
+        self
+    }
+
+    /// Authenticates the bytes `aad` returns for each chunk as associated data of the chunk, as
+    /// written by
+    /// [`EncryptBufWriter::with_aad_provider`](crate::EncryptBufWriter::with_aad_provider). It
+    /// has to return the same bytes for each index as the closure given to the Writer
+    #[cfg(feature = "alloc")]
+    pub fn with_aad_provider(
+        mut self,
+        aad: impl FnMut(u32) -> Vec<u8> + Send + Sync + 'static,
+    ) -> Self {
+        self.associated.provider = Some(Box::new(aad));
+        self
+    }
+
+    /// Expects the stream to start with the chunk committing to the key written by
+    /// [`EncryptBufWriter::with_key_commitment`](crate::EncryptBufWriter::with_key_commitment),
+    /// and fails with [`Error::Aead`](Error::Aead) unless it is there and was sealed with this
+    /// key, see [Key commitment](crate#key-commitment)
+    pub fn with_key_commitment(mut self) -> Self {
+        self.commitment_pending = true;
+        self
+    }
+
+    /// Expects the footer written by
+    /// [`EncryptBufWriter::with_crc_footer`](crate::EncryptBufWriter::with_crc_footer) after the
+    /// last chunk, and fails if it doesn't match the chunks which were read
+    #[cfg(feature = "crc-footer")]
+    pub fn with_crc_footer(mut self) -> Self {
+        self.footer = Some(Footer::default());
+        self
+    }
+
     /// Gets a reference to the inner reader
     pub fn inner(&self) -> &R {
         &self.reader
@@ -127,7 +588,7 @@ where
     }
 }
 
-impl<A, B, R, S> DecryptBufReader<A, B, R, S>
+impl<A, B, R, S, F> DecryptBufReader<A, B, R, S, F>
 where
     A: AeadInPlace + NewAead,
     B: ResizeBuffer + CappedBuffer,
@@ -135,23 +596,20 @@ where
     S: StreamPrimitive<A> + NewStream<A>,
     A::NonceSize: Sub<S::NonceOverhead>,
     NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
 {
     fn read_chunk_size(&mut self) -> Result<(), Error<R::Error>> {
-        let mut bytes_to_read = [0u8; 4];
-        let mut offset = 0;
-        while offset < 4 {
-            let read = self.reader.read(&mut bytes_to_read[offset..])?;
-            if read == 0 {
-                if offset == 0 {
-                    self.bytes_to_read = 0;
-                    return Ok(());
-                } else {
-                    return Err(Error::Aead);
-                }
-            }
-            offset += read;
-        }
-        let bytes_to_read = u32::from_be_bytes(bytes_to_read) as usize;
+        let ends_marked = self.ends_marked();
+        let bytes_to_read = match self.ciphertext_left {
+            Some(0) => 0,
+            _ => Self::read_next_chunk_header(
+                &mut self.reader,
+                &mut self.framing,
+                &mut self.last_marked,
+                ends_marked,
+            )?,
+        };
+        Self::take_chunk(&mut self.ciphertext_left, &self.framing, bytes_to_read)?;
         if bytes_to_read > self.capacity {
             Err(Error::Aead)
         } else {
@@ -160,101 +618,2336 @@ where
         }
     }
 
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error<R::Error>> {
-        if self.decryptor.is_uninit() {
-            let mut nonce = Nonce::<A, S>::default();
-            self.reader.read_exact(&mut nonce)?;
-            self.decryptor.init(&nonce).map_err(|_| Error::Aead)?;
-            self.read_chunk_size()?;
+    /// Reads the stream header and the size of the first chunk if that hasn't happened yet
+    fn start(&mut self) -> Result<(), Error<R::Error>> {
+        if self.step != Step::Idle {
+            self.resume()?;
         }
-
-        while self.buffer.is_empty() {
+        if !self.started {
+            if self.decryptor.is_uninit() {
+                let mut nonce = Nonce::<A, S>::default();
+                match self.associated.header.as_mut() {
+                    // keep the raw header around to authenticate it with the first chunk
+                    Some(header) => {
+                        let len = self.framing.header_len(nonce.len());
+                        if !header.fill(&mut self.reader, len)? {
+                            return Err(Error::Aead);
+                        }
+                        self.framing
+                            .read_header(&mut header.remaining(), &mut nonce)
+                            .map_err(Error::without_io)?;
+                    }
+                    None => self.framing.read_header(&mut self.reader, &mut nonce)?,
+                }
+                Self::take_ciphertext(
+                    &mut self.ciphertext_left,
+                    self.framing.header_len(nonce.len()),
+                )?;
+                self.decryptor.init(&nonce).map_err(|_| Error::Aead)?;
+                #[cfg(feature = "alloc")]
+                {
+                    self.nonce = nonce;
+                }
+            }
+            #[cfg(feature = "alloc")]
+            self.read_metadata()?;
+            self.read_chunk_size()?;
+            // Even an empty stream has a final chunk, so a stream without any was truncated
             if self.bytes_to_read == 0 {
-                return Ok(0);
+                return Err(Error::Aead);
             }
-            self.buffer
-                .resize_zeroed(self.bytes_to_read)
+            self.started = true;
+        }
+        Ok(())
+    }
+
+    /// Decrypts chunks until there is plaintext available in the buffer or the stream has ended
+    fn fill_buffer(&mut self) -> Result<(), Error<R::Error>> {
+        self.start()?;
+        while self.buffer.is_empty() && self.bytes_to_read != 0 {
+            self.decrypt_chunk(None)?;
+        }
+        Ok(())
+    }
+
+    /// Reads and decrypts the next chunk into `out`, or into the internal buffer if `None`. The
+    /// target buffer is replaced with the chunk's plaintext
+    fn decrypt_chunk(&mut self, out: Option<&mut dyn ResizeBuffer>) -> Result<(), Error<R::Error>> {
+        self.check_max_chunks()?;
+        // only read ahead where the next header fits into the buffer on top of the chunk
+        let read_ahead = out.is_none()
+            && self.ciphertext_left != Some(0)
+            && !self.last_marked
+            && self.bytes_to_read + self.framing.chunk_header_len() <= self.buffer.capacity();
+        let ends_marked = self.ends_marked();
+        let last_marked = &mut self.last_marked;
+        let buffer: &mut dyn ResizeBuffer = match out {
+            Some(out) => out,
+            None => &mut self.buffer,
+        };
+        let next_header = if read_ahead {
+            Self::read_chunk_ahead(
+                &mut self.reader,
+                &mut self.framing,
+                buffer,
+                self.bytes_to_read,
+            )?
+            .map(|(len, last)| {
+                *last_marked = last;
+                len
+            })
+        } else {
+            buffer
+                .resize_for_overwrite(self.bytes_to_read)
                 .map_err(|_| Error::Aead)?;
-            self.reader.read_exact(self.buffer.as_mut())?;
-            self.read_chunk_size()?;
+            self.reader.read_exact(buffer.as_mut())?;
+            None
+        };
+        #[cfg(feature = "crc-footer")]
+        if let Some(footer) = self.footer.as_mut() {
+            footer.update(buffer.as_ref());
+        }
 
-            if self.bytes_to_read == 0 {
-                self.decryptor
-                    .take()
-                    .ok_or(Error::Aead)?
-                    .decrypt_last_in_place(&[], &mut self.buffer)
-                    .map_err(|_| Error::Aead)?;
+        let bytes_to_read = match (next_header, self.ciphertext_left) {
+            (Some(len), _) => len,
+            (None, Some(0)) => 0,
+            (None, _) => Self::read_next_chunk_header(
+                &mut self.reader,
+                &mut self.framing,
+                last_marked,
+                ends_marked,
+            )?,
+        };
+        Self::take_chunk(&mut self.ciphertext_left, &self.framing, bytes_to_read)?;
+        if bytes_to_read > self.capacity {
+            return Err(Error::Aead);
+        }
+        self.bytes_to_read = bytes_to_read;
+        #[cfg(feature = "crc-footer")]
+        if bytes_to_read == 0 {
+            Self::read_footer(&mut self.reader, self.footer.as_ref())?;
+        }
+        let len = buffer.as_ref().len();
+
+        if Self::open_commitment(
+            &mut self.decryptor,
+            buffer,
+            bytes_to_read == 0,
+            &mut self.commitment_pending,
+        )? {
+            self.chunk_opened(true, len, 0);
+            return Ok(());
+        }
+        #[cfg(feature = "alloc")]
+        if Self::open_rekey(
+            &mut self.decryptor,
+            buffer,
+            bytes_to_read == 0,
+            self.rekey.as_mut(),
+            &self.nonce,
+        ) {
+            self.chunk_opened(true, len, 0);
+            return Ok(());
+        }
+        let opened = Self::open_chunk(
+            &mut self.decryptor,
+            buffer,
+            self.chunk_index,
+            bytes_to_read == 0,
+            self.padded,
+            self.overhead,
+            &mut self.associated,
+            self.recover,
+        )?;
+        let plaintext = buffer.as_ref().len();
+        self.chunk_opened(opened, len, plaintext);
+        Ok(())
+    }
+
+    /// Reads a chunk of `len` bytes into `buffer` together with the header of the next chunk, so
+    /// that a source without buffering of its own is mostly read once per chunk rather than
+    /// twice. Returns the length from the next header and whether it marks the last chunk, `0`
+    /// if the source ended right after the chunk, or `None` if the header is still to be read on
+    /// its own. Nothing past the header is read, so the inner reader ends up where reading the
+    /// two separately would leave it
+    fn read_chunk_ahead(
+        reader: &mut R,
+        framing: &mut F,
+        buffer: &mut dyn ResizeBuffer,
+        len: usize,
+    ) -> Result<Option<(usize, bool)>, Error<R::Error>> {
+        let header_len = framing.chunk_header_len();
+        buffer
+            .resize_for_overwrite(len + header_len)
+            .map_err(|_| Error::Aead)?;
+        let mut filled = 0;
+        let mut ended = false;
+        while filled < len + header_len {
+            match reader.read(&mut buffer.as_mut()[filled..]) {
+                Ok(0) => {
+                    ended = true;
+                    break;
+                }
+                Ok(read) => filled += read,
+                // the chunk is complete, so the header is left to be read on its own, which
+                // fails all the same unless the error was a passing one
+                Err(_) if filled == len => break,
+                Err(err) => return Err(err.into()),
+            }
+        }
+        if filled < len {
+            // fails at the end of the source like reading the chunk on its own would
+            reader.read_exact(&mut buffer.as_mut()[filled..len])?;
+            filled = len;
+            ended = false;
+        }
+        let next = match filled - len {
+            0 if ended => Some((0, false)),
+            0 => None,
+            read if read < header_len => return Err(Error::Truncated),
+            _ => Some(
+                framing
+                    .read_marked_chunk_header(&mut &buffer.as_ref()[len..])
+                    .map_err(|_| Error::Aead)?,
+            ),
+        };
+        buffer.truncate(len);
+        Ok(next)
+    }
+
+    /// Decrypts a chunk in place, removing its padding if the stream is padded. The plaintext
+    /// length is accounted for in the associated data. If the chunk fails to authenticate in
+    /// recover mode, it is dropped and `false` is returned
+    #[allow(clippy::too_many_arguments)]
+    fn open_chunk(
+        decryptor: &mut MaybeUninitDecryptor<A, S>,
+        buffer: &mut dyn ResizeBuffer,
+        index: u32,
+        last: bool,
+        padded: bool,
+        overhead: usize,
+        associated: &mut AssociatedData,
+        recover: bool,
+    ) -> Result<bool, Error<R::Error>> {
+        let len = buffer.as_ref().len().saturating_sub(overhead);
+        #[cfg(feature = "alloc")]
+        let opens_metadata = associated.metadata_pending;
+        let result = associated.next(index, len, last, |aad| {
+            if last {
+                decryptor.decrypt_last(aad, buffer)
             } else {
-                self.decryptor
-                    .as_mut()
-                    .ok_or(Error::Aead)?
-                    .decrypt_next_in_place(&[], &mut self.buffer)
-                    .map_err(|_| Error::Aead)?;
+                decryptor.decrypt_next(aad, buffer, recover)
             }
+        })?;
+        if result.is_err() {
+            // never hand out the ciphertext left behind as plaintext
+            buffer.truncate(0);
+            // nor metadata which failed to authenticate
+            #[cfg(feature = "alloc")]
+            if opens_metadata {
+                associated.metadata = None;
+            }
+            return if recover { Ok(false) } else { Err(Error::Aead) };
+        }
+        if padded {
+            Self::strip_padding(buffer)?;
         }
+        Ok(true)
+    }
 
-        let bytes_to_copy = (self.buffer.len() - self.read_offset).min(buf.len());
-        buf[..bytes_to_copy].copy_from_slice(
-            &self.buffer.as_ref()[self.read_offset..self.read_offset + bytes_to_copy],
-        );
-        self.buffer.as_mut()[self.read_offset..self.read_offset + bytes_to_copy].fill(0);
+    /// Opens the chunk committing to the key if it is still to be checked, failing unless it
+    /// holds the zeroes sealed with this key. Returns `false`, leaving the buffer as it was, once
+    /// the commitment has been checked
+    fn open_commitment(
+        decryptor: &mut MaybeUninitDecryptor<A, S>,
+        buffer: &mut dyn ResizeBuffer,
+        last: bool,
+        pending: &mut bool,
+    ) -> Result<bool, Error<R::Error>> {
+        if !*pending {
+            return Ok(false);
+        }
+        // a stream can't end with its commitment
+        if last {
+            return Err(Error::Aead);
+        }
+        decryptor
+            .decrypt_next(COMMITMENT_AAD, buffer, false)
+            .map_err(|_| Error::Aead)?;
+        if buffer.as_ref() != [0; COMMITMENT_LEN] {
+            return Err(Error::Aead);
+        }
+        buffer.truncate(0);
+        *pending = false;
+        Ok(true)
+    }
 
-        if self.buffer.len() == self.read_offset + bytes_to_copy {
-            self.read_offset = 0;
-            self.buffer.truncate(0);
-        } else {
-            self.read_offset += bytes_to_copy;
+    /// Opens the chunk as a key change if it could be one, that is an empty chunk which isn't
+    /// the last one. Returns `false`, leaving the buffer as it was, if the chunk isn't one
+    #[cfg(feature = "alloc")]
+    fn open_rekey(
+        decryptor: &mut MaybeUninitDecryptor<A, S>,
+        buffer: &mut dyn ResizeBuffer,
+        last: bool,
+        rekey: Option<&mut Rekey<A>>,
+        nonce: &Nonce<A, S>,
+    ) -> bool {
+        let tag_len = <<A as AeadCore>::TagSize as Unsigned>::to_usize();
+        let rekey = match rekey {
+            Some(rekey) if !last && buffer.as_ref().len() == tag_len && tag_len <= STAGE_LEN => {
+                rekey
+            }
+            _ => return false,
+        };
+        let mut chunk = Stage::new();
+        chunk.push(buffer.as_ref());
+        if decryptor
+            .decrypt_rekey(buffer, nonce, || rekey.next())
+            .is_ok()
+        {
+            return true;
         }
+        // A failed attempt may have left the buffer modified, and the chunk is opened as data
+        // next. The buffer held these bytes before, so restoring them can't fail
+        buffer.truncate(0);
+        let _ = buffer.extend_from_slice(chunk.remaining());
+        false
+    }
 
-        Ok(bytes_to_copy)
+    /// Reads the metadata following the stream header if the stream is expected to carry any
+    #[cfg(feature = "alloc")]
+    fn read_metadata(&mut self) -> Result<(), Error<R::Error>> {
+        let max_len = match self.max_metadata_len {
+            Some(max_len) => max_len,
+            None => return Ok(()),
+        };
+        let mut prefix = [0u8; 4];
+        self.reader.read_exact(&mut prefix)?;
+        let len = u32::from_be_bytes(prefix) as usize;
+        if len > max_len {
+            return Err(Error::Aead);
+        }
+        Self::take_ciphertext(&mut self.ciphertext_left, prefix.len() + len)?;
+        let mut metadata = Vec::new();
+        metadata.resize_zeroed(len).map_err(|_| Error::Aead)?;
+        self.reader.read_exact(&mut metadata)?;
+        self.associated.metadata = Some(metadata);
+        self.associated.metadata_pending = true;
+        Ok(())
     }
-}
 
-#[cfg(feature = "std")]
-impl<A, B, R, S> std::io::Read for DecryptBufReader<A, B, R, S>
-where
-    A: AeadInPlace + NewAead,
-    B: ResizeBuffer + CappedBuffer,
-    R: Read,
-    R::Error: Into<std::io::Error>,
-    S: StreamPrimitive<A> + NewStream<A>,
-    A::NonceSize: Sub<S::NonceOverhead>,
-    NonceSize<A, S>: ArrayLength<u8>,
-{
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        Ok(self.read(buf)?)
+    /// Reads the header of the next chunk, or returns `0` without reading anything once the
+    /// framing marked the chunk before as the last one
+    fn read_next_chunk_header(
+        reader: &mut R,
+        framing: &mut F,
+        last_marked: &mut bool,
+        ends_marked: bool,
+    ) -> Result<usize, Error<R::Error>> {
+        if ends_marked {
+            return Ok(0);
+        }
+        let (len, last) = framing.read_marked_chunk_header(reader)?;
+        *last_marked = last;
+        Ok(len)
     }
-}
 
-#[cfg(not(feature = "std"))]
-impl<A, B, R, S> Read for DecryptBufReader<A, B, R, S>
-where
-    A: AeadInPlace + NewAead,
-    B: ResizeBuffer + CappedBuffer,
-    R: Read,
-    S: StreamPrimitive<A> + NewStream<A>,
-    A::NonceSize: Sub<S::NonceOverhead>,
-    NonceSize<A, S>: ArrayLength<u8>,
-{
-    type Error = Error<R::Error>;
-    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
-        Ok(self.read(buf)?)
+    /// Whether the stream ends after the chunk just read, as the framing marked it as the last
+    /// one. A CRC footer is still preceded by the usual empty chunk header
+    fn ends_marked(&self) -> bool {
+        #[cfg(feature = "crc-footer")]
+        if self.footer.is_some() {
+            return false;
+        }
+        self.last_marked
     }
-    fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<(), Self::Error> {
-        while !buf.is_empty() {
-            match self.read(buf) {
-                Ok(0) => break,
-                Ok(n) => {
-                    let tmp = buf;
-                    buf = &mut tmp[n..];
+
+    /// Takes `len` bytes out of the ciphertext left to read, if its length is known, failing if
+    /// the stream runs past it
+    fn take_ciphertext(left: &mut Option<u64>, len: usize) -> Result<(), Error<R::Error>> {
+        if let Some(left) = left.as_mut() {
+            *left = left.checked_sub(len as u64).ok_or(Error::Aead)?;
+        }
+        Ok(())
+    }
+
+    /// Takes the header just read and the chunk of `bytes_to_read` bytes it announces out of the
+    /// ciphertext left to read. A stream which ends before its known length was truncated
+    fn take_chunk(
+        left: &mut Option<u64>,
+        framing: &F,
+        bytes_to_read: usize,
+    ) -> Result<(), Error<R::Error>> {
+        match (bytes_to_read, *left) {
+            (0, Some(left)) if left > 0 => Err(Error::Aead),
+            (0, _) => Ok(()),
+            _ => Self::take_ciphertext(left, framing.chunk_header_len() + bytes_to_read),
+        }
+    }
+
+    /// Fails if reading another chunk would exceed [`with_max_chunks`](Self::with_max_chunks)
+    fn check_max_chunks(&self) -> Result<(), Error<R::Error>> {
+        match self.max_chunks {
+            Some(max) if self.chunk_index >= max => Err(Error::Aead),
+            _ => Ok(()),
+        }
+    }
+
+    /// Counts a chunk of `len` encrypted bytes which has been read and opened to `plaintext`
+    /// bytes, noting it if it was dropped in recover mode
+    #[cfg_attr(
+        not(any(feature = "alloc", feature = "tracing")),
+        allow(unused_variables)
+    )]
+    fn chunk_opened(&mut self, opened: bool, len: usize, plaintext: usize) {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            chunk_index = self.chunk_index,
+            len,
+            last = self.bytes_to_read == 0,
+            opened,
+            "encrypted chunk read"
+        );
+        #[cfg(feature = "alloc")]
+        if !opened {
+            self.failed_chunks.push(self.chunk_index);
+        }
+        self.chunk_index = self.chunk_index.wrapping_add(1);
+        self.stats.add_chunk(plaintext, len);
+    }
+
+    /// Continues reading from wherever the last call left off until there is plaintext available
+    /// in the buffer or the stream has ended. The inner reader is only read from with single
+    /// reads, with headers staged until they are complete, so all progress is kept when reading
+    /// fails and this can be called again
+    fn resume(&mut self) -> Result<(), Error<R::Error>> {
+        loop {
+            match self.step {
+                Step::Idle => {
+                    if !self.started {
+                        self.step = if self.decryptor.is_uninit() {
+                            Step::Header
+                        } else {
+                            Step::FirstChunkHeader
+                        };
+                    } else if !self.buffer.is_empty() || self.bytes_to_read == 0 {
+                        return Ok(());
+                    } else {
+                        self.check_max_chunks()?;
+                        self.buffer
+                            .resize_for_overwrite(self.bytes_to_read)
+                            .map_err(|_| Error::Aead)?;
+                        self.filled = 0;
+                        self.step = Step::Body;
+                    }
+                }
+                Step::Header => {
+                    let mut nonce = Nonce::<A, S>::default();
+                    let len = self.framing.header_len(nonce.len());
+                    if !self.stage.fill(&mut self.reader, len)? {
+                        return Err(Error::Aead);
+                    }
+                    self.framing
+                        .read_header(&mut self.stage.remaining(), &mut nonce)
+                        .map_err(Error::without_io)?;
+                    if let Some(header) = self.associated.header.as_mut() {
+                        *header = self.stage;
+                    }
+                    self.stage.clear();
+                    Self::take_ciphertext(&mut self.ciphertext_left, len)?;
+                    self.decryptor.init(&nonce).map_err(|_| Error::Aead)?;
+                    #[cfg(feature = "alloc")]
+                    {
+                        self.nonce = nonce;
+                    }
+                    self.step = Step::FirstChunkHeader;
+                }
+                #[cfg(feature = "alloc")]
+                Step::FirstChunkHeader if self.max_metadata_len.is_some() => {
+                    return Err(Error::Aead);
+                }
+                Step::FirstChunkHeader => {
+                    let bytes_to_read = self.read_staged_chunk_size()?;
+                    // Even an empty stream has a final chunk, so a stream without any was
+                    // truncated
+                    if bytes_to_read == 0 {
+                        return Err(Error::Aead);
+                    }
+                    self.bytes_to_read = bytes_to_read;
+                    self.started = true;
+                    self.step = Step::Idle;
+                }
+                Step::Body => {
+                    while self.filled < self.buffer.len() {
+                        match self.reader.read(&mut self.buffer.as_mut()[self.filled..])? {
+                            0 => return Err(Error::Aead),
+                            read => self.filled += read,
+                        }
+                    }
+                    #[cfg(feature = "crc-footer")]
+                    if let Some(footer) = self.footer.as_mut() {
+                        footer.update(self.buffer.as_ref());
+                    }
+                    self.step = Step::NextChunkHeader;
+                }
+                Step::NextChunkHeader => {
+                    self.bytes_to_read = self.read_staged_chunk_size()?;
+                    #[cfg(feature = "crc-footer")]
+                    if self.bytes_to_read == 0 && self.footer.is_some() {
+                        self.step = Step::Footer;
+                        continue;
+                    }
+                    self.open_staged_chunk()?;
+                }
+                #[cfg(feature = "crc-footer")]
+                Step::Footer => {
+                    if !self.stage.fill(&mut self.reader, FOOTER_LEN)? {
+                        return Err(Error::Aead);
+                    }
+                    let mut bytes = [0u8; FOOTER_LEN];
+                    bytes.copy_from_slice(self.stage.remaining());
+                    let matches = match self.footer.as_ref() {
+                        Some(footer) => footer.matches(&bytes),
+                        None => true,
+                    };
+                    if !matches {
+                        return Err(Error::Aead);
+                    }
+                    self.stage.clear();
+                    self.open_staged_chunk()?;
                 }
-                Err(e) => return Err(e),
             }
         }
-        if !buf.is_empty() {
-            Err(Error::Aead)
-        } else {
+    }
+
+    /// Stages the next chunk header and returns the chunk size, or `0` if the stream has ended
+    fn read_staged_chunk_size(&mut self) -> Result<usize, Error<R::Error>> {
+        if self.ciphertext_left == Some(0) {
+            return Ok(0);
+        }
+        if self.ends_marked() {
+            Self::take_chunk(&mut self.ciphertext_left, &self.framing, 0)?;
+            return Ok(0);
+        }
+        let len = self.framing.chunk_header_len();
+        if !self.stage.fill(&mut self.reader, len)? {
+            if !self.stage.is_empty() {
+                return Err(Error::Aead);
+            }
+            Self::take_chunk(&mut self.ciphertext_left, &self.framing, 0)?;
+            return Ok(0);
+        }
+        let (bytes_to_read, last) = self
+            .framing
+            .read_marked_chunk_header(&mut self.stage.remaining())
+            .map_err(|_| Error::Aead)?;
+        self.last_marked = last;
+        self.stage.clear();
+        Self::take_chunk(&mut self.ciphertext_left, &self.framing, bytes_to_read)?;
+        if bytes_to_read > self.capacity {
+            return Err(Error::Aead);
+        }
+        Ok(bytes_to_read)
+    }
+
+    /// Decrypts the chunk read by [`resume`](Self::resume) once the size of the next one is known
+    fn open_staged_chunk(&mut self) -> Result<(), Error<R::Error>> {
+        self.step = Step::Idle;
+        let len = self.buffer.len();
+        if Self::open_commitment(
+            &mut self.decryptor,
+            &mut self.buffer,
+            self.bytes_to_read == 0,
+            &mut self.commitment_pending,
+        )? {
+            self.chunk_opened(true, len, 0);
+            return Ok(());
+        }
+        #[cfg(feature = "alloc")]
+        if Self::open_rekey(
+            &mut self.decryptor,
+            &mut self.buffer,
+            self.bytes_to_read == 0,
+            self.rekey.as_mut(),
+            &self.nonce,
+        ) {
+            self.chunk_opened(true, len, 0);
+            return Ok(());
+        }
+        let opened = Self::open_chunk(
+            &mut self.decryptor,
+            &mut self.buffer,
+            self.chunk_index,
+            self.bytes_to_read == 0,
+            self.padded,
+            self.overhead,
+            &mut self.associated,
+            self.recover,
+        )?;
+        self.chunk_opened(opened, len, self.buffer.len());
+        Ok(())
+    }
+
+    #[cfg(feature = "crc-footer")]
+    fn read_footer(reader: &mut R, footer: Option<&Footer>) -> Result<(), Error<R::Error>> {
+        if let Some(footer) = footer {
+            let mut bytes = [0u8; FOOTER_LEN];
+            reader.read_exact(&mut bytes)?;
+            if !footer.matches(&bytes) {
+                return Err(Error::Aead);
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the footer written by
+    /// [`EncryptBufWriter::with_crc_footer`](crate::EncryptBufWriter::with_crc_footer) against
+    /// the CRC32 and length of all chunks without decrypting them, then seeks back to where the
+    /// stream started. This quickly rejects a corrupted stream before spending time on
+    /// decryption, and must be called before anything is read
+    #[cfg(all(feature = "crc-footer", feature = "std"))]
+    pub fn check_crc_footer(&mut self) -> Result<(), Error<R::Error>>
+    where
+        R: std::io::Seek,
+        R::Error: From<std::io::Error>,
+    {
+        use std::io::SeekFrom;
+        if self.started {
+            return Err(Error::Aead);
+        }
+        let start = self.reader.stream_position().map_err(R::Error::from)?;
+        let result = self.scan_crc_footer();
+        self.buffer.truncate(0);
+        self.reader
+            .seek(SeekFrom::Start(start))
+            .map_err(R::Error::from)?;
+        result
+    }
+
+    #[cfg(all(feature = "crc-footer", feature = "std"))]
+    fn scan_crc_footer(&mut self) -> Result<(), Error<R::Error>> {
+        let mut footer = Footer::default();
+        if self.decryptor.is_uninit() {
+            let mut nonce = Nonce::<A, S>::default();
+            self.framing.read_header(&mut self.reader, &mut nonce)?;
+        }
+        loop {
+            let len = self.framing.read_chunk_header(&mut self.reader)?;
+            if len == 0 {
+                break;
+            }
+            if len > self.capacity {
+                return Err(Error::Aead);
+            }
+            self.buffer
+                .resize_for_overwrite(len)
+                .map_err(|_| Error::Aead)?;
+            self.reader.read_exact(self.buffer.as_mut())?;
+            footer.update(self.buffer.as_ref());
+        }
+        let mut bytes = [0u8; FOOTER_LEN];
+        self.reader.read_exact(&mut bytes)?;
+        if footer.matches(&bytes) {
             Ok(())
+        } else {
+            Err(Error::Aead)
+        }
+    }
+
+    /// Moves the data of a decrypted padded chunk to the start of the buffer and drops the rest
+    fn strip_padding(buffer: &mut dyn ResizeBuffer) -> Result<(), Error<R::Error>> {
+        let data = buffer.as_mut();
+        if data.len() < PADDING_HEADER_LEN {
+            return Err(Error::Aead);
+        }
+        let mut len = [0u8; PADDING_HEADER_LEN];
+        len.copy_from_slice(&data[..PADDING_HEADER_LEN]);
+        let len = u32::from_be_bytes(len) as usize;
+        if len > data.len() - PADDING_HEADER_LEN {
+            return Err(Error::Aead);
+        }
+        data.copy_within(PADDING_HEADER_LEN..PADDING_HEADER_LEN + len, 0);
+        data[len..].fill(0);
+        buffer.truncate(len);
+        Ok(())
+    }
+
+    /// The decrypted bytes in the buffer which haven't been read yet
+    #[inline]
+    fn available(&self) -> &[u8] {
+        if self.step != Step::Idle {
+            return &[];
         }
+        debug_assert!(self.read_offset <= self.buffer.len());
+        self.buffer.as_ref().get(self.read_offset..).unwrap_or(&[])
+    }
+
+    /// Marks `amt` available bytes as read, wiping them if configured to
+    #[inline]
+    fn consume(&mut self, amt: usize) {
+        let end = match self.read_offset.checked_add(amt) {
+            Some(end) if end <= self.buffer.len() => end,
+            _ => {
+                debug_assert!(false, "consumed {} bytes past the available ones", amt);
+                self.buffer.len()
+            }
+        };
+        if self.zeroize_read_buffer {
+            self.buffer.as_mut()[self.read_offset..end].fill(0);
+        }
+
+        if self.buffer.len() == end {
+            self.read_offset = 0;
+            self.buffer.truncate(0);
+        } else {
+            self.read_offset = end;
+        }
+    }
+
+    /// Copies as much of the available plaintext into `buf` as fits, decrypting the next chunk
+    /// first if none is left. Returns as soon as some plaintext was copied rather than waiting on
+    /// further chunks, so that interactive streams aren't held up
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error<R::Error>> {
+        // plaintext is only available once the stream has started and no chunk is in progress,
+        // so there is nothing for `fill_buffer` to do
+        if self.available().is_empty() {
+            self.fill_buffer()?;
+        }
+        Ok(self.copy_available(buf))
+    }
+
+    /// Copies as much of the available plaintext into `buf` as fits, returning how much that was
+    #[inline]
+    fn copy_available(&mut self, buf: &mut [u8]) -> usize {
+        let available = self.available();
+        let bytes_to_copy = available.len().min(buf.len());
+        buf[..bytes_to_copy].copy_from_slice(&available[..bytes_to_copy]);
+        self.consume(bytes_to_copy);
+        bytes_to_copy
+    }
+
+    /// Decrypts the next chunk directly into `out`, replacing its contents, and returns the number
+    /// of plaintext bytes it now holds. This skips the copy out of the internal buffer, so `out`
+    /// must have room for a whole encrypted chunk including its tag. Plaintext already decrypted
+    /// by an earlier call to `read` is handed out first. Returns `0` once the stream has ended
+    pub fn read_into<T: ResizeBuffer>(&mut self, out: &mut T) -> Result<usize, Error<R::Error>> {
+        self.start()?;
+        out.truncate(0);
+        let len = self.available().len();
+        if len > 0 {
+            out.extend_from_slice(self.available())
+                .map_err(|_| Error::Aead)?;
+            self.consume(len);
+            return Ok(len);
+        }
+        while out.is_empty() && self.bytes_to_read != 0 {
+            self.decrypt_chunk(Some(out))?;
+        }
+        Ok(out.len())
+    }
+
+    /// Decrypts and discards the next `n` bytes of plaintext without copying them out, for
+    /// resuming a stream whose beginning was already processed. Every skipped chunk is still
+    /// authenticated. Returns the number of bytes skipped, which is less than `n` only if the
+    /// stream ended first
+    pub fn skip(&mut self, n: u64) -> Result<u64, Error<R::Error>> {
+        let mut skipped = 0;
+        while skipped < n {
+            self.fill_buffer()?;
+            let len = self.available().len();
+            if len == 0 {
+                break;
+            }
+            let amt = (len as u64).min(n - skipped) as usize;
+            self.consume(amt);
+            skipped += amt as u64;
+        }
+        Ok(skipped)
+    }
+
+    /// Returns an iterator over the decrypted bytes, for byte-oriented parsers. The bytes are
+    /// handed out of the internal buffer one chunk at a time
+    pub fn bytes(&mut self) -> DecryptBytes<'_, A, B, R, S, F> {
+        DecryptBytes::new(self)
+    }
+
+    /// Reads the next decrypted byte, or `None` once the stream has ended
+    pub(crate) fn next_byte(&mut self) -> Result<Option<u8>, Error<R::Error>> {
+        if self.available().is_empty() {
+            self.fill_buffer()?;
+        }
+        let byte = self.available().first().copied();
+        if byte.is_some() {
+            self.consume(1);
+        }
+        Ok(byte)
+    }
+
+    /// The number of decrypted bytes which haven't been read yet and can be handed out without
+    /// reading from the inner reader, like the length of the slice `BufRead::fill_buf` would
+    /// return without the possibility of a refill
+    pub fn buffered(&self) -> usize {
+        self.available().len()
+    }
+
+    /// How many bytes the buffer holds, for diagnosing a stream which stalls. Unlike
+    /// [`buffered`](Self::buffered), this includes plaintext which was already read until the
+    /// rest of its chunk is, and a chunk whose ciphertext is still being read
+    pub fn buffer_len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// The most the buffer holds, which is the longest encrypted chunk, tag included, the Reader
+    /// accepts
+    pub fn buffer_capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// The chunks opened so far, with the plaintext they held, whether it has been read yet or
+    /// not
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Whether the whole stream has been read: the last chunk was decrypted and all of its
+    /// plaintext handed out, so any further read returns `0`
+    pub fn is_at_eof(&self) -> bool {
+        self.decryptor.is_finished() && self.available().is_empty()
+    }
+
+    /// The metadata read with [`with_metadata`](Self::with_metadata). The first chunk is
+    /// decrypted if that hasn't happened yet, so the metadata is only returned once it has been
+    /// authenticated. The chunk's plaintext is kept for the following reads
+    #[cfg(feature = "alloc")]
+    pub fn metadata(&mut self) -> Result<&[u8], Error<R::Error>> {
+        if self.max_metadata_len.is_none() {
+            return Err(Error::Aead);
+        }
+        self.fill_buffer()?;
+        match (&self.associated.metadata, self.associated.metadata_pending) {
+            (Some(metadata), false) => Ok(metadata),
+            _ => Err(Error::Aead),
+        }
+    }
+
+    /// Reads decrypted bytes into `buf` without blocking, for driving the Reader from a custom
+    /// event loop over a non-blocking inner reader. Plaintext which is already decrypted is
+    /// handed out right away, otherwise the next chunk is read with single reads and decrypted.
+    /// If the inner reader would block, [`Progress::Pending`](Progress::Pending) is returned and
+    /// the next call picks up where this one left off.
+    ///
+    /// Headers are read in one piece, so this relies on
+    /// [`Framing::header_len`](Framing::header_len) and
+    /// [`Framing::chunk_header_len`](Framing::chunk_header_len) being accurate. The blocking
+    /// methods first finish reading any chunk left in progress, so the two can be mixed
+    pub fn try_read(&mut self, buf: &mut [u8]) -> Result<Progress, Error<R::Error>>
+    where
+        R::Error: WouldBlock,
+    {
+        let chunk_index = self.chunk_index;
+        if self.available().is_empty() && nonblocking(self.resume())?.is_none() {
+            return Ok(if self.chunk_index != chunk_index {
+                Progress::Flushed(0)
+            } else {
+                Progress::Pending
+            });
+        }
+        let available = self.available();
+        let bytes_to_copy = available.len().min(buf.len());
+        buf[..bytes_to_copy].copy_from_slice(&available[..bytes_to_copy]);
+        self.consume(bytes_to_copy);
+        Ok(if self.chunk_index != chunk_index {
+            Progress::Flushed(bytes_to_copy)
+        } else {
+            Progress::Buffered(bytes_to_copy)
+        })
+    }
+
+    /// Authenticates the rest of the stream without handing out any plaintext, for checking
+    /// whether a stream is intact. Every chunk is decrypted in the buffer and discarded, so memory
+    /// use doesn't grow with the stream. Only succeeds if the final chunk was reached and
+    /// authenticated, so a truncated stream is rejected as well
+    pub fn verify(&mut self) -> Result<(), Error<R::Error>> {
+        loop {
+            self.fill_buffer()?;
+            let len = self.available().len();
+            if len == 0 {
+                break;
+            }
+            self.consume(len);
+        }
+        if self.decryptor.is_finished() {
+            Ok(())
+        } else {
+            Err(Error::Aead)
+        }
+    }
+
+    /// Reads up to `buf.len()` bytes of plaintext, then authenticates the rest of the stream like
+    /// [`verify`](Self::verify), discarding its plaintext, for a quick check of a stream which
+    /// also returns its beginning. Returns the number of bytes read into `buf`, which is less
+    /// than its length only if the stream is shorter. On an error, whatever was read into `buf`
+    /// belongs to a stream which isn't intact and must not be used
+    pub fn read_prefix_and_verify(&mut self, buf: &mut [u8]) -> Result<usize, Error<R::Error>> {
+        let mut read = 0;
+        while read < buf.len() {
+            self.fill_buffer()?;
+            match self.copy_available(&mut buf[read..]) {
+                0 => break,
+                copied => read += copied,
+            }
+        }
+        self.verify()?;
+        Ok(read)
+    }
+
+    /// Decrypts the rest of the stream chunk by chunk, handing the plaintext of each to `f`
+    /// right out of the buffer, for processing it without keeping it around, such as feeding it
+    /// to a hasher. The plaintext is wiped once `f` returns, even with an error, unless turned off
+    /// with [`with_zeroize_read_buffer`](Self::with_zeroize_read_buffer). The first error of `f`
+    /// is returned as is, and like [`verify`](Self::verify) a stream which ends before its final
+    /// chunk is rejected
+    pub fn for_each_chunk<E>(mut self, mut f: impl FnMut(&[u8]) -> Result<(), E>) -> Result<(), E>
+    where
+        E: From<Error<R::Error>>,
+    {
+        loop {
+            self.fill_buffer()?;
+            let len = self.available().len();
+            if len == 0 {
+                break;
+            }
+            let result = f(self.available());
+            self.consume(len);
+            result?;
+        }
+        if self.decryptor.is_finished() {
+            Ok(())
+        } else {
+            Err(Error::Aead.into())
+        }
+    }
+
+    /// Decrypts the rest of the stream into a newly allocated `Vec`
+    #[cfg(feature = "alloc")]
+    pub fn decrypt_all(&mut self) -> Result<Vec<u8>, Error<R::Error>> {
+        let mut out = Vec::new();
+        self.decrypt_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    /// Decrypts the rest of the stream into a newly allocated `Vec`. The remaining length of the
+    /// underlying reader is used to reserve enough space upfront, so that the output doesn't need
+    /// to be reallocated while decrypting
+    #[cfg(feature = "std")]
+    pub fn decrypt_all_seekable(&mut self) -> Result<Vec<u8>, Error<R::Error>>
+    where
+        R: std::io::Seek,
+        R::Error: From<std::io::Error>,
+    {
+        let position = self.reader.stream_position().map_err(R::Error::from)?;
+        let end = self
+            .reader
+            .seek(std::io::SeekFrom::End(0))
+            .map_err(R::Error::from)?;
+        self.reader
+            .seek(std::io::SeekFrom::Start(position))
+            .map_err(R::Error::from)?;
+        // a reader seeked past its end has nothing left to reserve for
+        let remaining = end
+            .checked_sub(position)
+            .map_or(0, |len| len as usize)
+            .saturating_add(self.available().len());
+
+        let mut out = Vec::with_capacity(remaining);
+        self.decrypt_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    /// Decrypts only the chunks with an index in `range`, appending their plaintext to `out` and
+    /// returning the number of bytes appended. The inner reader must be where the stream starts,
+    /// or where its first chunk starts if the nonce was given upfront.
+    ///
+    /// Every chunk but the last has to be as long as the first one, as written when the buffer of
+    /// the [`BufWriter`](crate::EncryptBufWriter) is only flushed once full, so that a chunk can be
+    /// found by its index. The Readers of several threads, each with its own clone of the inner
+    /// reader, can then decrypt disjoint ranges at the same time. Chunks past the end of the
+    /// stream are skipped, so only ranges covering every chunk up to the end of the stream
+    /// together detect truncation. Streams with a key change, metadata or extra associated data
+    /// aren't supported and fail with [`Error::Aead`](Error::Aead). A CRC footer isn't checked
+    #[cfg(feature = "std")]
+    pub fn decrypt_chunk_range(
+        mut self,
+        range: core::ops::Range<u32>,
+        out: &mut Vec<u8>,
+    ) -> Result<usize, Error<R::Error>>
+    where
+        R: std::io::Seek,
+        R::Error: From<std::io::Error>,
+    {
+        use std::io::SeekFrom;
+        let unsupported = self.associated.total_length.is_some()
+            || self.associated.header.is_some()
+            || self.max_metadata_len.is_some()
+            || self.rekey.is_some()
+            || self.ciphertext_left.is_some()
+            || self.commitment_pending
+            || self.recover;
+        if self.started || self.step != Step::Idle || unsupported {
+            return Err(Error::Aead);
+        }
+        if self.decryptor.is_uninit() {
+            let mut nonce = Nonce::<A, S>::default();
+            self.framing.read_header(&mut self.reader, &mut nonce)?;
+            self.decryptor.init(&nonce).map_err(|_| Error::Aead)?;
+        }
+
+        let first = self.reader.stream_position().map_err(R::Error::from)?;
+        let chunk_len = self.framing.read_chunk_header(&mut self.reader)?;
+        if chunk_len == 0 || chunk_len > self.capacity {
+            return Err(Error::Aead);
+        }
+        let stride = (self.framing.chunk_header_len() + chunk_len) as u64;
+        self.reader
+            .seek(SeekFrom::Start(first + stride * range.start as u64))
+            .map_err(R::Error::from)?;
+        self.decryptor
+            .seek_chunk(range.start)
+            .map_err(|_| Error::Aead)?;
+
+        let start_len = out.len();
+        let (mut bytes_to_read, mut last) =
+            self.framing.read_marked_chunk_header(&mut self.reader)?;
+        for index in range {
+            if bytes_to_read == 0 {
+                break;
+            }
+            if bytes_to_read > chunk_len {
+                return Err(Error::Aead);
+            }
+            self.buffer
+                .resize_for_overwrite(bytes_to_read)
+                .map_err(|_| Error::Aead)?;
+            self.reader.read_exact(self.buffer.as_mut())?;
+            let next = if last {
+                0
+            } else {
+                let (next, next_last) = self.framing.read_marked_chunk_header(&mut self.reader)?;
+                last = next_last;
+                next
+            };
+            // a short chunk before the last one would move all the chunks after it
+            if next != 0 && bytes_to_read != chunk_len {
+                return Err(Error::Aead);
+            }
+            Self::open_chunk(
+                &mut self.decryptor,
+                &mut self.buffer,
+                index,
+                next == 0,
+                self.padded,
+                self.overhead,
+                &mut self.associated,
+                false,
+            )?;
+            out.extend_from_slice(self.buffer.as_ref());
+            if self.zeroize_read_buffer {
+                self.buffer.as_mut().fill(0);
+            }
+            self.buffer.truncate(0);
+            bytes_to_read = next;
+        }
+        Ok(out.len() - start_len)
+    }
+
+    #[cfg(feature = "alloc")]
+    fn decrypt_to_end(&mut self, out: &mut Vec<u8>) -> Result<(), Error<R::Error>> {
+        loop {
+            self.fill_buffer()?;
+            let available = self.available();
+            if available.is_empty() {
+                return Ok(());
+            }
+            let len = available.len();
+            out.extend_from_slice(available);
+            self.consume(len);
+        }
+    }
+}
+
+impl<A, B, R, S, F> fmt::Debug for DecryptBufReader<A, B, R, S, F>
+where
+    A: AeadInPlace + NewAead,
+    B: ResizeBuffer + CappedBuffer,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DecryptBufReader")
+            .field("state", &self.decryptor.state())
+            .field("chunk_index", &self.chunk_index)
+            .field("capacity", &self.capacity)
+            .field(
+                "buffered",
+                &self.buffer.len().saturating_sub(self.read_offset),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A, B, R, S, F> std::io::Read for DecryptBufReader<A, B, R, S, F>
+where
+    A: AeadInPlace + NewAead,
+    B: ResizeBuffer + CappedBuffer,
+    R: Read,
+    R::Error: Into<std::io::Error>,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        Ok(self.read(buf)?)
+    }
+
+    /// Tells the ways of falling short apart: an encrypted stream which ends part way through a
+    /// chunk fails with [`Error::Truncated`](Error::Truncated), which
+    /// [`Error::from_io_error`](Error::from_io_error) recovers, a chunk which fails to
+    /// authenticate with [`Error::Aead`](Error::Aead), and an intact stream with less plaintext
+    /// than `buf` holds with a plain `UnexpectedEof`
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> std::io::Result<()> {
+        while !buf.is_empty() {
+            match self.read(buf) {
+                Ok(0) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "decrypted stream ended before filling the buffer",
+                    ))
+                }
+                Ok(read) => buf = &mut buf[read..],
+                Err(Error::Io(err)) => {
+                    let err = err.into();
+                    return Err(match err.kind() {
+                        std::io::ErrorKind::UnexpectedEof => Error::<R::Error>::Truncated.into(),
+                        _ => err,
+                    });
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies decrypted bytes straight into the unfilled part of `cursor`, without it having to
+    /// be initialized first
+    #[cfg(all(feature = "read-buf", nightly))]
+    fn read_buf(&mut self, mut cursor: std::io::BorrowedCursor<'_>) -> std::io::Result<()> {
+        self.fill_buffer()?;
+        let available = self.available();
+        let bytes_to_copy = available.len().min(cursor.capacity());
+        cursor.append(&available[..bytes_to_copy]);
+        self.consume(bytes_to_copy);
+        Ok(())
+    }
+}
+
+/// Hands out the decrypted bytes of the internal buffer, so that `lines` and `read_until` work on
+/// the decrypted stream without wrapping the Reader in a `std::io::BufReader` of its own
+#[cfg(feature = "std")]
+impl<A, B, R, S, F> std::io::BufRead for DecryptBufReader<A, B, R, S, F>
+where
+    A: AeadInPlace + NewAead,
+    B: ResizeBuffer + CappedBuffer,
+    R: Read,
+    R::Error: Into<std::io::Error>,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if self.available().is_empty() {
+            self.fill_buffer()?;
+        }
+        Ok(self.available())
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let amt = amt.min(self.available().len());
+        self.consume(amt);
+    }
+}
+
+#[cfg(all(feature = "core2", not(feature = "std")))]
+impl<A, B, R, S, F> core2::io::Read for DecryptBufReader<A, B, R, S, F>
+where
+    A: AeadInPlace + NewAead,
+    B: ResizeBuffer + CappedBuffer,
+    R: Read,
+    R::Error: Into<core2::io::Error>,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> core2::io::Result<usize> {
+        Ok(self.read(buf)?)
+    }
+}
+
+#[cfg(all(feature = "embedded-io", not(any(feature = "std", feature = "core2"))))]
+impl<A, B, R, S, F> embedded_io::ErrorType for DecryptBufReader<A, B, R, S, F>
+where
+    A: AeadInPlace + NewAead,
+    B: ResizeBuffer + CappedBuffer,
+    R: Read,
+    R::Error: fmt::Debug,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    type Error = Error<R::Error>;
+}
+
+#[cfg(all(feature = "embedded-io", not(any(feature = "std", feature = "core2"))))]
+impl<A, B, R, S, F> embedded_io::Read for DecryptBufReader<A, B, R, S, F>
+where
+    A: AeadInPlace + NewAead,
+    B: ResizeBuffer + CappedBuffer,
+    R: Read,
+    R::Error: fmt::Debug,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.read(buf)
+    }
+}
+
+#[cfg(not(any(feature = "std", feature = "core2", feature = "embedded-io")))]
+impl<A, B, R, S, F> Read for DecryptBufReader<A, B, R, S, F>
+where
+    A: AeadInPlace + NewAead,
+    B: ResizeBuffer + CappedBuffer,
+    R: Read,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    type Error = Error<R::Error>;
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        Ok(self.read(buf)?)
+    }
+    fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<(), Self::Error> {
+        while !buf.is_empty() {
+            match self.read(buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    let tmp = buf;
+                    buf = &mut tmp[n..];
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        if !buf.is_empty() {
+            Err(Error::Aead)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::KEY;
+    use crate::{ArrayBuffer, EncryptBE32BufWriter};
+    use aead::stream::{StreamBE32, StreamLE31};
+    use chacha20poly1305::ChaCha20Poly1305;
+    use std::io::{Cursor, Write};
+
+    fn encrypt(plaintext: &[u8]) -> Vec<u8> {
+        encrypt_with_nonce(plaintext, &[0; 7])
+    }
+
+    fn encrypt_with_nonce(plaintext: &[u8], nonce: &[u8; 7]) -> Vec<u8> {
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            nonce.as_ref().into(),
+            ArrayBuffer::<128>::new(),
+            &mut ciphertext,
+        )
+        .unwrap();
+        // a chunk's worth at a time, so that every chunk but the last is full
+        for chunk in plaintext.chunks(112) {
+            writer.write_all(chunk).unwrap();
+        }
+        writer.flush_with(crate::FlushMode::Finalize).unwrap();
+        drop(writer);
+        ciphertext
+    }
+
+    type TestReader<'a> = DecryptBufReader<
+        ChaCha20Poly1305,
+        ArrayBuffer<256>,
+        &'a [u8],
+        StreamBE32<ChaCha20Poly1305>,
+    >;
+
+    fn decrypt_reader(ciphertext: &[u8]) -> TestReader<'_> {
+        DecryptBufReader::new(KEY.into(), ArrayBuffer::<256>::new(), ciphertext).unwrap()
+    }
+
+    #[test]
+    fn zeroize_read_buffer() {
+        let ciphertext = encrypt(b"hello world!");
+
+        let mut reader = decrypt_reader(&ciphertext);
+        let mut out = [0u8; 5];
+        assert_eq!(reader.read(&mut out).unwrap(), 5);
+        assert_eq!(&out, b"hello");
+        assert!(reader.buffer[..5].iter().all(|b| *b == 0));
+        assert_eq!(&reader.buffer[5..], b" world!");
+
+        let mut reader = decrypt_reader(&ciphertext).with_zeroize_read_buffer(false);
+        assert_eq!(reader.read(&mut out).unwrap(), 5);
+        assert_eq!(&reader.buffer[..], b"hello world!");
+    }
+
+    #[test]
+    fn decrypt_all() {
+        let plaintext = [0x42u8; 1000];
+        let ciphertext = encrypt(&plaintext);
+
+        let mut expected = Vec::new();
+        std::io::Read::read_to_end(&mut decrypt_reader(&ciphertext), &mut expected).unwrap();
+        assert_eq!(decrypt_reader(&ciphertext).decrypt_all().unwrap(), expected);
+
+        let mut reader = DecryptBufReader::<ChaCha20Poly1305, _, _, StreamBE32<_>>::new(
+            KEY.into(),
+            ArrayBuffer::<256>::new(),
+            Cursor::new(&ciphertext),
+        )
+        .unwrap();
+        let allocations = crate::test_util::allocations();
+        let out = reader.decrypt_all_seekable().unwrap();
+        // the reservation covered the whole output, so the vec never had to grow
+        assert_eq!(crate::test_util::allocations() - allocations, 1);
+        assert_eq!(out, expected);
+        assert_eq!(out.capacity(), ciphertext.len());
+
+        // without one, it does
+        let allocations = crate::test_util::allocations();
+        decrypt_reader(&ciphertext).decrypt_all().unwrap();
+        assert!(crate::test_util::allocations() - allocations > 1);
+
+        // a reader past its end reserves nothing
+        let mut cursor = Cursor::new(&ciphertext);
+        cursor.set_position(ciphertext.len() as u64 + 10);
+        let mut reader = DecryptBufReader::<ChaCha20Poly1305, _, _, StreamBE32<_>>::new(
+            KEY.into(),
+            ArrayBuffer::<256>::new(),
+            cursor,
+        )
+        .unwrap();
+        assert!(reader.decrypt_all_seekable().is_err());
+    }
+
+    #[test]
+    fn capacity_too_small_for_tag() {
+        let new = |buffer: Vec<u8>| {
+            DecryptBufReader::<ChaCha20Poly1305, _, _, StreamBE32<_>>::new(
+                KEY.into(),
+                buffer,
+                &b""[..],
+            )
+            .err()
+        };
+        assert!(matches!(new(Vec::with_capacity(8)), Some(InvalidCapacity)));
+        assert!(matches!(new(Vec::with_capacity(16)), Some(InvalidCapacity)));
+        assert!(new(Vec::with_capacity(17)).is_none());
+    }
+
+    #[test]
+    fn debug() {
+        let ciphertext = encrypt(b"hello world!");
+        let mut reader = decrypt_reader(&ciphertext);
+        assert!(format!("{:?}", reader).contains("state: \"Init\""));
+        let mut out = [0u8; 5];
+        reader.read(&mut out).unwrap();
+        let debug = format!("{:?}", reader);
+        assert!(debug.contains("state: \"Finished\""));
+        assert!(debug.contains("chunk_index: 1"));
+        assert!(debug.contains("buffered: 7"));
+    }
+
+    #[test]
+    fn out_of_band_nonce() {
+        use aead::NewAead;
+        let aead = ChaCha20Poly1305::new(KEY.into());
+        let nonce = [0x24u8; 7];
+        let plaintext = [0x42u8; 300];
+
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::from_aead(
+            aead.clone(),
+            nonce.as_ref().into(),
+            ArrayBuffer::<128>::new(),
+            &mut ciphertext,
+        )
+        .unwrap()
+        .without_nonce_prefix();
+        for chunk in plaintext.chunks(112) {
+            writer.write_all(chunk).unwrap();
+        }
+        writer.flush_with(crate::FlushMode::Finalize).unwrap();
+        drop(writer);
+        assert_eq!(ciphertext, &encrypt_with_nonce(&plaintext, &nonce)[7..]);
+
+        let mut reader = DecryptBufReader::<_, _, _, StreamBE32<_>>::from_aead_with_nonce(
+            aead,
+            nonce.as_ref().into(),
+            ArrayBuffer::<256>::new(),
+            ciphertext.as_slice(),
+        )
+        .unwrap();
+        assert_eq!(reader.decrypt_all().unwrap(), plaintext);
+    }
+
+    #[test]
+    fn non_empty_buffer() {
+        let aead = ChaCha20Poly1305::new(KEY.into());
+        let ciphertext = encrypt(b"hello world!");
+        let mut buffer = ArrayBuffer::<256>::new();
+        buffer.try_extend_from_slice(b"leftover").unwrap();
+
+        let err = TestReader::from_aead_with_buffer(aead.clone(), buffer.clone(), &ciphertext)
+            .unwrap_err();
+        assert_eq!(err, InvalidBuffer::NotEmpty);
+
+        let mut reader = TestReader::from_aead(aead, buffer, &ciphertext).unwrap();
+        assert!(reader.buffer.is_empty());
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, b"hello world!");
+    }
+
+    #[test]
+    fn verify() {
+        let ciphertext = encrypt(&[0x42u8; 1000]);
+        assert!(decrypt_reader(&ciphertext).verify().is_ok());
+
+        let mut tampered = ciphertext.clone();
+        let len = tampered.len();
+        tampered[len / 2] ^= 1;
+        assert!(decrypt_reader(&tampered).verify().is_err());
+
+        assert!(decrypt_reader(&ciphertext[..len - 1]).verify().is_err());
+        assert!(decrypt_reader(&ciphertext[..7]).verify().is_err());
+    }
+
+    #[cfg(feature = "crc-footer")]
+    #[test]
+    fn crc_footer() {
+        let plaintext = [0x42u8; 1000];
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            &mut ciphertext,
+        )
+        .unwrap()
+        .with_crc_footer();
+        writer.write_all(&plaintext).unwrap();
+        drop(writer);
+
+        let mut reader = DecryptBufReader::<_, _, _, StreamBE32<ChaCha20Poly1305>>::new(
+            KEY.into(),
+            ArrayBuffer::<256>::new(),
+            Cursor::new(ciphertext.as_slice()),
+        )
+        .unwrap()
+        .with_crc_footer();
+        reader.check_crc_footer().unwrap();
+        assert_eq!(reader.decrypt_all().unwrap(), plaintext);
+
+        let mut flipped = ciphertext.clone();
+        let len = flipped.len();
+        flipped[len / 2] ^= 1;
+        let mut reader = DecryptBufReader::<_, _, _, StreamBE32<ChaCha20Poly1305>>::new(
+            KEY.into(),
+            ArrayBuffer::<256>::new(),
+            Cursor::new(flipped.as_slice()),
+        )
+        .unwrap()
+        .with_crc_footer();
+        assert!(reader.check_crc_footer().is_err());
+        assert_eq!(reader.chunk_index, 0);
+
+        let mut flipped = ciphertext;
+        flipped[len - 1] ^= 1;
+        let mut reader = DecryptBufReader::<_, _, _, StreamBE32<ChaCha20Poly1305>>::new(
+            KEY.into(),
+            ArrayBuffer::<256>::new(),
+            flipped.as_slice(),
+        )
+        .unwrap()
+        .with_crc_footer();
+        assert!(reader.decrypt_all().is_err());
+    }
+
+    #[test]
+    fn read_into() {
+        let plaintext = (0..1000u32).map(|i| i as u8).collect::<Vec<_>>();
+        let ciphertext = encrypt(&plaintext);
+
+        let mut expected = Vec::new();
+        std::io::Read::read_to_end(&mut decrypt_reader(&ciphertext), &mut expected).unwrap();
+
+        let mut reader = decrypt_reader(&ciphertext);
+        let mut first = [0u8; 10];
+        std::io::Read::read_exact(&mut reader, &mut first).unwrap();
+        let mut out = first.to_vec();
+        let mut chunk = Vec::with_capacity(128);
+        loop {
+            let len = reader.read_into(&mut chunk).unwrap();
+            if len == 0 {
+                break;
+            }
+            assert_eq!(len, chunk.len());
+            out.extend_from_slice(&chunk);
+        }
+        assert_eq!(out, expected);
+        assert_eq!(out, plaintext);
+    }
+
+    #[test]
+    fn skip() {
+        let plaintext = (0..1000u32).map(|i| i as u8).collect::<Vec<_>>();
+        let ciphertext = encrypt(&plaintext);
+
+        let mut reader = decrypt_reader(&ciphertext);
+        assert_eq!(reader.skip(10).unwrap(), 10);
+        let mut out = [0u8; 5];
+        std::io::Read::read_exact(&mut reader, &mut out).unwrap();
+        assert_eq!(out, plaintext[10..15]);
+
+        // lands in the middle of a later chunk
+        assert_eq!(reader.skip(300).unwrap(), 300);
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, &plaintext[315..]);
+        assert_eq!(reader.skip(10).unwrap(), 0);
+
+        let mut reader = decrypt_reader(&ciphertext);
+        assert_eq!(reader.skip(2000).unwrap(), 1000);
+    }
+
+    #[cfg(all(feature = "read-buf", nightly))]
+    #[test]
+    fn read_buf() {
+        use std::io::{BorrowedBuf, Read};
+        use std::mem::MaybeUninit;
+
+        let plaintext = (0..1000u32).map(|i| i as u8).collect::<Vec<_>>();
+        let ciphertext = encrypt(&plaintext);
+
+        let mut expected = Vec::new();
+        let mut reader = decrypt_reader(&ciphertext);
+        let mut buf = [0u8; 50];
+        loop {
+            let len = reader.read(&mut buf).unwrap();
+            if len == 0 {
+                break;
+            }
+            expected.extend_from_slice(&buf[..len]);
+        }
+
+        let mut out = Vec::new();
+        let mut reader = decrypt_reader(&ciphertext);
+        let mut storage = [MaybeUninit::<u8>::uninit(); 50];
+        loop {
+            let mut buf = BorrowedBuf::from(&mut storage[..]);
+            reader.read_buf(buf.unfilled()).unwrap();
+            if buf.len() == 0 {
+                break;
+            }
+            // reads of 50 bytes cross the 112 byte chunks at different offsets
+            assert!(buf.len() <= 50);
+            out.extend_from_slice(buf.filled());
+        }
+        assert_eq!(out, expected);
+        assert_eq!(out, plaintext);
+    }
+
+    #[test]
+    fn recover_mode() {
+        let plaintext = (0..1000u32).map(|i| i as u8).collect::<Vec<_>>();
+        let mut ciphertext = encrypt(&plaintext);
+
+        // flip a byte inside the fourth chunk and note which plaintext it held
+        let (mut offset, mut start) = (7, 0);
+        for _ in 0..3 {
+            let len = &ciphertext[offset..offset + 4];
+            let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+            offset += 4 + len;
+            start += len - 16;
+        }
+        let len = &ciphertext[offset..offset + 4];
+        let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        assert!(offset + 4 + len < ciphertext.len());
+        ciphertext[offset + 4 + 10] ^= 1;
+        let end = start + len - 16;
+
+        let mut reader = decrypt_reader(&ciphertext);
+        let mut out = Vec::new();
+        assert!(std::io::Read::read_to_end(&mut reader, &mut out).is_err());
+        assert_eq!(out, &plaintext[..start]);
+        // the rejected chunk is not handed out when reading again
+        let mut buf = [0u8; 16];
+        assert!(std::io::Read::read(&mut reader, &mut buf).is_err());
+
+        let mut reader = decrypt_reader(&ciphertext).recover_mode(true);
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, [&plaintext[..start], &plaintext[end..]].concat());
+        assert_eq!(reader.failed_chunks(), [3]);
+    }
+
+    #[test]
+    fn is_at_eof() {
+        let plaintext = [0x42u8; 300];
+        let ciphertext = encrypt(&plaintext);
+
+        let mut reader = decrypt_reader(&ciphertext);
+        assert!(!reader.is_at_eof());
+        let mut out = [0u8; 299];
+        std::io::Read::read_exact(&mut reader, &mut out).unwrap();
+        assert!(!reader.is_at_eof());
+        let mut rest = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut rest).unwrap();
+        assert_eq!(rest, [0x42]);
+        assert!(reader.is_at_eof());
+        assert_eq!(std::io::Read::read(&mut reader, &mut out).unwrap(), 0);
+    }
+
+    #[test]
+    fn rekey() {
+        use aead::NewAead;
+
+        fn kdf(epoch: u32) -> ChaCha20Poly1305 {
+            let mut key = *KEY;
+            key[28..].copy_from_slice(&epoch.to_be_bytes());
+            ChaCha20Poly1305::new(&key.into())
+        }
+
+        let plaintext = (0..600u32).map(|i| i as u8).collect::<Vec<_>>();
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            &mut ciphertext,
+        )
+        .unwrap()
+        .with_rekey(kdf);
+        writer.write_all(&plaintext[..250]).unwrap();
+        writer.rekey().unwrap();
+        let rekeyed_at = writer.inner().len();
+        writer.write_all(&plaintext[250..]).unwrap();
+        writer.flush_with(crate::FlushMode::Finalize).unwrap();
+        drop(writer);
+
+        let reader = |ciphertext: &[u8], rekey: bool| {
+            let reader = decrypt_reader(ciphertext);
+            let mut reader = if rekey {
+                reader.with_rekey(kdf)
+            } else {
+                reader
+            };
+            let mut out = Vec::new();
+            std::io::Read::read_to_end(&mut reader, &mut out).map(|_| out)
+        };
+        assert_eq!(reader(&ciphertext, true).unwrap(), plaintext);
+        assert!(reader(&ciphertext, false).is_err());
+        // cut off right after the key change
+        assert!(reader(&ciphertext[..rekeyed_at], true).is_err());
+
+        let mut reader = decrypt_reader(&ciphertext).with_rekey(kdf);
+        let mut out = Vec::new();
+        let mut buf = [0u8; 64];
+        while !reader.is_at_eof() {
+            match reader.try_read(&mut buf).unwrap() {
+                Progress::Buffered(len) | Progress::Flushed(len) => {
+                    out.extend_from_slice(&buf[..len])
+                }
+                Progress::Pending => unreachable!(),
+            }
+        }
+        assert_eq!(out, plaintext);
+    }
+
+    #[test]
+    fn from_aead_ref() {
+        use aead::NewAead;
+
+        let aead = ChaCha20Poly1305::new(KEY.into());
+        let first = encrypt(b"first stream");
+        let second = encrypt_with_nonce(b"second stream", &[1; 7]);
+
+        let mut readers = [first.as_slice(), second.as_slice()].map(|ciphertext| {
+            TestReader::from_aead_ref(&aead, ArrayBuffer::<256>::new(), ciphertext).unwrap()
+        });
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut readers[0], &mut out).unwrap();
+        assert_eq!(out, b"first stream");
+        out.clear();
+        std::io::Read::read_to_end(&mut readers[1], &mut out).unwrap();
+        assert_eq!(out, b"second stream");
+    }
+
+    #[test]
+    fn max_chunks() {
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            &mut ciphertext,
+        )
+        .unwrap();
+        for _ in 0..10 {
+            writer.write_all(b"tiny").unwrap();
+            writer.flush_with(crate::FlushMode::Chunk).unwrap();
+        }
+        writer.flush_with(crate::FlushMode::Finalize).unwrap();
+        drop(writer);
+
+        // ten chunks of data and an empty last one
+        let mut out = Vec::new();
+        let mut reader = decrypt_reader(&ciphertext).with_max_chunks(11);
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, b"tiny".repeat(10));
+
+        out.clear();
+        let mut reader = decrypt_reader(&ciphertext).with_max_chunks(10);
+        assert!(std::io::Read::read_to_end(&mut reader, &mut out).is_err());
+        assert_eq!(out, b"tiny".repeat(10));
+
+        let mut reader = decrypt_reader(&ciphertext).with_max_chunks(3);
+        let mut buf = [0u8; 64];
+        let mut read = 0;
+        let err = loop {
+            match reader.try_read(&mut buf) {
+                Ok(Progress::Buffered(len) | Progress::Flushed(len)) => read += len,
+                Ok(Progress::Pending) => unreachable!(),
+                Err(err) => break err,
+            }
+        };
+        assert!(matches!(err, Error::Aead));
+        assert_eq!(read, 12);
+    }
+
+    #[test]
+    fn buffered() {
+        let plaintext = [0x42u8; 300];
+        let ciphertext = encrypt(&plaintext);
+
+        let mut reader = decrypt_reader(&ciphertext);
+        assert_eq!(reader.buffered(), 0);
+        let mut out = [0u8; 10];
+        std::io::Read::read_exact(&mut reader, &mut out).unwrap();
+        // the first chunk holds as much plaintext as the writer's buffer minus the tag
+        assert_eq!(reader.buffered(), 112 - 10);
+        let mut out = [0u8; 102];
+        std::io::Read::read_exact(&mut reader, &mut out).unwrap();
+        assert_eq!(reader.buffered(), 0);
+    }
+
+    #[test]
+    fn max_chunk_len() {
+        let plaintext = (0..3000u32).map(|i| i as u8).collect::<Vec<_>>();
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<1024>::new(),
+            &mut ciphertext,
+        )
+        .unwrap();
+        writer.write_all(&plaintext).unwrap();
+        writer.flush_with(crate::FlushMode::Finalize).unwrap();
+        drop(writer);
+
+        let reader = || {
+            crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+                KEY.into(),
+                Vec::with_capacity(128),
+                ciphertext.as_slice(),
+            )
+            .unwrap()
+        };
+        let mut out = Vec::new();
+        assert!(std::io::Read::read_to_end(&mut reader(), &mut out).is_err());
+
+        out.clear();
+        let mut reader = reader().with_max_chunk_len(1024).unwrap();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, plaintext);
+
+        let mut reader = decrypt_reader(&ciphertext)
+            .with_max_chunk_len(1024)
+            .unwrap();
+        assert!(std::io::Read::read_to_end(&mut reader, &mut Vec::new()).is_err());
+        assert!(matches!(
+            decrypt_reader(&ciphertext).with_max_chunk_len(16),
+            Err(InvalidCapacity)
+        ));
+    }
+
+    #[test]
+    fn ciphertext_len() {
+        let plaintext = (0..300u32).map(|i| i as u8).collect::<Vec<_>>();
+        let ciphertext = encrypt(&plaintext);
+        let len = ciphertext.len() as u64;
+        // the stream is followed by more of an outer format rather than ending
+        let region = [ciphertext.as_slice(), b"trailer"].concat();
+
+        let mut rest = region.as_slice();
+        let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<256>::new(),
+            &mut rest,
+        )
+        .unwrap()
+        .with_ciphertext_len(len);
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert!(reader.is_at_eof());
+        drop(reader);
+        assert_eq!(out, plaintext);
+        assert_eq!(rest, b"trailer");
+
+        // the non-blocking path stops at the same place
+        let mut rest = region.as_slice();
+        let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<256>::new(),
+            &mut rest,
+        )
+        .unwrap()
+        .with_ciphertext_len(len);
+        let mut out = Vec::new();
+        let mut buf = [0u8; 64];
+        while !reader.is_at_eof() {
+            match reader.try_read(&mut buf).unwrap() {
+                Progress::Buffered(len) | Progress::Flushed(len) => {
+                    out.extend_from_slice(&buf[..len])
+                }
+                Progress::Pending => unreachable!(),
+            }
+        }
+        drop(reader);
+        assert_eq!(out, plaintext);
+        assert_eq!(rest, b"trailer");
+
+        for wrong_len in [len - 1, len + 3, len + 100] {
+            let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+                KEY.into(),
+                ArrayBuffer::<256>::new(),
+                region.as_slice(),
+            )
+            .unwrap()
+            .with_ciphertext_len(wrong_len);
+            assert!(std::io::Read::read_to_end(&mut reader, &mut Vec::new()).is_err());
+        }
+    }
+
+    #[test]
+    fn metadata() {
+        let plaintext = (0..300u32).map(|i| i as u8).collect::<Vec<_>>();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            Vec::new(),
+        )
+        .unwrap()
+        .with_metadata(&b"recipient: alice"[..]);
+        for part in plaintext.chunks(112) {
+            writer.write_all(part).unwrap();
+        }
+        let ciphertext = writer.into_inner().unwrap();
+        // the metadata is sent in the clear after the nonce
+        assert_eq!(&ciphertext[7..11], 16u32.to_be_bytes());
+        assert_eq!(&ciphertext[11..27], b"recipient: alice");
+
+        let mut reader = decrypt_reader(&ciphertext).with_metadata(64);
+        assert_eq!(reader.metadata().unwrap(), b"recipient: alice");
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, plaintext);
+
+        let mut tampered = ciphertext.clone();
+        tampered[11] ^= 1;
+        let mut reader = decrypt_reader(&tampered).with_metadata(64);
+        assert!(reader.metadata().is_err());
+        let mut reader = decrypt_reader(&tampered).with_metadata(64);
+        assert!(std::io::Read::read_to_end(&mut reader, &mut Vec::new()).is_err());
+
+        // metadata larger than accepted, or not expected at all
+        let mut reader = decrypt_reader(&ciphertext).with_metadata(8);
+        assert!(reader.metadata().is_err());
+        let mut reader = decrypt_reader(&ciphertext);
+        assert!(std::io::Read::read_to_end(&mut reader, &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn decrypt_chunk_range() {
+        let plaintext = (0..1000).map(|i| i as u8).collect::<Vec<_>>();
+        let ciphertext = encrypt(&plaintext);
+        let source = std::io::Cursor::new(ciphertext.as_slice());
+        let decrypt = |source, range| {
+            let mut out = Vec::new();
+            crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+                KEY.into(),
+                ArrayBuffer::<256>::new(),
+                source,
+            )
+            .unwrap()
+            .decrypt_chunk_range(range, &mut out)
+            .map(|_| out)
+        };
+
+        // 112 bytes of plaintext per chunk, so nine chunks with the last one short
+        let (first, second) = std::thread::scope(|scope| {
+            let first = scope.spawn(|| decrypt(source.clone(), 0..5));
+            let second = scope.spawn(|| decrypt(source.clone(), 5..u32::MAX));
+            (first.join().unwrap(), second.join().unwrap())
+        });
+        assert_eq!([first.unwrap(), second.unwrap()].concat(), plaintext);
+        assert_eq!(decrypt(source.clone(), 8..9).unwrap(), &plaintext[896..]);
+        assert!(decrypt(source.clone(), 9..20).unwrap().is_empty());
+
+        // the chunk before a truncation is opened as the last one and fails to authenticate
+        let truncated = &ciphertext[..ciphertext.len() - 124];
+        assert!(decrypt(std::io::Cursor::new(truncated), 6..8).is_err());
+
+        // a short chunk before the last one is rejected, or throws off where the others are
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            &mut ciphertext,
+        )
+        .unwrap();
+        writer.write_all(&plaintext[..50]).unwrap();
+        writer.flush().unwrap();
+        writer.write_all(&plaintext[50..]).unwrap();
+        writer.flush_with(crate::FlushMode::Finalize).unwrap();
+        drop(writer);
+        let source = std::io::Cursor::new(ciphertext.as_slice());
+        assert!(decrypt(source.clone(), 0..9).is_err());
+        assert!(decrypt(source, 1..2).is_err());
+    }
+
+    #[test]
+    fn read_chunk_ahead() {
+        /// Counts the reads made on the ciphertext
+        struct CountingReader<'a> {
+            inner: &'a [u8],
+            reads: usize,
+        }
+
+        impl std::io::Read for CountingReader<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.reads += 1;
+                std::io::Read::read(&mut self.inner, buf)
+            }
+        }
+
+        let plaintext = (0..1000).map(|i| i as u8).collect::<Vec<_>>();
+        let ciphertext = encrypt(&plaintext);
+        let decrypt = |buffer| {
+            let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+                KEY.into(),
+                buffer,
+                CountingReader {
+                    inner: &ciphertext,
+                    reads: 0,
+                },
+            )
+            .unwrap();
+            let mut out = Vec::new();
+            let mut part = [0u8; 100];
+            loop {
+                match reader.read(&mut part).unwrap() {
+                    0 => break,
+                    read => out.extend_from_slice(&part[..read]),
+                }
+            }
+            assert_eq!(out, plaintext);
+            reader.into_inner()
+        };
+
+        // the nonce and the first header, then nine chunks, each read along with the header of
+        // the next one, except for the last one which is followed by the end of the stream
+        let source = decrypt(Vec::with_capacity(256));
+        assert!(source.inner.is_empty());
+        assert_eq!(source.reads, 1 + 1 + 8 + 2);
+        // without room for the header the chunk and the header are read separately
+        let source = decrypt(Vec::with_capacity(128));
+        assert!(source.inner.is_empty());
+        assert_eq!(source.reads, 1 + 1 + 9 * 2);
+
+        // the header of the next chunk is all that is read ahead, so the inner reader is left
+        // right after the stream when its length is known
+        let trailed = [ciphertext.as_slice(), b"trailer"].concat();
+        let mut reader = decrypt_reader(&trailed).with_ciphertext_len(ciphertext.len() as u64);
+        assert_eq!(reader.decrypt_all().unwrap(), plaintext);
+        assert_eq!(reader.into_inner(), b"trailer");
+
+        // a stream cut off inside the header read ahead is truncated
+        let cut = &ciphertext[..7 + 4 + 128 + 2];
+        assert!(matches!(
+            decrypt_reader(cut).decrypt_all(),
+            Err(Error::Truncated)
+        ));
+
+        /// Would block once, at the end of the first chunk
+        struct Hiccup<'a> {
+            inner: &'a [u8],
+            at: usize,
+        }
+
+        impl std::io::Read for Hiccup<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                match self.at {
+                    0 => {
+                        self.at = usize::MAX;
+                        Err(std::io::ErrorKind::WouldBlock.into())
+                    }
+                    at => {
+                        let len = buf.len().min(at);
+                        let read = std::io::Read::read(&mut self.inner, &mut buf[..len])?;
+                        self.at -= read.min(self.at);
+                        Ok(read)
+                    }
+                }
+            }
+        }
+
+        // the chunk read before blocking on the header after it is kept
+        let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<256>::new(),
+            Hiccup {
+                inner: &ciphertext,
+                at: 7 + 4 + 128,
+            },
+        )
+        .unwrap();
+        assert_eq!(reader.decrypt_all().unwrap(), plaintext);
+    }
+
+    #[test]
+    fn boxed_reader() {
+        let plaintext = b"read through a boxed trait object";
+        let ciphertext = encrypt(plaintext);
+        let source: Box<dyn std::io::Read> = Box::new(std::io::Cursor::new(ciphertext));
+        let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<256>::new(),
+            source,
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, plaintext);
+        assert_eq!(reader.decrypt_all().unwrap(), b"");
+    }
+
+    #[test]
+    fn buf_read_lines() {
+        use std::io::BufRead;
+
+        // lines cross the 112-byte chunks
+        let lines = (0..20)
+            .map(|i| format!("line {} of the decrypted stream", i))
+            .collect::<Vec<_>>();
+        let ciphertext = encrypt(lines.join("\n").as_bytes());
+        let read = decrypt_reader(&ciphertext)
+            .lines()
+            .collect::<std::io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(read, lines);
+
+        let mut reader = decrypt_reader(&ciphertext);
+        let mut first = Vec::new();
+        reader.read_until(b'\n', &mut first).unwrap();
+        assert_eq!(first, b"line 0 of the decrypted stream\n");
+        assert_eq!(
+            reader.buffered(),
+            BufRead::fill_buf(&mut reader).unwrap().len()
+        );
+        BufRead::consume(&mut reader, usize::MAX);
+        assert_eq!(reader.buffered(), 0);
+
+        let mut tampered = ciphertext;
+        tampered[200] ^= 1;
+        let read = decrypt_reader(&tampered)
+            .lines()
+            .collect::<std::io::Result<Vec<_>>>();
+        assert!(read.is_err());
+    }
+
+    #[test]
+    fn read_returns_without_waiting_for_the_next_chunk() {
+        /// Hands out `ready` bytes of `inner`, then would block
+        struct Stalling<'a> {
+            inner: &'a [u8],
+            ready: usize,
+        }
+
+        impl std::io::Read for Stalling<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                if self.ready == 0 && !self.inner.is_empty() {
+                    return Err(std::io::ErrorKind::WouldBlock.into());
+                }
+                let len = buf.len().min(self.ready).min(self.inner.len());
+                buf[..len].copy_from_slice(&self.inner[..len]);
+                self.inner = &self.inner[len..];
+                self.ready -= len;
+                Ok(len)
+            }
+        }
+
+        let plaintext: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        let ciphertext = encrypt(&plaintext);
+        let mut reader = DecryptBufReader::<ChaCha20Poly1305, _, _, StreamBE32<_>>::new(
+            KEY.into(),
+            ArrayBuffer::<256>::new(),
+            Stalling {
+                inner: &ciphertext,
+                ready: 7 + 4 + 128 + 4 + 10,
+            },
+        )
+        .unwrap();
+
+        // the first chunk is returned even though `out` has room for more, and the stall is only
+        // reported by the next read
+        let mut out = [0u8; 512];
+        assert_eq!(std::io::Read::read(&mut reader, &mut out).unwrap(), 112);
+        assert_eq!(&out[..112], &plaintext[..112]);
+        let err = std::io::Read::read(&mut reader, &mut out).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+    }
+
+    #[test]
+    fn for_each_chunk() {
+        let plaintext: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        let ciphertext = encrypt(&plaintext);
+
+        let mut sum = 0u64;
+        let mut chunks = 0;
+        decrypt_reader(&ciphertext)
+            .for_each_chunk(|chunk| {
+                sum += chunk.iter().map(|b| *b as u64).sum::<u64>();
+                chunks += 1;
+                Ok::<_, Error<std::io::Error>>(())
+            })
+            .unwrap();
+        assert_eq!(sum, plaintext.iter().map(|b| *b as u64).sum::<u64>());
+        assert_eq!(chunks, 3);
+
+        // the callback's error stops the stream
+        let err = decrypt_reader(&ciphertext)
+            .for_each_chunk(|_| Err(std::io::Error::other("stop")))
+            .unwrap_err();
+        assert_eq!(err.to_string(), "stop");
+        // and the chunk is wiped all the same
+        let mut storage = [0u8; 256];
+        let reader = DecryptBufReader::<ChaCha20Poly1305, _, _, StreamBE32<_>>::new(
+            KEY.into(),
+            crate::SliceBuffer::new(&mut storage),
+            &ciphertext[..],
+        )
+        .unwrap();
+        let mut seen = Vec::new();
+        let err = reader
+            .for_each_chunk(|chunk| {
+                seen.extend_from_slice(chunk);
+                Err(std::io::Error::other("stop"))
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "stop");
+        assert_eq!(seen, &plaintext[..112]);
+        assert_eq!(storage[..112], [0; 112]);
+
+        // as does a stream cut off after a chunk
+        let result = decrypt_reader(&ciphertext[..7 + 4 + 128])
+            .for_each_chunk(|_| Ok::<_, Error<std::io::Error>>(()));
+        assert!(matches!(result, Err(Error::Aead)));
+    }
+
+    #[test]
+    fn read_exact_errors() {
+        let plaintext = [0x42u8; 300];
+        let ciphertext = encrypt(&plaintext);
+        let mut out = [0u8; 300];
+
+        // cut off part way through the second chunk
+        let mut reader = decrypt_reader(&ciphertext[..200]);
+        let err = std::io::Read::read_exact(&mut reader, &mut out).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+        assert!(matches!(Error::from_io_error(err), Error::Truncated));
+
+        let mut tampered = ciphertext.clone();
+        tampered[20] ^= 1;
+        let mut reader = decrypt_reader(&tampered);
+        let err = std::io::Read::read_exact(&mut reader, &mut out).unwrap_err();
+        assert!(matches!(Error::from_io_error(err), Error::Aead));
+
+        // an intact stream shorter than the buffer
+        let mut reader = decrypt_reader(&ciphertext);
+        let err = std::io::Read::read_exact(&mut reader, &mut [0u8; 301]).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+        assert!(matches!(Error::from_io_error(err), Error::Io(_)));
+    }
+
+    #[test]
+    fn consume_boundaries() {
+        use std::io::BufRead;
+        let plaintext = [0x42; 224];
+        let ciphertext = encrypt(&plaintext);
+
+        let mut reader = decrypt_reader(&ciphertext);
+        assert!(format!("{:?}", reader).contains("buffered: 0"));
+        assert_eq!(reader.fill_buf().unwrap().len(), 112);
+        BufRead::consume(&mut reader, 111);
+        assert!(format!("{:?}", reader).contains("buffered: 1"));
+        // consuming more than is available stops at the end of the chunk
+        BufRead::consume(&mut reader, usize::MAX);
+        assert!(format!("{:?}", reader).contains("buffered: 0"));
+        assert_eq!(reader.fill_buf().unwrap().len(), 112);
+        BufRead::consume(&mut reader, 112);
+        assert!(reader.fill_buf().unwrap().is_empty());
+        BufRead::consume(&mut reader, 1);
+        assert!(format!("{:?}", reader).contains("buffered: 0"));
+    }
+
+    #[test]
+    fn read_from_chunk() {
+        let plaintext: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        let ciphertext = encrypt(&plaintext);
+        // the nonce header, then chunks of 112 bytes of plaintext behind their header
+        let chunk_start = |index: usize| 7 + 132 * index;
+        let from_chunk = |index: u32, part| {
+            TestReader::from_aead_at_chunk(
+                ChaCha20Poly1305::new(KEY.into()),
+                &[0; 7].into(),
+                index,
+                ArrayBuffer::<256>::new(),
+                part,
+            )
+            .unwrap()
+        };
+
+        // a part running to the end of the stream
+        let mut reader = from_chunk(3, &ciphertext[chunk_start(3)..]);
+        assert_eq!(reader.decrypt_all().unwrap(), &plaintext[336..]);
+
+        // chunks 3 and 4 out of the middle, with the header of chunk 5
+        let mut reader = from_chunk(3, &ciphertext[chunk_start(3)..chunk_start(5) + 4]);
+        let mut out = [0u8; 224];
+        reader.read_exact(&mut out).unwrap();
+        assert_eq!(&out[..], &plaintext[336..560]);
+        assert!(reader.read(&mut out).is_err());
+
+        // the index has to match where the part starts
+        let mut reader = from_chunk(2, &ciphertext[chunk_start(3)..]);
+        assert!(reader.decrypt_all().is_err());
+
+        // and fit in the stream
+        let reader = DecryptBufReader::<_, _, _, StreamLE31<_>>::from_aead_at_chunk(
+            ChaCha20Poly1305::new(KEY.into()),
+            &Default::default(),
+            u32::MAX,
+            ArrayBuffer::<256>::new(),
+            &ciphertext[..],
+        );
+        assert_eq!(reader.err(), Some(ChunkIndexError::ChunkIndex));
+        let reader = DecryptBufReader::<_, _, _, StreamBE32<_>>::from_aead_at_chunk(
+            ChaCha20Poly1305::new(KEY.into()),
+            &Default::default(),
+            0,
+            ArrayBuffer::<16>::new(),
+            &ciphertext[..],
+        );
+        assert_eq!(reader.err(), Some(ChunkIndexError::Capacity));
+    }
+
+    #[test]
+    fn read_prefix_and_verify() {
+        let plaintext: Vec<u8> = (0..300).map(|i| i as u8).collect();
+        let ciphertext = encrypt(&plaintext);
+
+        let mut prefix = [0u8; 16];
+        let mut reader = decrypt_reader(&ciphertext);
+        assert_eq!(reader.read_prefix_and_verify(&mut prefix).unwrap(), 16);
+        assert_eq!(&prefix[..], &plaintext[..16]);
+        assert!(reader.is_at_eof());
+
+        // a buffer longer than the stream holds all of it
+        let mut all = [0u8; 400];
+        let mut reader = decrypt_reader(&ciphertext);
+        assert_eq!(reader.read_prefix_and_verify(&mut all).unwrap(), 300);
+        assert_eq!(&all[..300], &plaintext[..]);
+
+        // tampering with the last chunk fails even though the prefix authenticated
+        let mut tampered = ciphertext.clone();
+        *tampered.last_mut().unwrap() ^= 1;
+        let mut reader = decrypt_reader(&tampered);
+        assert!(reader.read_prefix_and_verify(&mut prefix).is_err());
+
+        // as does a stream cut off at a chunk boundary
+        let mut reader = decrypt_reader(&ciphertext[..7 + 2 * 132]);
+        assert!(reader.read_prefix_and_verify(&mut prefix).is_err());
+    }
+
+    #[test]
+    fn buffer_fill_level() {
+        let plaintext = [0x42; 300];
+        let ciphertext = encrypt(&plaintext);
+
+        let mut reader = decrypt_reader(&ciphertext);
+        assert_eq!(reader.buffer_capacity(), 256);
+        assert_eq!(reader.buffer_len(), 0);
+        let mut out = [0u8; 10];
+        reader.read_exact(&mut out).unwrap();
+        // the rest of the chunk's plaintext stays in the buffer along with what was read
+        assert_eq!(reader.buffer_len(), 112);
+        assert_eq!(reader.buffered(), 102);
+        let mut out = [0u8; 102];
+        reader.read_exact(&mut out).unwrap();
+        assert_eq!(reader.buffer_len(), 0);
+        reader.verify().unwrap();
+        assert_eq!(reader.buffer_len(), 0);
+        assert_eq!(reader.buffer_capacity(), 256);
     }
 }
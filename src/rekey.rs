@@ -0,0 +1,27 @@
+use alloc::boxed::Box;
+
+/// The associated data of the control chunk announcing a key change. It is sealed like the last
+/// chunk of a stream, which this tells apart from the real last chunk, so that a stream cut off
+/// right after a key change still fails to authenticate
+pub(crate) const REKEY_AAD: &[u8] = b"aead-io rekey";
+
+/// Derives the AEAD of each epoch from a caller-supplied KDF or ratchet
+pub(crate) struct Rekey<A> {
+    kdf: Box<dyn FnMut(u32) -> A + Send + Sync>,
+    epoch: u32,
+}
+
+impl<A> Rekey<A> {
+    pub(crate) fn new(kdf: impl FnMut(u32) -> A + Send + Sync + 'static) -> Self {
+        Self {
+            kdf: Box::new(kdf),
+            epoch: 0,
+        }
+    }
+
+    /// Moves on to the next epoch and returns its AEAD
+    pub(crate) fn next(&mut self) -> A {
+        self.epoch += 1;
+        (self.kdf)(self.epoch)
+    }
+}
@@ -8,6 +8,14 @@ pub trait Write {
     fn flush(&mut self) -> Result<(), Self::Error>;
     /// Attempts to write an entire buffer into this writer.
     fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Error>;
+    /// Close this output stream, releasing whatever the sink holds on to. Called once by the
+    /// [`BufWriter`](crate::EncryptBufWriter) right after the last chunk of the stream has been
+    /// written, after which nothing more is written, although the writer may still be flushed.
+    /// Does nothing by default, as do the implementations for the `std`, `core2` and
+    /// `embedded-io` traits.
+    fn close(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
 }
 
 #[cfg(feature = "std")]
@@ -16,9 +24,15 @@ where
     T: std::io::Write,
 {
     type Error = std::io::Error;
+    /// Retries writes which were interrupted, as `write_all` does
     #[inline]
     fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
-        std::io::Write::write(self, buf)
+        loop {
+            match std::io::Write::write(self, buf) {
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                result => return result,
+            }
+        }
     }
     #[inline]
     fn flush(&mut self) -> Result<(), Self::Error> {
@@ -30,6 +44,46 @@ where
     }
 }
 
+#[cfg(all(feature = "core2", not(feature = "std")))]
+impl<T> Write for T
+where
+    T: core2::io::Write,
+{
+    type Error = core2::io::Error;
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        core2::io::Write::write(self, buf)
+    }
+    #[inline]
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        core2::io::Write::flush(self)
+    }
+    #[inline]
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+        core2::io::Write::write_all(self, buf)
+    }
+}
+
+#[cfg(all(feature = "embedded-io", not(any(feature = "std", feature = "core2"))))]
+impl<T> Write for T
+where
+    T: embedded_io::Write,
+{
+    type Error = T::Error;
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        embedded_io::Write::write(self, buf)
+    }
+    #[inline]
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        embedded_io::Write::flush(self)
+    }
+    #[inline]
+    fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+        embedded_io::Write::write_all(self, buf)
+    }
+}
+
 /// Emulates [`std::io::Read`](std::io::Read) with a simplified interface for `no_std`
 /// environments.
 pub trait Read {
@@ -46,16 +100,119 @@ where
     T: std::io::Read,
 {
     type Error = std::io::Error;
+    /// Retries reads which were interrupted, as `read_exact` does, so that a signal arriving
+    /// while a chunk is read doesn't fail the stream
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
-        self.read(buf)
+        loop {
+            match std::io::Read::read(self, buf) {
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                result => return result,
+            }
+        }
     }
     fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
         self.read_exact(buf)
     }
 }
 
+#[cfg(all(feature = "core2", not(feature = "std")))]
+impl<T> Read for T
+where
+    T: core2::io::Read,
+{
+    type Error = core2::io::Error;
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        core2::io::Read::read(self, buf)
+    }
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
+        core2::io::Read::read_exact(self, buf)
+    }
+}
+
+#[cfg(all(feature = "embedded-io", not(any(feature = "std", feature = "core2"))))]
+impl<T> Read for T
+where
+    T: embedded_io::Read,
+{
+    type Error = embedded_io::ReadExactError<T::Error>;
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        Ok(embedded_io::Read::read(self, buf)?)
+    }
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
+        embedded_io::Read::read_exact(self, buf)
+    }
+}
+
+/// How far a call to one of the non-blocking `try_*` methods of the
+/// [`BufWriter`](crate::EncryptBufWriter) and [`BufReader`](crate::DecryptBufReader) got
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Progress {
+    /// `n` bytes were moved between the caller and the internal buffer without completing a chunk
+    /// on the inner stream. When reading, `0` bytes into a non-empty buffer means the stream has
+    /// ended
+    Buffered(usize),
+    /// A chunk was completed on the inner stream, written out or read and decrypted, and then
+    /// `n` bytes were moved between the caller and the internal buffer
+    Flushed(usize),
+    /// The inner stream would have blocked before a chunk was completed and no bytes were moved.
+    /// Whatever got through is kept, so the call should be repeated once the inner stream is
+    /// ready
+    Pending,
+}
+
+/// Errors which can tell that a non-blocking operation had to stop because it would have blocked.
+/// The `try_*` methods of the [`BufWriter`](crate::EncryptBufWriter) and
+/// [`BufReader`](crate::DecryptBufReader) report such errors as
+/// [`Progress::Pending`](crate::Progress::Pending) rather than failing
+pub trait WouldBlock {
+    /// Whether the operation would have blocked
+    fn would_block(&self) -> bool;
+}
+
+impl WouldBlock for core::convert::Infallible {
+    fn would_block(&self) -> bool {
+        match *self {}
+    }
+}
+
+#[cfg(feature = "std")]
+impl WouldBlock for std::io::Error {
+    fn would_block(&self) -> bool {
+        self.kind() == std::io::ErrorKind::WouldBlock
+    }
+}
+
+#[cfg(all(feature = "core2", not(feature = "std")))]
+impl WouldBlock for core2::io::Error {
+    fn would_block(&self) -> bool {
+        self.kind() == core2::io::ErrorKind::WouldBlock
+    }
+}
+
+#[cfg(all(feature = "embedded-io", not(any(feature = "std", feature = "core2"))))]
+impl<E> WouldBlock for embedded_io::ReadExactError<E>
+where
+    E: WouldBlock,
+{
+    fn would_block(&self) -> bool {
+        match self {
+            Self::UnexpectedEof => false,
+            Self::Other(err) => err.would_block(),
+        }
+    }
+}
+
+#[cfg(all(feature = "embedded-io", not(any(feature = "std", feature = "core2"))))]
+impl WouldBlock for embedded_io::ErrorKind {
+    fn would_block(&self) -> bool {
+        false
+    }
+}
+
 /// A simple Error for implementations on byte slices in a `no_std` environment
-#[cfg(not(feature = "std"))]
+///
+/// It's available with every set of features, so that code naming it keeps compiling when the
+/// `core2` or `embedded-io` integration replaces the byte slice implementations
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub enum IoError {
     /// Reached the end of the buffer when reading
@@ -64,7 +221,6 @@ pub enum IoError {
     WriteZero,
 }
 
-#[cfg(not(feature = "std"))]
 impl core::fmt::Display for IoError {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
@@ -74,7 +230,13 @@ impl core::fmt::Display for IoError {
     }
 }
 
-#[cfg(not(feature = "std"))]
+impl WouldBlock for IoError {
+    fn would_block(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(not(any(feature = "std", feature = "core2", feature = "embedded-io")))]
 impl Read for &[u8] {
     type Error = IoError;
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
@@ -113,7 +275,7 @@ impl Read for &[u8] {
     }
 }
 
-#[cfg(not(feature = "std"))]
+#[cfg(not(any(feature = "std", feature = "core2", feature = "embedded-io")))]
 impl Write for &mut [u8] {
     type Error = IoError;
     #[inline]
@@ -138,7 +300,10 @@ impl Write for &mut [u8] {
     }
 }
 
-#[cfg(all(not(feature = "std"), feature = "alloc"))]
+#[cfg(all(
+    not(any(feature = "std", feature = "core2", feature = "embedded-io")),
+    feature = "alloc"
+))]
 impl Write for alloc::vec::Vec<u8> {
     type Error = core::convert::Infallible;
     #[inline]
@@ -157,7 +322,7 @@ impl Write for alloc::vec::Vec<u8> {
     }
 }
 
-#[cfg(not(feature = "std"))]
+#[cfg(not(any(feature = "std", feature = "core2", feature = "embedded-io")))]
 impl<R: Read + ?Sized> Read for &mut R {
     type Error = R::Error;
     #[inline]
@@ -169,7 +334,7 @@ impl<R: Read + ?Sized> Read for &mut R {
         (**self).read_exact(buf)
     }
 }
-#[cfg(not(feature = "std"))]
+#[cfg(not(any(feature = "std", feature = "core2", feature = "embedded-io")))]
 impl<W: Write + ?Sized> Write for &mut W {
     type Error = W::Error;
     #[inline]
@@ -184,8 +349,33 @@ impl<W: Write + ?Sized> Write for &mut W {
     fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
         (**self).write_all(buf)
     }
+    #[inline]
+    fn close(&mut self) -> Result<(), Self::Error> {
+        (**self).close()
+    }
 }
-#[cfg(all(not(feature = "std"), feature = "alloc"))]
+/// A boxed [`Read`](Read) trait object with the error type fixed to `E`, for choosing the inner
+/// reader at runtime without `std`. Readers with different error types have to be mapped to a
+/// common one first
+#[cfg(all(
+    not(any(feature = "std", feature = "core2", feature = "embedded-io")),
+    feature = "alloc"
+))]
+pub type DynRead<'a, E> = alloc::boxed::Box<dyn Read<Error = E> + 'a>;
+
+/// A boxed [`Write`](Write) trait object with the error type fixed to `E`, for choosing the inner
+/// writer at runtime without `std`. Writers with different error types have to be mapped to a
+/// common one first
+#[cfg(all(
+    not(any(feature = "std", feature = "core2", feature = "embedded-io")),
+    feature = "alloc"
+))]
+pub type DynWrite<'a, E> = alloc::boxed::Box<dyn Write<Error = E> + 'a>;
+
+#[cfg(all(
+    not(any(feature = "std", feature = "core2", feature = "embedded-io")),
+    feature = "alloc"
+))]
 impl<R: Read + ?Sized> Read for alloc::boxed::Box<R> {
     type Error = R::Error;
     #[inline]
@@ -197,7 +387,10 @@ impl<R: Read + ?Sized> Read for alloc::boxed::Box<R> {
         (**self).read_exact(buf)
     }
 }
-#[cfg(all(not(feature = "std"), feature = "alloc"))]
+#[cfg(all(
+    not(any(feature = "std", feature = "core2", feature = "embedded-io")),
+    feature = "alloc"
+))]
 impl<W: Write + ?Sized> Write for alloc::boxed::Box<W> {
     type Error = W::Error;
     #[inline]
@@ -212,4 +405,436 @@ impl<W: Write + ?Sized> Write for alloc::boxed::Box<W> {
     fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
         (**self).write_all(buf)
     }
+    #[inline]
+    fn close(&mut self) -> Result<(), Self::Error> {
+        (**self).close()
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod std_tests {
+    use crate::test_util::KEY;
+    use crate::{ArrayBuffer, DecryptBE32BufReader, EncryptBE32BufWriter, FlushMode, Progress};
+    use chacha20poly1305::ChaCha20Poly1305;
+    use std::io::{Error, ErrorKind, Read, Write};
+
+    /// Fails every other call with `Interrupted` before passing it on
+    struct Interrupting<T> {
+        inner: T,
+        interrupt: bool,
+    }
+
+    impl<T> Interrupting<T> {
+        fn new(inner: T) -> Self {
+            Self {
+                inner,
+                interrupt: true,
+            }
+        }
+
+        fn interrupt(&mut self) -> std::io::Result<()> {
+            self.interrupt = !self.interrupt;
+            if self.interrupt {
+                Ok(())
+            } else {
+                Err(Error::from(ErrorKind::Interrupted))
+            }
+        }
+    }
+
+    impl<T: Read> Read for Interrupting<T> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.interrupt()?;
+            self.inner.read(buf)
+        }
+    }
+
+    impl<T: Write> Write for Interrupting<T> {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.interrupt()?;
+            self.inner.write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    #[test]
+    fn interrupted() {
+        let plaintext = [0x42u8; 300];
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            Interrupting::new(Vec::new()),
+        )
+        .unwrap();
+        let mut written = 0;
+        while written < plaintext.len() {
+            match writer.try_write(&plaintext[written..]).unwrap() {
+                Progress::Buffered(n) | Progress::Flushed(n) => written += n,
+                Progress::Pending => {}
+            }
+        }
+        while writer.try_flush(FlushMode::Finalize).unwrap() == Progress::Pending {}
+        let ciphertext = writer.into_inner().unwrap().inner;
+
+        let mut reader = DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<256>::new(),
+            Interrupting::new(&ciphertext[..]),
+        )
+        .unwrap();
+        let mut out = [0u8; 300];
+        reader.read_exact(&mut out).unwrap();
+        assert_eq!(out, plaintext);
+        assert_eq!(reader.read(&mut out).unwrap(), 0);
+
+        let mut reader = DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<256>::new(),
+            Interrupting::new(&ciphertext[..]),
+        )
+        .unwrap();
+        let mut len = 0;
+        loop {
+            match reader.try_read(&mut out[len..]).unwrap() {
+                Progress::Buffered(0) => break,
+                Progress::Buffered(read) | Progress::Flushed(read) => len += read,
+                Progress::Pending => {}
+            }
+        }
+        assert_eq!(&out[..len], &plaintext[..]);
+    }
+
+    /// Would block on every other flush
+    struct BlockingFlush {
+        inner: Vec<u8>,
+        block: bool,
+    }
+
+    impl Write for BlockingFlush {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.inner.write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.block = !self.block;
+            if self.block {
+                Err(ErrorKind::WouldBlock.into())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn try_flush_pending_on_inner_flush() {
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            BlockingFlush {
+                inner: Vec::new(),
+                block: false,
+            },
+        )
+        .unwrap();
+        writer.try_write(b"hello").unwrap();
+        // the chunk gets written out, but the inner writer isn't flushed yet
+        assert_eq!(
+            writer.try_flush(FlushMode::Chunk).unwrap(),
+            Progress::Pending
+        );
+        assert_eq!(writer.inner().inner.len(), 7 + 4 + 5 + 16);
+        assert_eq!(
+            writer.try_flush(FlushMode::Chunk).unwrap(),
+            Progress::Buffered(0)
+        );
+    }
+}
+
+#[cfg(all(feature = "core2", feature = "array-buffer", not(feature = "std")))]
+#[cfg(test)]
+mod tests {
+    use crate::test_util::KEY;
+    use crate::{ArrayBuffer, DecryptBE32BufReader, EncryptBE32BufWriter};
+    use aead::NewAead;
+    use chacha20poly1305::ChaCha20Poly1305;
+    use core2::io::{Cursor, Read, Write};
+
+    #[test]
+    fn core2_cursor() {
+        let aead = ChaCha20Poly1305::new(KEY.into());
+        let plaintext = [0x42u8; 300];
+
+        let mut ciphertext = [0u8; 512];
+        let mut cursor = Cursor::new(&mut ciphertext[..]);
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::from_aead(
+            aead.clone(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            &mut cursor,
+        )
+        .unwrap();
+        writer.write_all(&plaintext).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        let len = cursor.position() as usize;
+
+        let mut reader = DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::from_aead(
+            aead,
+            ArrayBuffer::<256>::new(),
+            Cursor::new(&ciphertext[..len]),
+        )
+        .unwrap();
+        let mut out = [0u8; 300];
+        reader.read_exact(&mut out).unwrap();
+        assert_eq!(out, plaintext);
+        assert_eq!(reader.read(&mut out).unwrap(), 0);
+    }
+}
+
+#[cfg(all(
+    feature = "embedded-io",
+    feature = "array-buffer",
+    not(any(feature = "std", feature = "core2"))
+))]
+#[cfg(test)]
+mod embedded_io_tests {
+    use crate::test_util::KEY;
+    use crate::{ArrayBuffer, DecryptBE32BufReader, EncryptBE32BufWriter};
+    use aead::NewAead;
+    use chacha20poly1305::ChaCha20Poly1305;
+    use embedded_io::{ErrorKind, ErrorType, Read, Write};
+
+    /// A loopback serial port which reads back whatever was written to it
+    struct MockUart {
+        data: [u8; 512],
+        written: usize,
+        read: usize,
+    }
+
+    impl ErrorType for MockUart {
+        type Error = ErrorKind;
+    }
+
+    impl Write for MockUart {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            if self.written == self.data.len() && !buf.is_empty() {
+                return Err(ErrorKind::WriteZero);
+            }
+            // emulate a small hardware FIFO
+            let amt = buf.len().min(self.data.len() - self.written).min(16);
+            self.data[self.written..self.written + amt].copy_from_slice(&buf[..amt]);
+            self.written += amt;
+            Ok(amt)
+        }
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    impl Read for MockUart {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            let amt = buf.len().min(self.written - self.read).min(16);
+            buf[..amt].copy_from_slice(&self.data[self.read..self.read + amt]);
+            self.read += amt;
+            Ok(amt)
+        }
+    }
+
+    #[test]
+    fn embedded_io_uart() {
+        let aead = ChaCha20Poly1305::new(KEY.into());
+        let plaintext = [0x42u8; 300];
+        let mut uart = MockUart {
+            data: [0; 512],
+            written: 0,
+            read: 0,
+        };
+
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::from_aead(
+            aead.clone(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            &mut uart,
+        )
+        .unwrap();
+        writer.write_all(&plaintext).unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+
+        let mut reader = DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::from_aead(
+            aead,
+            ArrayBuffer::<256>::new(),
+            &mut uart,
+        )
+        .unwrap();
+        let mut out = [0u8; 300];
+        reader.read_exact(&mut out).unwrap();
+        assert_eq!(out, plaintext);
+        assert_eq!(reader.read(&mut out).unwrap(), 0);
+    }
+}
+
+#[cfg(all(
+    feature = "array-buffer",
+    not(any(feature = "alloc", feature = "core2", feature = "embedded-io"))
+))]
+#[cfg(test)]
+mod no_alloc_tests {
+    use crate::test_util::KEY;
+    use crate::{ArrayBuffer, DecryptBE32BufReader, EncryptBE32BufWriter, FlushMode, Read};
+    use aead::NewAead;
+    use chacha20poly1305::ChaCha20Poly1305;
+
+    #[test]
+    fn slice_round_trip() {
+        let aead = ChaCha20Poly1305::new(KEY.into());
+        let plaintext = [0x42u8; 300];
+
+        let mut ciphertext = [0u8; 512];
+        let mut out = &mut ciphertext[..];
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::from_aead(
+            aead.clone(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            &mut out,
+        )
+        .unwrap();
+        crate::Write::write_all(&mut writer, &plaintext).unwrap();
+        writer.flush_with(FlushMode::Finalize).unwrap();
+        drop(writer);
+        let len = 512 - out.len();
+
+        let mut reader = DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::from_aead(
+            aead,
+            ArrayBuffer::<128>::new(),
+            &ciphertext[..len],
+        )
+        .unwrap();
+        let mut out = [0u8; 300];
+        reader.read_exact(&mut out).unwrap();
+        assert_eq!(out, plaintext);
+        assert_eq!(reader.read(&mut out).unwrap(), 0);
+    }
+}
+
+#[cfg(all(
+    feature = "array-buffer",
+    not(any(feature = "std", feature = "core2", feature = "embedded-io"))
+))]
+#[cfg(test)]
+mod no_std_tests {
+    use super::*;
+    use crate::test_util::KEY;
+    use crate::{ArrayBuffer, EncryptBE32BufWriter, FlushMode};
+    use chacha20poly1305::ChaCha20Poly1305;
+
+    /// A sink which only counts what is done to it, like a peripheral which has to be released
+    #[derive(Default)]
+    struct MockSink {
+        written: usize,
+        closed: usize,
+    }
+
+    impl Write for MockSink {
+        type Error = IoError;
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            if self.closed > 0 {
+                return Err(IoError::WriteZero);
+            }
+            self.written += buf.len();
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+        fn write_all(&mut self, buf: &[u8]) -> Result<(), Self::Error> {
+            self.write(buf).map(|_| ())
+        }
+        fn close(&mut self) -> Result<(), Self::Error> {
+            self.closed += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn close_after_last_chunk() {
+        let mut sink = MockSink::default();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            &mut sink,
+        )
+        .unwrap();
+        writer.write_all(&[0x42; 300]).unwrap();
+        writer.flush_with(FlushMode::Chunk).unwrap();
+        assert_eq!(writer.inner().closed, 0);
+        writer.flush_with(FlushMode::Finalize).unwrap();
+        assert_eq!(writer.inner().closed, 1);
+        writer.finish().unwrap();
+        drop(writer);
+        assert_eq!(sink.closed, 1);
+        assert!(sink.written > 300);
+
+        // closing the Writer itself finalizes the stream and closes the sink
+        let mut sink = MockSink::default();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            &mut sink,
+        )
+        .unwrap();
+        writer.write_all(b"hello").unwrap();
+        Write::close(&mut writer).unwrap();
+        drop(writer);
+        assert_eq!(sink.closed, 1);
+    }
+
+    #[test]
+    fn split_write_all_into_full_part() {
+        let mut storage = [0u8; 4];
+        let mut part = Some(&mut storage[..]);
+        let mut splitter =
+            crate::SplittingWriter::new(7, 64, |_| part.take().ok_or(IoError::WriteZero));
+        assert_eq!(splitter.write_all(&[0; 16]), Err(IoError::WriteZero));
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn dyn_reader_and_writer() {
+        use crate::{DecryptBE32BufReader, DynRead, DynWrite};
+        use alloc::boxed::Box;
+
+        let mut ciphertext = [0u8; 512];
+        let mut out = &mut ciphertext[..];
+        let sink: DynWrite<'_, IoError> = Box::new(&mut out);
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            sink,
+        )
+        .unwrap();
+        writer.write_all(&[0x42; 300]).unwrap();
+        writer.flush_with(FlushMode::Finalize).unwrap();
+        drop(writer);
+        let len = 512 - out.len();
+
+        let source: DynRead<'_, IoError> = Box::new(&ciphertext[..len]);
+        let mut reader = DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<128>::new(),
+            source,
+        )
+        .unwrap();
+        let mut plaintext = [0u8; 300];
+        reader.read_exact(&mut plaintext).unwrap();
+        assert_eq!(plaintext, [0x42; 300]);
+        assert_eq!(reader.read(&mut plaintext).unwrap(), 0);
+    }
 }
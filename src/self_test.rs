@@ -0,0 +1,196 @@
+use aead::generic_array::ArrayLength;
+use aead::stream::{NewStream, Nonce, NonceSize, StreamPrimitive};
+use aead::{AeadInPlace, Buffer, Key, NewAead};
+use core::ops::Sub;
+
+/// The plaintext of the two chunks sealed by the self-test
+const PLAINTEXT: &[u8; 32] = b"aead-io self-test known plaintxt";
+
+/// Room for a chunk of the self-test plaintext and the overhead of any reasonable AEAD
+const SELF_TEST_LEN: usize = 128;
+
+/// A fixed-size buffer, so that the self-test works without `alloc` or `array-buffer`
+struct SelfTestBuffer {
+    bytes: [u8; SELF_TEST_LEN],
+    len: usize,
+}
+
+impl AsRef<[u8]> for SelfTestBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+}
+
+impl AsMut<[u8]> for SelfTestBuffer {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes[..self.len]
+    }
+}
+
+impl Buffer for SelfTestBuffer {
+    fn extend_from_slice(&mut self, other: &[u8]) -> aead::Result<()> {
+        let end = self.len + other.len();
+        if end > SELF_TEST_LEN {
+            return Err(aead::Error);
+        }
+        self.bytes[self.len..end].copy_from_slice(other);
+        self.len = end;
+        Ok(())
+    }
+    fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+}
+
+/// Seals a known plaintext as two chunks of a STREAM under `key` and opens them again, failing
+/// unless the ciphertext differs from the plaintext, a tampered chunk is rejected and the
+/// plaintext comes back unchanged
+pub(crate) fn self_test<A, S>(key: &Key<A>) -> Result<(), aead::Error>
+where
+    A: AeadInPlace + NewAead,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    let nonce = Nonce::<A, S>::default();
+    let stream = S::new(key, &nonce);
+    let (first, last) = PLAINTEXT.split_at(PLAINTEXT.len() / 2);
+    let mut chunks = [first, last].map(|_| SelfTestBuffer {
+        bytes: [0; SELF_TEST_LEN],
+        len: 0,
+    });
+    let positions = [S::Counter::default(), S::COUNTER_INCR];
+
+    for (i, (chunk, plaintext)) in chunks.iter_mut().zip([first, last]).enumerate() {
+        chunk.extend_from_slice(plaintext)?;
+        stream.encrypt_in_place(positions[i], i == 1, b"", chunk)?;
+        if chunk.as_ref().starts_with(plaintext) {
+            return Err(aead::Error);
+        }
+    }
+
+    let stream = S::new(key, &nonce);
+    let mut tampered = SelfTestBuffer {
+        bytes: chunks[1].bytes,
+        len: chunks[1].len,
+    };
+    if let Some(byte) = tampered.as_mut().last_mut() {
+        *byte ^= 1;
+    }
+    if stream
+        .decrypt_in_place(positions[1], true, b"", &mut tampered)
+        .is_ok()
+    {
+        return Err(aead::Error);
+    }
+    for (i, (chunk, plaintext)) in chunks.iter_mut().zip([first, last]).enumerate() {
+        stream.decrypt_in_place(positions[i], i == 1, b"", chunk)?;
+        if chunk.as_ref() != plaintext {
+            return Err(aead::Error);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::KEY;
+    use crate::{ArrayBuffer, DecryptBE32BufReader, EncryptBE32BufWriter, SelfTestError};
+    use aead::consts::{U0, U12, U16, U32};
+    use aead::stream::StreamBE32;
+    use aead::{AeadCore, Nonce as AeadNonce, Tag};
+    use chacha20poly1305::ChaCha20Poly1305;
+
+    /// An AEAD which neither encrypts nor authenticates anything
+    struct BrokenAead;
+
+    impl NewAead for BrokenAead {
+        type KeySize = U32;
+
+        fn new(_: &Key<Self>) -> Self {
+            Self
+        }
+    }
+
+    impl AeadCore for BrokenAead {
+        type NonceSize = U12;
+        type TagSize = U16;
+        type CiphertextOverhead = U0;
+    }
+
+    impl AeadInPlace for BrokenAead {
+        fn encrypt_in_place_detached(
+            &self,
+            _: &AeadNonce<Self>,
+            _: &[u8],
+            _: &mut [u8],
+        ) -> aead::Result<Tag<Self>> {
+            Ok(Tag::<Self>::default())
+        }
+
+        fn decrypt_in_place_detached(
+            &self,
+            _: &AeadNonce<Self>,
+            _: &[u8],
+            _: &mut [u8],
+            _: &Tag<Self>,
+        ) -> aead::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn self_test_passes() {
+        assert!(self_test::<ChaCha20Poly1305, StreamBE32<_>>(KEY.into()).is_ok());
+        assert!(
+            EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new_with_self_test(
+                KEY.into(),
+                &Default::default(),
+                ArrayBuffer::<128>::new(),
+                Vec::new(),
+            )
+            .is_ok()
+        );
+        assert!(
+            DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new_with_self_test(
+                KEY.into(),
+                ArrayBuffer::<128>::new(),
+                &[0u8; 0][..],
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn self_test_fails() {
+        assert!(self_test::<BrokenAead, StreamBE32<_>>(KEY.into()).is_err());
+        assert!(matches!(
+            EncryptBE32BufWriter::<BrokenAead, _, _>::new_with_self_test(
+                KEY.into(),
+                &Default::default(),
+                ArrayBuffer::<128>::new(),
+                Vec::new(),
+            ),
+            Err(SelfTestError::Failed)
+        ));
+        assert!(matches!(
+            DecryptBE32BufReader::<BrokenAead, _, _>::new_with_self_test(
+                KEY.into(),
+                ArrayBuffer::<128>::new(),
+                &[0u8; 0][..],
+            ),
+            Err(SelfTestError::Failed)
+        ));
+        // a buffer too small for a chunk is still reported as such
+        assert!(matches!(
+            DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new_with_self_test(
+                KEY.into(),
+                ArrayBuffer::<16>::new(),
+                &[0u8; 0][..],
+            ),
+            Err(SelfTestError::Capacity)
+        ));
+    }
+}
@@ -0,0 +1,202 @@
+pub use crate::buffer::*;
+use aead::Buffer;
+use core::ops::Deref;
+
+/// A `no_std` compatible Capped Buffer over borrowed memory. Unlike an
+/// [`ArrayBuffer`](crate::ArrayBuffer), which is moved into the Reader or Writer along with its
+/// storage, the storage stays where it is, such as in a `static` set aside for an interrupt
+/// handler, and only a reference to it is held. Construction is a `const fn` and never allocates
+///
+/// The AEAD can't be constructed in a `const` context, as setting up the key runs code of the
+/// AEAD, so the Reader or Writer itself is still constructed at runtime, typically once when the
+/// handler first runs
+#[derive(Debug)]
+pub struct SliceBuffer<'a> {
+    storage: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> SliceBuffer<'a> {
+    /// Creates a new empty buffer using all of `storage`
+    pub const fn new(storage: &'a mut [u8]) -> Self {
+        Self { storage, len: 0 }
+    }
+
+    /// Returns the underlying storage, which still holds whatever was last written to it
+    pub fn into_inner(self) -> &'a mut [u8] {
+        self.storage
+    }
+}
+
+impl Deref for SliceBuffer<'_> {
+    type Target = [u8];
+    fn deref(&self) -> &Self::Target {
+        &self.storage[..self.len]
+    }
+}
+
+impl AsRef<[u8]> for SliceBuffer<'_> {
+    fn as_ref(&self) -> &[u8] {
+        &self.storage[..self.len]
+    }
+}
+
+impl AsMut<[u8]> for SliceBuffer<'_> {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.storage[..self.len]
+    }
+}
+
+impl Buffer for SliceBuffer<'_> {
+    fn extend_from_slice(&mut self, other: &[u8]) -> aead::Result<()> {
+        let end = self.len + other.len();
+        if end > self.storage.len() {
+            return Err(aead::Error);
+        }
+        self.storage[self.len..end].copy_from_slice(other);
+        self.len = end;
+        Ok(())
+    }
+    fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+}
+
+impl CappedBuffer for SliceBuffer<'_> {
+    fn capacity(&self) -> usize {
+        self.storage.len()
+    }
+}
+
+impl ResizeBuffer for SliceBuffer<'_> {
+    fn resize_zeroed(&mut self, new_len: usize) -> Result<(), aead::Error> {
+        if new_len > self.storage.len() {
+            return Err(aead::Error);
+        }
+        if new_len > self.len {
+            self.storage[self.len..new_len].fill(0);
+        }
+        self.len = new_len;
+        Ok(())
+    }
+
+    /// The whole storage is initialized, so nothing needs to be zeroed
+    fn resize_for_overwrite(&mut self, new_len: usize) -> Result<(), aead::Error> {
+        if new_len > self.storage.len() {
+            return Err(aead::Error);
+        }
+        self.len = new_len;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::KEY;
+    use crate::{DecryptBE32BufReader, EncryptBE32BufWriter, FlushMode};
+    use chacha20poly1305::ChaCha20Poly1305;
+    use core::ptr::addr_of_mut;
+
+    static mut READ_STORAGE: [u8; 64] = [0; 64];
+    static mut WRITE_STORAGE: [u8; 64] = [0; 64];
+    static mut CIPHERTEXT: [u8; 256] = [0; 256];
+
+    #[test]
+    fn static_storage() {
+        let plaintext = b"decrypted in an interrupt handler without any heap";
+        // each static is only ever borrowed here
+        let (read_storage, write_storage, ciphertext) = unsafe {
+            (
+                &mut *addr_of_mut!(READ_STORAGE),
+                &mut *addr_of_mut!(WRITE_STORAGE),
+                &mut *addr_of_mut!(CIPHERTEXT),
+            )
+        };
+
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            SliceBuffer::new(write_storage),
+            &mut ciphertext[..],
+        )
+        .unwrap();
+        std::io::Write::write_all(&mut writer, plaintext).unwrap();
+        writer.flush_with(FlushMode::Finalize).unwrap();
+        let remaining = writer.into_inner().unwrap().len();
+        let ciphertext_len = ciphertext.len() - remaining;
+
+        let mut reader = DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            SliceBuffer::new(read_storage),
+            &ciphertext[..ciphertext_len],
+        )
+        .unwrap();
+        let mut decrypted = [0u8; 64];
+        let mut len = 0;
+        loop {
+            match std::io::Read::read(&mut reader, &mut decrypted[len..]).unwrap() {
+                0 => break,
+                read => len += read,
+            }
+        }
+        assert_eq!(&decrypted[..len], plaintext);
+
+        // storage too small for a chunk is rejected like any other buffer
+        let mut small = [0u8; 16];
+        assert!(DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            SliceBuffer::new(&mut small),
+            &[0u8; 0][..],
+        )
+        .is_err());
+    }
+}
+
+#[cfg(all(
+    feature = "array-buffer",
+    not(any(feature = "std", feature = "core2", feature = "embedded-io"))
+))]
+#[cfg(test)]
+mod no_std_tests {
+    use super::*;
+    use crate::test_util::KEY;
+    use crate::{DecryptBE32BufReader, EncryptBE32BufWriter, FlushMode, Read, Write};
+    use chacha20poly1305::ChaCha20Poly1305;
+    use core::ptr::addr_of_mut;
+
+    static mut STORAGE: [u8; 64] = [0; 64];
+
+    #[test]
+    fn static_storage() {
+        let plaintext = [0x42u8; 100];
+        // the static is only ever borrowed here
+        let storage = unsafe { &mut *addr_of_mut!(STORAGE) };
+        let mut ciphertext = [0u8; 256];
+
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            SliceBuffer::new(storage),
+            &mut ciphertext[..],
+        )
+        .unwrap();
+        writer.write_all(&plaintext).unwrap();
+        writer.flush_with(FlushMode::Finalize).unwrap();
+        let ciphertext_len = 256 - writer.into_inner().unwrap().len();
+
+        // the storage is handed on from the writer to the reader
+        let storage = unsafe { &mut *addr_of_mut!(STORAGE) };
+        let mut reader = DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            SliceBuffer::new(storage),
+            &ciphertext[..ciphertext_len],
+        )
+        .unwrap();
+        let mut out = [0u8; 100];
+        reader.read_exact(&mut out).unwrap();
+        assert_eq!(out, plaintext);
+        assert_eq!(reader.read(&mut out).unwrap(), 0);
+    }
+}
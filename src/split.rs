@@ -0,0 +1,298 @@
+use crate::framing::{DefaultFraming, Framing};
+use crate::rw::Write;
+use crate::stage::Stage;
+use core::fmt;
+
+/// A writer which spreads the output of a [`BufWriter`](crate::EncryptBufWriter) across several
+/// parts, such as the parts of a multipart upload. A new part is requested from `next_part` with
+/// its index once the current one holds at least `part_size` bytes, and the finished part is
+/// flushed and dropped.
+///
+/// The splitter follows the framing of the bytes passing through, so parts are only switched at
+/// chunk boundaries: every part but the first starts with a chunk header and holds whole chunks.
+/// Every part but the last is at least `part_size` long, and the concatenation of all parts is
+/// the original stream
+pub struct SplittingWriter<W, G, F = DefaultFraming> {
+    framing: F,
+    next_part: G,
+    part: Option<W>,
+    part_size: usize,
+    parts: usize,
+    written: usize,
+    header: usize,
+    stage: Stage,
+    body: usize,
+    ended: bool,
+}
+
+impl<W, G> SplittingWriter<W, G>
+where
+    W: Write,
+    G: FnMut(usize) -> Result<W, W::Error>,
+{
+    /// Creates a new splitter for a stream using the [`DefaultFraming`](DefaultFraming). The
+    /// stream header is `header_len` bytes long, which is
+    /// [`nonce_len`](crate::nonce_len) for the default framing, or `0` for a stream written
+    /// [`without_nonce_prefix`](crate::EncryptBufWriter::without_nonce_prefix)
+    pub fn new(header_len: usize, part_size: usize, next_part: G) -> Self {
+        Self::with_framing(DefaultFraming, header_len, part_size, next_part)
+    }
+}
+
+impl<W, G, F> SplittingWriter<W, G, F>
+where
+    W: Write,
+    G: FnMut(usize) -> Result<W, W::Error>,
+    F: Framing,
+{
+    /// Creates a new splitter for a stream using a custom [`Framing`](Framing), whose
+    /// [`chunk_header_len`](Framing::chunk_header_len) has to be accurate
+    pub fn with_framing(framing: F, header_len: usize, part_size: usize, next_part: G) -> Self {
+        Self {
+            framing,
+            next_part,
+            part: None,
+            part_size,
+            parts: 0,
+            written: 0,
+            header: header_len,
+            stage: Stage::new(),
+            body: 0,
+            ended: false,
+        }
+    }
+
+    /// The number of parts started so far
+    pub fn parts(&self) -> usize {
+        self.parts
+    }
+
+    /// Flushes the current part and returns it, or `None` if nothing was written
+    pub fn finish(mut self) -> Result<Option<W>, W::Error> {
+        if let Some(part) = self.part.as_mut() {
+            part.flush()?;
+        }
+        Ok(self.part)
+    }
+
+    /// Whether the next byte starts a chunk header
+    fn at_chunk_boundary(&self) -> bool {
+        self.header == 0 && self.body == 0 && self.stage.is_empty() && !self.ended
+    }
+
+    fn next_part(&mut self) -> Result<&mut W, W::Error> {
+        if let Some(mut part) = self.part.take() {
+            part.flush()?;
+        }
+        let part = (self.next_part)(self.parts)?;
+        self.parts += 1;
+        self.written = 0;
+        Ok(self.part.insert(part))
+    }
+
+    /// How much of `len` bytes to pass on at once: only up to the end of the current header or
+    /// chunk, to see where the next one starts
+    fn next_write_len(&self, len: usize) -> usize {
+        if self.header > 0 {
+            len.min(self.header)
+        } else if self.body > 0 {
+            len.min(self.body)
+        } else if self.ended {
+            len
+        } else {
+            len.min(self.framing.chunk_header_len() - self.stage.remaining().len())
+        }
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, W::Error> {
+        self.write_piece(buf, false)
+    }
+
+    /// Passes on the next piece of `buf`, with `write_all` of the part if `all` is set, so that
+    /// a part which can't take any more fails as it would on its own
+    fn write_piece(&mut self, buf: &[u8], all: bool) -> Result<usize, W::Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let amt = self.next_write_len(buf.len());
+        let rotate = self.at_chunk_boundary() && self.written >= self.part_size;
+        let part = match self.part.as_mut() {
+            Some(part) if !rotate => part,
+            _ => self.next_part()?,
+        };
+        let written = if all {
+            part.write_all(&buf[..amt])?;
+            amt
+        } else {
+            part.write(&buf[..amt])?
+        };
+        self.written += written;
+
+        if self.header > 0 {
+            self.header -= written;
+        } else if self.body > 0 {
+            self.body -= written;
+        } else if !self.ended {
+            self.stage.push(&buf[..written]);
+            if self.stage.remaining().len() == self.framing.chunk_header_len() {
+                match self.framing.read_chunk_header(&mut self.stage.remaining()) {
+                    // A chunk header of 0 ends the chunks, anything after it is kept together
+                    Ok(0) | Err(_) => self.ended = true,
+                    Ok(len) => self.body = len,
+                }
+                self.stage.clear();
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> Result<(), W::Error> {
+        match self.part.as_mut() {
+            Some(part) => part.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<W, G, F> fmt::Debug for SplittingWriter<W, G, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SplittingWriter")
+            .field("part_size", &self.part_size)
+            .field("parts", &self.parts)
+            .field("written", &self.written)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W, G, F> std::io::Write for SplittingWriter<W, G, F>
+where
+    W: Write,
+    W::Error: Into<std::io::Error>,
+    G: FnMut(usize) -> Result<W, W::Error>,
+    F: Framing,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.write(buf).map_err(Into::into)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.flush().map_err(Into::into)
+    }
+}
+
+#[cfg(all(feature = "core2", not(feature = "std")))]
+impl<W, G, F> core2::io::Write for SplittingWriter<W, G, F>
+where
+    W: Write,
+    W::Error: Into<core2::io::Error>,
+    G: FnMut(usize) -> Result<W, W::Error>,
+    F: Framing,
+{
+    fn write(&mut self, buf: &[u8]) -> core2::io::Result<usize> {
+        self.write(buf).map_err(Into::into)
+    }
+    fn flush(&mut self) -> core2::io::Result<()> {
+        self.flush().map_err(Into::into)
+    }
+}
+
+#[cfg(all(feature = "embedded-io", not(any(feature = "std", feature = "core2"))))]
+impl<W, G, F> embedded_io::ErrorType for SplittingWriter<W, G, F>
+where
+    W: Write,
+    W::Error: embedded_io::Error,
+{
+    type Error = W::Error;
+}
+
+#[cfg(all(feature = "embedded-io", not(any(feature = "std", feature = "core2"))))]
+impl<W, G, F> embedded_io::Write for SplittingWriter<W, G, F>
+where
+    W: Write,
+    W::Error: embedded_io::Error,
+    G: FnMut(usize) -> Result<W, W::Error>,
+    F: Framing,
+{
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.write(buf)
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flush()
+    }
+}
+
+#[cfg(not(any(feature = "std", feature = "core2", feature = "embedded-io")))]
+impl<W, G, F> Write for SplittingWriter<W, G, F>
+where
+    W: Write,
+    G: FnMut(usize) -> Result<W, W::Error>,
+    F: Framing,
+{
+    type Error = W::Error;
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.write(buf)
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flush()
+    }
+    fn write_all(&mut self, mut buf: &[u8]) -> Result<(), Self::Error> {
+        while !buf.is_empty() {
+            let amt = self.write_piece(buf, true)?;
+            buf = &buf[amt..];
+        }
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{reader_with_buffer, writer_with_buffer};
+    use crate::{ArrayBuffer, FlushMode, Pipe};
+
+    #[test]
+    fn split_into_parts() {
+        let plaintext = (0..300u32).map(|i| i as u8).collect::<Vec<_>>();
+
+        let mut parts = Vec::new();
+        let splitter = SplittingWriter::new(7, 150, |_| {
+            let part = Pipe::new();
+            parts.push(part.clone());
+            Ok(part)
+        });
+        let mut writer = writer_with_buffer(ArrayBuffer::<64>::new(), splitter);
+        for part in plaintext.chunks(48) {
+            std::io::Write::write_all(&mut writer, part).unwrap();
+        }
+        writer.flush_with(FlushMode::Finalize).unwrap();
+        let splitter = writer.into_inner().unwrap();
+        assert_eq!(splitter.parts(), 3);
+        splitter.finish().unwrap();
+
+        let parts = parts
+            .into_iter()
+            .map(|mut part| {
+                let mut bytes = Vec::new();
+                std::io::Read::read_to_end(&mut part, &mut bytes).unwrap();
+                bytes
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(parts.len(), 3);
+        // every part holds whole chunks, after the stream header in the first one
+        for (i, part) in parts.iter().enumerate() {
+            let mut rest = if i == 0 { &part[7..] } else { &part[..] };
+            while !rest.is_empty() {
+                let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+                rest = &rest[4 + len..];
+            }
+        }
+        assert!(parts[..2].iter().all(|part| part.len() >= 150));
+
+        let ciphertext = parts.concat();
+        let mut reader = reader_with_buffer(ArrayBuffer::<64>::new(), ciphertext.as_slice());
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, plaintext);
+    }
+}
@@ -0,0 +1,94 @@
+use crate::error::Error;
+use crate::rw::{Read, WouldBlock};
+
+/// The largest stream header, chunk header or footer the non-blocking `try_*` methods can stage
+pub(crate) const STAGE_LEN: usize = 32;
+
+/// Holds a header while the non-blocking `try_*` methods move it to or from the inner stream in
+/// as many steps as it takes
+#[derive(Clone, Copy)]
+pub(crate) struct Stage {
+    bytes: [u8; STAGE_LEN],
+    len: u8,
+    offset: u8,
+}
+
+impl Stage {
+    pub(crate) const fn new() -> Self {
+        Self {
+            bytes: [0; STAGE_LEN],
+            len: 0,
+            offset: 0,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.len = 0;
+        self.offset = 0;
+    }
+
+    /// The staged bytes which haven't been consumed yet
+    pub(crate) fn remaining(&self) -> &[u8] {
+        &self.bytes[self.offset as usize..self.len as usize]
+    }
+
+    pub(crate) fn consume(&mut self, amt: usize) {
+        self.offset += amt as u8;
+    }
+
+    /// Appends as much of `bytes` as fits, returning how much that was
+    pub(crate) fn push(&mut self, bytes: &[u8]) -> usize {
+        let start = self.len as usize;
+        let amt = bytes.len().min(STAGE_LEN - start);
+        self.bytes[start..start + amt].copy_from_slice(&bytes[..amt]);
+        self.len += amt as u8;
+        amt
+    }
+
+    /// Appends whatever `write` writes into the stage, failing if it doesn't fit
+    pub(crate) fn write_with<E, Io>(
+        &mut self,
+        write: impl FnOnce(&mut &mut [u8]) -> Result<usize, E>,
+    ) -> Result<(), Error<Io>> {
+        let mut out = &mut self.bytes[self.len as usize..];
+        let available = out.len();
+        write(&mut out).map_err(|_| Error::Aead)?;
+        self.len += (available - out.len()) as u8;
+        Ok(())
+    }
+
+    /// Reads from `reader` until `len` bytes are staged. Returns `false` if the reader reached its
+    /// end first
+    pub(crate) fn fill<R: Read>(
+        &mut self,
+        reader: &mut R,
+        len: usize,
+    ) -> Result<bool, Error<R::Error>> {
+        if len > STAGE_LEN {
+            return Err(Error::Aead);
+        }
+        while (self.len as usize) < len {
+            match reader.read(&mut self.bytes[self.len as usize..len])? {
+                0 => return Ok(false),
+                read => self.len += read as u8,
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Turns an error which only means the inner stream would block into `None`
+pub(crate) fn nonblocking<T, E>(result: Result<T, Error<E>>) -> Result<Option<T>, Error<E>>
+where
+    E: WouldBlock,
+{
+    match result {
+        Ok(value) => Ok(Some(value)),
+        Err(Error::Io(err)) if err.would_block() => Ok(None),
+        Err(err) => Err(err),
+    }
+}
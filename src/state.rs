@@ -0,0 +1,147 @@
+use crate::error::InvalidStreamState;
+use crate::nonce::{nonce_from_slice, nonce_len};
+use aead::generic_array::ArrayLength;
+use aead::stream::{Nonce, NonceSize, StreamPrimitive};
+use aead::AeadInPlace;
+use core::convert::TryInto;
+use core::fmt;
+use core::ops::Sub;
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+/// Where an unfinished stream stands, as exported by
+/// [`EncryptBufWriter::export_state`](crate::EncryptBufWriter::export_state) and
+/// [`EncryptBufWriter::into_state`](crate::EncryptBufWriter::into_state), so that a
+/// Writer can carry on with it after a restart with
+/// [`EncryptBufWriter::from_state`](crate::EncryptBufWriter::from_state). Holds no key material,
+/// the key has to be provided again.
+///
+/// The nonce must not be used for another stream under the same key, and a state must only be
+/// resumed once, as resuming it twice encrypts two chunks under the same nonce
+pub struct StreamState<A, S>
+where
+    A: AeadInPlace,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    /// The nonce of the stream
+    pub nonce: Nonce<A, S>,
+    /// The number of chunks written so far
+    pub chunk_index: u32,
+    /// Whether the last chunk has been written
+    pub finished: bool,
+}
+
+impl<A, S> StreamState<A, S>
+where
+    A: AeadInPlace,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    /// The length of the state encoded by [`to_bytes`](Self::to_bytes): the nonce, the chunk
+    /// index as a big endian `u32` and a byte for whether the stream is finished
+    pub fn encoded_len() -> usize {
+        nonce_len::<A, S>() + 5
+    }
+
+    /// Encodes the state for storing it
+    #[cfg(feature = "alloc")]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(Self::encoded_len());
+        bytes.extend_from_slice(&self.nonce);
+        bytes.extend_from_slice(&self.chunk_index.to_be_bytes());
+        bytes.push(self.finished as u8);
+        bytes
+    }
+
+    /// Decodes a state encoded by [`to_bytes`](Self::to_bytes). Fails unless `bytes` is exactly
+    /// [`encoded_len`](Self::encoded_len) bytes long
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, InvalidStreamState> {
+        if bytes.len() != Self::encoded_len() {
+            return Err(InvalidStreamState);
+        }
+        let (nonce, rest) = bytes.split_at(nonce_len::<A, S>());
+        let (chunk_index, finished) = rest.split_at(4);
+        Ok(Self {
+            nonce: nonce_from_slice::<A, S>(nonce).map_err(|_| InvalidStreamState)?,
+            chunk_index: u32::from_be_bytes(
+                chunk_index.try_into().map_err(|_| InvalidStreamState)?,
+            ),
+            finished: match finished {
+                [0] => false,
+                [1] => true,
+                _ => return Err(InvalidStreamState),
+            },
+        })
+    }
+}
+
+impl<A, S> Clone for StreamState<A, S>
+where
+    A: AeadInPlace,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            nonce: self.nonce.clone(),
+            chunk_index: self.chunk_index,
+            finished: self.finished,
+        }
+    }
+}
+
+impl<A, S> PartialEq for StreamState<A, S>
+where
+    A: AeadInPlace,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.nonce == other.nonce
+            && self.chunk_index == other.chunk_index
+            && self.finished == other.finished
+    }
+}
+
+impl<A, S> fmt::Debug for StreamState<A, S>
+where
+    A: AeadInPlace,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamState")
+            .field("nonce", &"<redacted>")
+            .field("chunk_index", &self.chunk_index)
+            .field("finished", &self.finished)
+            .finish()
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aead::stream::StreamBE32;
+    use chacha20poly1305::ChaCha20Poly1305;
+
+    #[test]
+    fn debug_redacts_nonce() {
+        let state = StreamState::<ChaCha20Poly1305, StreamBE32<_>> {
+            nonce: [0xabu8; 7].into(),
+            chunk_index: 3,
+            finished: false,
+        };
+        let debug = format!("{:?}", state);
+        assert!(debug.contains("chunk_index: 3"));
+        assert!(debug.contains("<redacted>"));
+        assert!(!debug.contains("171"));
+    }
+}
@@ -0,0 +1,24 @@
+/// Counters of the chunks which went through a [`BufWriter`](crate::EncryptBufWriter) or
+/// [`BufReader`](crate::DecryptBufReader), as returned by
+/// [`EncryptBufWriter::stats`](crate::EncryptBufWriter::stats) and
+/// [`DecryptBufReader::stats`](crate::DecryptBufReader::stats). Both count the same way, so the
+/// Stats of a Writer and of the Reader which read its stream in full are equal
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of chunks sealed or opened, key changes included
+    pub chunks: u64,
+    /// The plaintext sealed into or opened from the chunks, without any padding
+    pub plaintext_bytes: u64,
+    /// The encrypted chunks, tags and padding included, but not the stream header, the chunk
+    /// headers or anything else the framing adds
+    pub ciphertext_bytes: u64,
+}
+
+impl Stats {
+    /// Counts a chunk of `ciphertext` bytes holding `plaintext` bytes of data
+    pub(crate) fn add_chunk(&mut self, plaintext: usize, ciphertext: usize) {
+        self.chunks += 1;
+        self.plaintext_bytes += plaintext as u64;
+        self.ciphertext_bytes += ciphertext as u64;
+    }
+}
@@ -0,0 +1,221 @@
+use crate::error::Error;
+use crate::reader::DecryptBufReader;
+use crate::rw::{Read, Write};
+use crate::writer::{EncryptBufWriter, FlushMode};
+use crate::RECOMMENDED_CHUNK_SIZE;
+use aead::generic_array::typenum::Unsigned;
+use aead::generic_array::ArrayLength;
+use aead::stream::{NewStream, Nonce, NonceSize, StreamPrimitive};
+use aead::{AeadCore, AeadInPlace, Key, NewAead};
+use alloc::vec::Vec;
+use core::ops::Sub;
+
+/// The largest encrypted chunk, tag included, [`decrypt_stream`](decrypt_stream) accepts
+pub const MAX_STREAM_CHUNK_LEN: usize = 64 * RECOMMENDED_CHUNK_SIZE;
+
+/// Encrypts everything from `reader` into `writer` as a complete stream with chunks of
+/// `chunk_size` bytes of plaintext, returning the number of plaintext bytes encrypted. Fails
+/// with [`Error::Aead`](Error::Aead) if `chunk_size` is `0`, or too large for a chunk's length to
+/// fit its `u32` prefix. If reading fails, `writer` is left
+/// with the chunks written so far but without a last chunk, so that what it holds fails to
+/// decrypt rather than passing for the whole stream.
+///
+/// [`RECOMMENDED_CHUNK_SIZE`](RECOMMENDED_CHUNK_SIZE) suits most uses, see
+/// [Choosing a buffer size](crate#choosing-a-buffer-size)
+pub fn encrypt_stream<A, S, R, W>(
+    key: &Key<A>,
+    nonce: &Nonce<A, S>,
+    reader: R,
+    writer: W,
+    chunk_size: usize,
+) -> Result<u64, Error<W::Error>>
+where
+    A: AeadInPlace + NewAead,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    R: Read,
+    W: Write,
+    W::Error: From<R::Error>,
+{
+    let tag_len = <<A as AeadCore>::TagSize as Unsigned>::to_usize();
+    let capacity = match chunk_size.checked_add(tag_len) {
+        Some(capacity) if chunk_size > 0 && capacity <= u32::MAX as usize => capacity,
+        _ => return Err(Error::Aead),
+    };
+    let buffer = Vec::with_capacity(capacity);
+    let mut writer =
+        EncryptBufWriter::<A, _, _, S>::new(key, nonce, buffer, writer).map_err(|_| Error::Aead)?;
+    let read = match writer.write_from(reader) {
+        Ok(read) => read,
+        Err(err) => {
+            // the plaintext read so far must not be sealed as a complete stream
+            writer.abandon();
+            return Err(err);
+        }
+    };
+    writer.flush_with(FlushMode::Finalize)?;
+    Ok(read)
+}
+
+/// Decrypts the stream read from `reader` into `writer`, returning the number of plaintext bytes
+/// written. The stream may have been written with any chunk size, as long as no encrypted chunk
+/// is larger than [`MAX_STREAM_CHUNK_LEN`](MAX_STREAM_CHUNK_LEN), which caps the memory a bogus
+/// chunk length can make this allocate. Larger chunks need a
+/// [`DecryptBufReader`](DecryptBufReader) with a larger
+/// [`with_max_chunk_len`](DecryptBufReader::with_max_chunk_len).
+///
+/// Plaintext is written out as soon as each chunk has been authenticated, so if decrypting fails
+/// part way, `writer` holds the plaintext up to the failed chunk
+pub fn decrypt_stream<A, S, R, W>(
+    key: &Key<A>,
+    reader: R,
+    mut writer: W,
+) -> Result<u64, Error<W::Error>>
+where
+    A: AeadInPlace + NewAead,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    R: Read,
+    W: Write,
+    W::Error: From<R::Error>,
+{
+    let tag_len = <<A as AeadCore>::TagSize as Unsigned>::to_usize();
+    let buffer = Vec::with_capacity(RECOMMENDED_CHUNK_SIZE + tag_len);
+    let mut reader = DecryptBufReader::<A, _, _, S>::new(key, buffer, reader)
+        .and_then(|reader| reader.with_max_chunk_len(MAX_STREAM_CHUNK_LEN))
+        .map_err(|_| Error::Aead)?;
+    let mut chunk = Vec::new();
+    let mut written = 0;
+    loop {
+        let len = reader.read_into(&mut chunk).map_err(|err| match err {
+            Error::Aead => Error::Aead,
+            Error::Io(err) => Error::Io(W::Error::from(err)),
+            Error::StreamMismatch => Error::StreamMismatch,
+            Error::Truncated => Error::Truncated,
+        })?;
+        if len == 0 {
+            break;
+        }
+        writer.write_all(&chunk)?;
+        written += len as u64;
+    }
+    writer.flush()?;
+    Ok(written)
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::KEY;
+    use aead::stream::StreamBE32;
+    use chacha20poly1305::ChaCha20Poly1305;
+
+    #[test]
+    fn stream_round_trip() {
+        let plaintext = (0..200_000u32).map(|i| i as u8).collect::<Vec<_>>();
+
+        let mut ciphertext = Vec::new();
+        let read = encrypt_stream::<ChaCha20Poly1305, StreamBE32<_>, _, _>(
+            KEY.into(),
+            &Default::default(),
+            plaintext.as_slice(),
+            &mut ciphertext,
+            RECOMMENDED_CHUNK_SIZE,
+        )
+        .unwrap();
+        assert_eq!(read, plaintext.len() as u64);
+        // 13 chunks of 16 byte tags and 4 byte lengths after the 7 byte nonce
+        assert_eq!(ciphertext.len(), plaintext.len() + 7 + 13 * 20);
+
+        let mut out = Vec::new();
+        let written = decrypt_stream::<ChaCha20Poly1305, StreamBE32<_>, _, _>(
+            KEY.into(),
+            ciphertext.as_slice(),
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(written, plaintext.len() as u64);
+        assert_eq!(out, plaintext);
+
+        // chunks larger than the reader's initial buffer are accepted too
+        ciphertext.clear();
+        encrypt_stream::<ChaCha20Poly1305, StreamBE32<_>, _, _>(
+            KEY.into(),
+            &Default::default(),
+            plaintext.as_slice(),
+            &mut ciphertext,
+            4 * RECOMMENDED_CHUNK_SIZE,
+        )
+        .unwrap();
+        out.clear();
+        decrypt_stream::<ChaCha20Poly1305, StreamBE32<_>, _, _>(
+            KEY.into(),
+            ciphertext.as_slice(),
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(out, plaintext);
+
+        assert!(matches!(
+            encrypt_stream::<ChaCha20Poly1305, StreamBE32<_>, _, _>(
+                KEY.into(),
+                &Default::default(),
+                plaintext.as_slice(),
+                Vec::new(),
+                0,
+            ),
+            Err(Error::Aead)
+        ));
+        for chunk_size in [u32::MAX as usize, usize::MAX] {
+            assert!(matches!(
+                encrypt_stream::<ChaCha20Poly1305, StreamBE32<_>, _, _>(
+                    KEY.into(),
+                    &Default::default(),
+                    plaintext.as_slice(),
+                    Vec::new(),
+                    chunk_size,
+                ),
+                Err(Error::Aead)
+            ));
+        }
+    }
+
+    #[test]
+    fn failed_read_is_not_finalized() {
+        /// Fails once it has handed out 5000 bytes
+        struct FailingReader(usize);
+
+        impl std::io::Read for FailingReader {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                if self.0 >= 5000 {
+                    return Err(std::io::Error::other("disk gone"));
+                }
+                let len = buf.len().min(5000 - self.0);
+                buf[..len].fill(0x42);
+                self.0 += len;
+                Ok(len)
+            }
+        }
+
+        let mut ciphertext = Vec::new();
+        assert!(encrypt_stream::<ChaCha20Poly1305, StreamBE32<_>, _, _>(
+            KEY.into(),
+            &Default::default(),
+            FailingReader(0),
+            &mut ciphertext,
+            1024,
+        )
+        .is_err());
+        // the chunks before the failure were written, but the stream doesn't authenticate
+        assert!(!ciphertext.is_empty());
+        assert!(decrypt_stream::<ChaCha20Poly1305, StreamBE32<_>, _, _>(
+            KEY.into(),
+            ciphertext.as_slice(),
+            Vec::new(),
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,142 @@
+//! Fixtures shared by the tests of the other modules, so that each of them only holds what it
+//! checks
+// not every configuration of features runs the tests using each of them
+#![allow(dead_code)]
+
+use crate::buffer::{CappedBuffer, ResizeBuffer};
+use crate::rw::{Read, Write};
+use crate::{ArrayBuffer, DecryptBE32BufReader, EncryptBE32BufWriter};
+#[cfg(feature = "std")]
+use crate::{EncryptBufWriter, FlushMode};
+#[cfg(feature = "std")]
+use aead::generic_array::ArrayLength;
+#[cfg(feature = "std")]
+use aead::stream::{NewStream, NonceSize, StreamPrimitive};
+#[cfg(feature = "std")]
+use aead::{AeadCore, AeadInPlace, Key, NewAead};
+use chacha20poly1305::ChaCha20Poly1305;
+#[cfg(feature = "std")]
+use core::ops::Sub;
+#[cfg(feature = "std")]
+use std::alloc::{GlobalAlloc, Layout, System};
+#[cfg(feature = "std")]
+use std::cell::Cell;
+
+/// Counts the allocations and reallocations of each thread, so that a test can check how often
+/// it allocated while the other tests run alongside it
+#[cfg(feature = "std")]
+struct CountingAllocator;
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+#[cfg(feature = "std")]
+impl CountingAllocator {
+    fn count() {
+        // the counter is gone while the thread is torn down
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+    }
+}
+
+#[cfg(feature = "std")]
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        Self::count();
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        Self::count();
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[cfg(feature = "std")]
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// The number of allocations and reallocations made by the current thread so far
+#[cfg(feature = "std")]
+pub(crate) fn allocations() -> usize {
+    ALLOCATIONS.with(Cell::get)
+}
+
+/// The key of all the streams in the tests
+pub(crate) const KEY: &[u8; 32] = b"my very super super secret key!!";
+
+/// Plaintext spanning a few chunks of a [`writer`]
+pub(crate) const PLAINTEXT: [u8; 300] = [0x42; 300];
+
+/// A Writer encrypting to `output` under [`KEY`] and the default nonce, with chunks of up to 112
+/// bytes of plaintext
+pub(crate) fn writer<W: Write>(
+    output: W,
+) -> EncryptBE32BufWriter<ChaCha20Poly1305, ArrayBuffer<128>, W> {
+    writer_with_buffer(ArrayBuffer::new(), output)
+}
+
+/// A Writer like [`writer`], with chunks sized by `buffer`
+pub(crate) fn writer_with_buffer<B: CappedBuffer, W: Write>(
+    buffer: B,
+    output: W,
+) -> EncryptBE32BufWriter<ChaCha20Poly1305, B, W> {
+    EncryptBE32BufWriter::new(KEY.into(), &Default::default(), buffer, output).unwrap()
+}
+
+/// A Reader decrypting what a [`writer`] wrote to `input`
+pub(crate) fn reader<R: Read>(
+    input: R,
+) -> DecryptBE32BufReader<ChaCha20Poly1305, ArrayBuffer<128>, R> {
+    reader_with_buffer(ArrayBuffer::new(), input)
+}
+
+/// A Reader like [`reader`], for chunks of up to the capacity of `buffer`
+pub(crate) fn reader_with_buffer<B: ResizeBuffer + CappedBuffer, R: Read>(
+    buffer: B,
+    input: R,
+) -> DecryptBE32BufReader<ChaCha20Poly1305, B, R> {
+    DecryptBE32BufReader::new(KEY.into(), buffer, input).unwrap()
+}
+
+/// Encrypts `plaintext` with a [`writer`] as a finalized stream, a chunk's worth at a time so
+/// that every chunk but the last is full
+#[cfg(feature = "std")]
+pub(crate) fn encrypt(plaintext: &[u8]) -> Vec<u8> {
+    let mut writer = writer(Vec::new());
+    for chunk in plaintext.chunks(112) {
+        std::io::Write::write_all(&mut writer, chunk).unwrap();
+    }
+    writer.into_inner().unwrap()
+}
+
+/// Encrypts `plaintext` as a finalized stream of the AEAD `A` and STREAM `S`, with a buffer of
+/// `capacity` bytes, writing `write_size` bytes at a time
+#[cfg(feature = "std")]
+pub(crate) fn encrypt_chunked<A, S>(plaintext: &[u8], capacity: usize, write_size: usize) -> Vec<u8>
+where
+    A: AeadInPlace + NewAead,
+    S: StreamPrimitive<A> + NewStream<A>,
+    <A as AeadCore>::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    let mut ciphertext = Vec::new();
+    let mut writer = EncryptBufWriter::<A, _, _, S>::new(
+        Key::<A>::from_slice(KEY),
+        &Default::default(),
+        Vec::with_capacity(capacity),
+        &mut ciphertext,
+    )
+    .unwrap();
+    for block in plaintext.chunks(write_size) {
+        std::io::Write::write_all(&mut writer, block).unwrap();
+    }
+    writer.flush_with(FlushMode::Finalize).unwrap();
+    drop(writer);
+    ciphertext
+}
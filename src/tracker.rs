@@ -0,0 +1,73 @@
+#[cfg(feature = "alloc")]
+use alloc::collections::BTreeSet;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+/// Records the nonces used with a key, so that
+/// [`BufWriter::new_tracked`](crate::EncryptBufWriter::new_tracked) can refuse to start a second
+/// stream under the same key and nonce, which would give away the plaintext of both. The crate
+/// has no view of other Writers, so this is opt-in: a tracker only catches reuse among the
+/// Writers constructed through it, and should be kept for exactly one key, persisted alongside it
+/// where streams are written across restarts
+pub trait NonceTracker {
+    /// Records `nonce` as used, returning `false` if it was recorded before
+    fn record(&mut self, nonce: &[u8]) -> bool;
+}
+
+/// A [`NonceTracker`](NonceTracker) keeping every nonce it has seen in memory
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone, Default)]
+pub struct MemoryNonceTracker(BTreeSet<Vec<u8>>);
+
+#[cfg(feature = "alloc")]
+impl MemoryNonceTracker {
+    /// Creates a tracker which hasn't seen any nonce yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of nonces seen
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Whether no nonce has been seen
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl NonceTracker for MemoryNonceTracker {
+    fn record(&mut self, nonce: &[u8]) -> bool {
+        self.0.insert(nonce.to_vec())
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::KEY;
+    use crate::{ArrayBuffer, EncryptBE32BufWriter, NonceReuseError};
+    use chacha20poly1305::ChaCha20Poly1305;
+
+    #[test]
+    fn nonce_reuse_rejected() {
+        let mut tracker = MemoryNonceTracker::new();
+        let new = |tracker: &mut MemoryNonceTracker, nonce: [u8; 7]| {
+            EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new_tracked(
+                KEY.into(),
+                &nonce.into(),
+                ArrayBuffer::<128>::new(),
+                Vec::new(),
+                tracker,
+            )
+            .err()
+        };
+        assert_eq!(new(&mut tracker, [1; 7]), None);
+        assert_eq!(new(&mut tracker, [2; 7]), None);
+        assert_eq!(new(&mut tracker, [1; 7]), Some(NonceReuseError::Reused));
+        assert_eq!(tracker.len(), 2);
+    }
+}
@@ -0,0 +1,276 @@
+//! Pull-based counterparts of the [`BufWriter`](EncryptBufWriter) and
+//! [`BufReader`](DecryptBufReader): an [`EncryptReader`] reads plaintext from an inner reader and
+//! hands out the encrypted stream, and a [`DecryptWriter`] takes the encrypted stream and writes
+//! the plaintext to an inner writer. Both are driven by the existing Writer and Reader over an
+//! in-memory [`Pipe`], so the stream is the same byte for byte
+
+use crate::buffer::{CappedBuffer, ResizeBuffer};
+use crate::error::InvalidCapacity;
+use crate::pipe::Pipe;
+use crate::reader::DecryptBufReader;
+use crate::rw::Progress;
+use crate::writer::{EncryptBufWriter, FlushMode};
+use aead::generic_array::ArrayLength;
+use aead::stream::{NewStream, Nonce, NonceSize, StreamPrimitive};
+use aead::{AeadInPlace, Key, NewAead};
+use alloc::rc::Rc;
+use core::cell::Cell;
+use core::ops::Sub;
+
+/// The plaintext moved between the inner reader or writer and the Writer or Reader per step
+const SCRATCH_LEN: usize = 512;
+
+/// A `std::io::Read` which reads plaintext from an inner reader and returns the encrypted stream,
+/// as an [`EncryptBufWriter`](EncryptBufWriter) writing to an in-memory buffer would have written
+/// it. Plaintext is only read from the inner reader as the ciphertext is asked for, and the
+/// stream is finalized once the inner reader ends
+pub struct EncryptReader<A, B, R, S>
+where
+    A: AeadInPlace,
+    B: CappedBuffer,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    writer: EncryptBufWriter<A, B, Pipe, S>,
+    ciphertext: Pipe,
+    reader: R,
+    finished: bool,
+}
+
+impl<A, B, R, S> EncryptReader<A, B, R, S>
+where
+    A: AeadInPlace + NewAead,
+    B: CappedBuffer,
+    R: std::io::Read,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    /// Constructs a new EncryptReader using an AEAD key, nonce, buffer and the reader of the
+    /// plaintext
+    pub fn new(
+        key: &Key<A>,
+        nonce: &Nonce<A, S>,
+        buffer: B,
+        reader: R,
+    ) -> Result<Self, InvalidCapacity> {
+        let ciphertext = Pipe::new();
+        let writer = EncryptBufWriter::new(key, nonce, buffer, ciphertext.clone())?;
+        Ok(Self {
+            writer,
+            ciphertext,
+            reader,
+            finished: false,
+        })
+    }
+
+    /// Consumes the EncryptReader and returns the inner reader, dropping any ciphertext which
+    /// hasn't been read yet
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<A, B, R, S> std::io::Read for EncryptReader<A, B, R, S>
+where
+    A: AeadInPlace + NewAead,
+    B: CappedBuffer,
+    R: std::io::Read,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut plaintext = [0u8; SCRATCH_LEN];
+        // the Writer only writes out whole chunks, so several reads may go into filling one
+        while self.ciphertext.is_empty() && !self.finished && !buf.is_empty() {
+            match self.reader.read(&mut plaintext)? {
+                0 => {
+                    self.writer.flush_with(FlushMode::Finalize)?;
+                    self.finished = true;
+                }
+                read => std::io::Write::write_all(&mut self.writer, &plaintext[..read])?,
+            }
+        }
+        std::io::Read::read(&mut self.ciphertext, buf)
+    }
+}
+
+/// The source of the Reader in a [`DecryptWriter`], which would block while it is empty until
+/// the writer is finished
+struct Feed {
+    pipe: Pipe,
+    finished: Rc<Cell<bool>>,
+}
+
+impl std::io::Read for Feed {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pipe.is_empty() && !self.finished.get() {
+            return Err(std::io::ErrorKind::WouldBlock.into());
+        }
+        std::io::Read::read(&mut self.pipe, buf)
+    }
+}
+
+/// A `std::io::Write` which takes the encrypted stream and writes the plaintext to an inner
+/// writer, as a [`DecryptBufReader`](DecryptBufReader) reading from an in-memory buffer would
+/// have read it.
+///
+/// A chunk is only decrypted once the header of the next one has been written, so the plaintext
+/// of the last chunk is only written out by [`finish`](Self::finish), which also checks that the
+/// stream wasn't cut short
+pub struct DecryptWriter<A, B, W, S>
+where
+    A: AeadInPlace + NewAead,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    reader: DecryptBufReader<A, B, Feed, S>,
+    ciphertext: Pipe,
+    finished: Rc<Cell<bool>>,
+    writer: W,
+}
+
+impl<A, B, W, S> DecryptWriter<A, B, W, S>
+where
+    A: AeadInPlace + NewAead,
+    B: ResizeBuffer + CappedBuffer,
+    W: std::io::Write,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    /// Constructs a new DecryptWriter using an AEAD key, buffer and the writer of the plaintext
+    pub fn new(key: &Key<A>, buffer: B, writer: W) -> Result<Self, InvalidCapacity> {
+        let ciphertext = Pipe::new();
+        let finished = Rc::new(Cell::new(false));
+        let feed = Feed {
+            pipe: ciphertext.clone(),
+            finished: finished.clone(),
+        };
+        Ok(Self {
+            reader: DecryptBufReader::new(key, buffer, feed)?,
+            ciphertext,
+            finished,
+            writer,
+        })
+    }
+
+    /// Marks the end of the encrypted stream, and writes out the rest of the plaintext once the
+    /// last chunk has been authenticated. A stream which ends before its last chunk fails with an
+    /// error of kind `Other`, like any chunk which fails to authenticate
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        self.finished.set(true);
+        self.decrypt()?;
+        if !self.reader.is_at_eof() {
+            return Err(std::io::Error::other("encrypted stream ended early"));
+        }
+        self.writer.flush()
+    }
+
+    /// Consumes the DecryptWriter and returns the inner writer, dropping any ciphertext which
+    /// hasn't been decrypted yet
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Decrypts as much of the ciphertext written so far as possible into the inner writer
+    fn decrypt(&mut self) -> std::io::Result<()> {
+        let mut plaintext = [0u8; SCRATCH_LEN];
+        loop {
+            match self.reader.try_read(&mut plaintext)? {
+                Progress::Pending | Progress::Buffered(0) => return Ok(()),
+                Progress::Buffered(read) | Progress::Flushed(read) => {
+                    self.writer.write_all(&plaintext[..read])?
+                }
+            }
+        }
+    }
+}
+
+impl<A, B, W, S> std::io::Write for DecryptWriter<A, B, W, S>
+where
+    A: AeadInPlace + NewAead,
+    B: ResizeBuffer + CappedBuffer,
+    W: std::io::Write,
+    S: StreamPrimitive<A> + NewStream<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        std::io::Write::write(&mut self.ciphertext, buf)?;
+        self.decrypt()?;
+        Ok(buf.len())
+    }
+
+    /// Writes out the plaintext of every chunk which could be decrypted so far and flushes the
+    /// inner writer
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.decrypt()?;
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{encrypt, encrypt_chunked, reader, KEY};
+    use crate::ArrayBuffer;
+    use aead::stream::StreamBE32;
+    use chacha20poly1305::ChaCha20Poly1305;
+    use std::io::{Read, Write};
+
+    fn plaintext() -> Vec<u8> {
+        (0..1000).map(|i| i as u8).collect()
+    }
+
+    #[test]
+    fn encrypt_reader() {
+        let plaintext = plaintext();
+        let mut encryptor = EncryptReader::<ChaCha20Poly1305, _, _, StreamBE32<_>>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            &plaintext[..],
+        )
+        .unwrap();
+        let mut ciphertext = Vec::new();
+        encryptor.read_to_end(&mut ciphertext).unwrap();
+        // the plaintext is handed to the Writer as it is read
+        assert_eq!(
+            ciphertext,
+            encrypt_chunked::<ChaCha20Poly1305, StreamBE32<_>>(&plaintext, 128, SCRATCH_LEN)
+        );
+        assert_eq!(reader(&ciphertext[..]).decrypt_all().unwrap(), plaintext);
+    }
+
+    #[test]
+    fn decrypt_writer() {
+        let plaintext = plaintext();
+        let ciphertext = encrypt(&plaintext);
+
+        let mut writer = DecryptWriter::<ChaCha20Poly1305, _, _, StreamBE32<_>>::new(
+            KEY.into(),
+            ArrayBuffer::<128>::new(),
+            Vec::new(),
+        )
+        .unwrap();
+        for part in ciphertext.chunks(7) {
+            writer.write_all(part).unwrap();
+        }
+        writer.finish().unwrap();
+        assert_eq!(writer.into_inner(), plaintext);
+
+        // a stream cut off part way through is rejected
+        let mut writer = DecryptWriter::<ChaCha20Poly1305, _, _, StreamBE32<_>>::new(
+            KEY.into(),
+            ArrayBuffer::<128>::new(),
+            Vec::new(),
+        )
+        .unwrap();
+        writer.write_all(&ciphertext[..300]).unwrap();
+        assert!(writer.finish().is_err());
+    }
+}
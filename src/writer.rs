@@ -1,14 +1,192 @@
+use crate::associated::AssociatedData;
 use crate::buffer::CappedBuffer;
-use crate::error::{Error, IntoInnerError, InvalidCapacity};
-use crate::rw::Write;
+use crate::commitment::{COMMITMENT_AAD, COMMITMENT_LEN};
+use crate::error::{
+    ChunkIndexError, Error, IntoInnerError, InvalidCapacity, KeySliceError, KeyringError,
+    NonceReuseError, SelfTestError,
+};
+#[cfg(feature = "crc-footer")]
+use crate::footer::Footer;
+use crate::framing::{DefaultFraming, Framing};
+use crate::keyring::Keyring;
+#[cfg(feature = "alloc")]
+use crate::rekey::{Rekey, REKEY_AAD};
+use crate::rw::{Progress, Read, WouldBlock, Write};
+use crate::self_test::self_test;
+use crate::slice_buffer::SliceBuffer;
+use crate::stage::{nonblocking, Stage};
+use crate::state::StreamState;
+use crate::stats::Stats;
+use crate::tracker::NonceTracker;
 use aead::generic_array::typenum::Unsigned;
 use aead::generic_array::ArrayLength;
-use aead::stream::{Encryptor, NewStream, Nonce, NonceSize, StreamPrimitive};
-use aead::{AeadCore, AeadInPlace, Key, NewAead};
+use aead::stream::{NewStream, Nonce, NonceSize, StreamPrimitive};
+use aead::{AeadCore, AeadInPlace, Buffer, Key, NewAead};
+use core::fmt;
+use core::mem::{self, ManuallyDrop};
 use core::ops::Sub;
-use core::{mem, ptr};
 
-#[derive(Clone, Copy)]
+#[cfg(feature = "alloc")]
+use crate::associated::metadata_prefix;
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+
+#[cfg(feature = "alloc")]
+type DropErrorHook<E> = Box<dyn FnMut(&Error<E>) + Send>;
+
+/// Where an unfinished stream stands, along with the inner writer it was written to
+type Parted<A, S, W> = (StreamState<A, S>, W);
+
+/// Size of the length field at the start of each padded chunk's plaintext
+pub(crate) const PADDING_HEADER_LEN: usize = 4;
+
+/// The exact length in bytes of the stream a [`BufWriter`](EncryptBufWriter) using the AEAD `A`
+/// and the [`StreamPrimitive`](StreamPrimitive) `S` writes when `plaintext_len` bytes of
+/// plaintext are handed to a single `write_all` before finalizing, given a buffer holding
+/// `chunk_size` bytes of plaintext per chunk. This is the nonce, followed by every chunk with its
+/// length header and tag.
+///
+/// A write which doesn't fit into the rest of the buffer writes out the buffered data first, so
+/// plaintext larger than a chunk starts out with an empty chunk, and the stream of other writes
+/// depends on how they split the plaintext. It assumes the [`DefaultFraming`](DefaultFraming) and
+/// none of the optional extras such as an end marker, metadata, padding or a footer. Panics if
+/// `chunk_size` is 0
+pub fn predicted_ciphertext_len<A, S>(plaintext_len: usize, chunk_size: usize) -> usize
+where
+    A: AeadInPlace,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    assert!(chunk_size > 0, "chunk size must not be 0");
+    let tag_len = <A::TagSize as Unsigned>::to_usize();
+    let chunks = match plaintext_len > chunk_size {
+        true => 1 + plaintext_len.div_ceil(chunk_size),
+        false => 1,
+    };
+    crate::nonce_len::<A, S>() + chunks * (4 + tag_len) + plaintext_len
+}
+
+/// How [`EncryptBufWriter::flush_with`](EncryptBufWriter::flush_with) treats the stream.
+/// Flushing through the `Write` traits always uses [`Chunk`](FlushMode::Chunk)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlushMode {
+    /// Writes out the buffered data as a chunk, leaving the stream open for more data. Does not
+    /// write an empty chunk if nothing is buffered
+    Chunk,
+    /// Writes out the buffered data as the final chunk, completing the stream. Nothing can be
+    /// written afterwards
+    Finalize,
+}
+
+/// What happens when finalizing the stream fails as the Writer is dropped, see
+/// [`EncryptBufWriter::with_drop_policy`](EncryptBufWriter::with_drop_policy)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Carries on as if nothing happened, after calling the
+    /// [`on_drop_error`](EncryptBufWriter::on_drop_error) hook if there is one
+    Ignore,
+    /// Panics, unless the Writer is dropped while the thread is already panicking. Without `std`
+    /// there is no way to tell, so dropping the Writer during unwinding panics a second time,
+    /// which aborts
+    Panic,
+    /// Aborts the process. Without `std` there is no way to abort, so this panics like
+    /// [`Panic`](DropPolicy::Panic)
+    Abort,
+}
+
+/// The output of a sealed chunk which the non-blocking methods haven't finished writing to the
+/// inner writer yet. The reserved header is written first when this is the first chunk, then the
+/// staged headers and the ciphertext in the buffer
+struct Pending {
+    head: Stage,
+    body: usize,
+    last: bool,
+    /// Whether the end of the chunks has been staged in place of the head
+    tail: bool,
+}
+
+/// Where the inner writer was moved to by seeking back into the output already written, which
+/// is then overwritten as is instead of being encrypted
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug)]
+struct Overwrite {
+    position: u64,
+    /// The end of the output written so far, where the stream continues
+    end: u64,
+}
+
+/// The STREAM encryptor of `aead::stream`, with its position at hand so that a stream can be
+/// resumed at a chunk
+struct Encryptor<A, S>
+where
+    A: AeadInPlace,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    stream: S,
+    position: S::Counter,
+}
+
+impl<A, S> Encryptor<A, S>
+where
+    A: AeadInPlace,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+{
+    fn from_aead(aead: A, nonce: &Nonce<A, S>) -> Self
+    where
+        A: NewAead,
+        S: NewStream<A>,
+    {
+        Self {
+            stream: S::from_aead(aead, nonce),
+            position: Default::default(),
+        }
+    }
+
+    /// Moves on by `chunks` positions, failing past the last position a chunk can be encrypted
+    /// at
+    fn skip(&mut self, chunks: u32) -> Result<(), aead::Error> {
+        for _ in 0..chunks {
+            if self.position == S::COUNTER_MAX {
+                return Err(aead::Error);
+            }
+            self.position += S::COUNTER_INCR;
+        }
+        Ok(())
+    }
+
+    fn encrypt_next_in_place(
+        &mut self,
+        associated_data: &[u8],
+        buffer: &mut dyn aead::Buffer,
+    ) -> Result<(), aead::Error> {
+        // the last position is left for the last chunk
+        if self.position == S::COUNTER_MAX {
+            return Err(aead::Error);
+        }
+        self.stream
+            .encrypt_in_place(self.position, false, associated_data, buffer)?;
+        self.position += S::COUNTER_INCR;
+        Ok(())
+    }
+
+    fn encrypt_last_in_place(
+        self,
+        associated_data: &[u8],
+        buffer: &mut dyn aead::Buffer,
+    ) -> Result<(), aead::Error> {
+        self.stream
+            .encrypt_in_place(self.position, true, associated_data, buffer)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
 enum State {
     Init,
     Writing,
@@ -17,8 +195,20 @@ enum State {
 
 /// A wrapper around a [`Write`](Write) object and a [`StreamPrimitive`](`StreamPrimitive`)
 /// providing a [`Write`](Write) interface which automatically encrypts the underlying stream when
-/// writing
-pub struct EncryptBufWriter<A, B, W, S>
+/// writing.
+///
+/// The stream is finalized by [`flush_with(FlushMode::Finalize)`](Self::flush_with),
+/// [`into_inner`](Self::into_inner) or when the Writer is dropped. Flushing through the `Write`
+/// traits only writes out the buffered data as a chunk.
+///
+/// The Writer is `Send` whenever the AEAD, buffer, inner writer and framing are, so it can be
+/// moved to a worker thread. With the `alloc` feature it is not `Sync`, as the
+/// [`on_drop_error`](Self::on_drop_error) hook is only required to be `Send`.
+///
+/// `&mut EncryptBufWriter` is a writer as well, through the forwarding `&mut W` implementations
+/// of each I/O backend, so the Writer can be lent to generic code taking `impl Write` and
+/// finalized afterwards
+pub struct EncryptBufWriter<A, B, W, S, F = DefaultFraming>
 where
     A: AeadInPlace,
     B: CappedBuffer,
@@ -26,13 +216,52 @@ where
     S: StreamPrimitive<A>,
     A::NonceSize: Sub<S::NonceOverhead>,
     NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
 {
     encryptor: Option<Encryptor<A, S>>,
     nonce: Nonce<A, S>,
     buffer: B,
-    writer: W,
+    /// Only taken out by [`into_inner`](Self::into_inner), after which `Drop` leaves it alone
+    writer: ManuallyDrop<W>,
+    framing: F,
     capacity: usize,
+    overhead: usize,
     state: State,
+    chunk_index: u32,
+    bytes_written: u64,
+    /// How many of the bytes written went to writers swapped out by
+    /// [`replace_writer`](Self::replace_writer), and aren't held by the current one
+    replaced_bytes: u64,
+    reserved_header: usize,
+    nonce_prefix: bool,
+    padded: bool,
+    end_marker: bool,
+    key_commitment: bool,
+    /// The plaintext after which a write is written out as a chunk right away
+    flush_threshold: Option<usize>,
+    /// The error writing out a chunk once a write reached the flush threshold, which had already
+    /// buffered the data, returned by the next call instead
+    threshold_error: Option<Error<W::Error>>,
+    associated: AssociatedData,
+    pending: Option<Pending>,
+    stats: Stats,
+    #[cfg(feature = "std")]
+    overwrite: Option<Overwrite>,
+    #[cfg(feature = "crc-footer")]
+    footer: Option<Footer>,
+    #[cfg(feature = "alloc")]
+    on_drop_error: Option<DropErrorHook<W::Error>>,
+    drop_policy: DropPolicy,
+    /// Set once the stream has been abandoned, so that dropping the Writer doesn't finalize it
+    abandoned: bool,
+    /// Set once the inner writer has been taken out, so that dropping the rest neither finalizes
+    /// the stream nor drops the writer
+    writer_taken: bool,
+    #[cfg(feature = "alloc")]
+    rekey: Option<Rekey<A>>,
+    /// The encryptor of the next epoch while the control chunk announcing it is sealed
+    #[cfg(feature = "alloc")]
+    next_encryptor: Option<Encryptor<A, S>>,
 }
 
 impl<A, B, W, S> EncryptBufWriter<A, B, W, S>
@@ -48,29 +277,199 @@ where
     pub fn new(
         key: &Key<A>,
         nonce: &Nonce<A, S>,
-        mut buffer: B,
+        buffer: B,
         writer: W,
     ) -> Result<Self, InvalidCapacity>
     where
         A: NewAead,
         S: NewStream<A>,
     {
-        buffer.truncate(0);
-        let capacity = Self::capacity_for_buffer(&buffer)?;
-        Ok(Self {
-            encryptor: Some(Encryptor::new(key, nonce)),
-            nonce: nonce.clone(),
-            writer,
-            buffer,
-            capacity,
-            state: State::Init,
-        })
+        Self::from_aead(A::new(key), nonce, buffer, writer)
+    }
+
+    /// Constructs a new Writer like [`new`](Self::new) from a key given as raw bytes. Fails with
+    /// [`KeySliceError::KeyLength`](KeySliceError::KeyLength) if `key` isn't exactly as long as
+    /// the AEAD's keys
+    pub fn from_key_slice(
+        key: &[u8],
+        nonce: &Nonce<A, S>,
+        buffer: B,
+        writer: W,
+    ) -> Result<Self, KeySliceError>
+    where
+        A: NewAead,
+        S: NewStream<A>,
+    {
+        if key.len() != A::KeySize::to_usize() {
+            return Err(KeySliceError::KeyLength);
+        }
+        Ok(Self::new(Key::<A>::from_slice(key), nonce, buffer, writer)?)
+    }
+
+    /// Constructs a new Writer like [`new`](Self::new), but first seals and opens a known test
+    /// vector with the key to check the AEAD round-trips, failing with
+    /// [`SelfTestError::Failed`](SelfTestError::Failed) if it doesn't
+    pub fn new_with_self_test(
+        key: &Key<A>,
+        nonce: &Nonce<A, S>,
+        buffer: B,
+        writer: W,
+    ) -> Result<Self, SelfTestError>
+    where
+        A: NewAead,
+        S: NewStream<A>,
+    {
+        self_test::<A, S>(key).map_err(|_| SelfTestError::Failed)?;
+        Ok(Self::new(key, nonce, buffer, writer)?)
     }
 
     /// Constructs a new Writer using an AEAD primitive, buffer and reader
     pub fn from_aead(
         aead: A,
         nonce: &Nonce<A, S>,
+        buffer: B,
+        writer: W,
+    ) -> Result<Self, InvalidCapacity>
+    where
+        A: NewAead,
+        S: NewStream<A>,
+    {
+        Self::from_aead_with_framing(aead, nonce, DefaultFraming, buffer, writer)
+    }
+
+    /// Constructs a new Writer like [`from_aead`](Self::from_aead) from a shared AEAD primitive,
+    /// which is cloned for the Writer, for when one AEAD instance backs many streams
+    pub fn from_aead_ref(
+        aead: &A,
+        nonce: &Nonce<A, S>,
+        buffer: B,
+        writer: W,
+    ) -> Result<Self, InvalidCapacity>
+    where
+        A: NewAead + Clone,
+        S: NewStream<A>,
+    {
+        Self::from_aead(aead.clone(), nonce, buffer, writer)
+    }
+
+    /// Constructs a new Writer using the AEAD primitive a [`Keyring`](Keyring) provides for
+    /// `key_id`, for keys which never leave a secure element
+    pub fn from_keyring<K>(
+        keyring: &K,
+        key_id: &K::KeyId,
+        nonce: &Nonce<A, S>,
+        buffer: B,
+        writer: W,
+    ) -> Result<Self, KeyringError<K::Error>>
+    where
+        A: NewAead,
+        S: NewStream<A>,
+        K: Keyring<A>,
+    {
+        let aead = keyring.aead(key_id).map_err(KeyringError::Keyring)?;
+        Ok(Self::from_aead(aead, nonce, buffer, writer)?)
+    }
+
+    /// Constructs a new Writer like [`new`](Self::new), but first records the nonce with a
+    /// [`NonceTracker`](NonceTracker) kept for the key, failing with
+    /// [`NonceReuseError::Reused`](NonceReuseError::Reused) if it has been used before. The nonce
+    /// is recorded even if the buffer then turns out to be too small, so it is never handed out
+    /// twice
+    pub fn new_tracked<T>(
+        key: &Key<A>,
+        nonce: &Nonce<A, S>,
+        buffer: B,
+        writer: W,
+        tracker: &mut T,
+    ) -> Result<Self, NonceReuseError>
+    where
+        A: NewAead,
+        S: NewStream<A>,
+        T: NonceTracker + ?Sized,
+    {
+        if !tracker.record(nonce.as_slice()) {
+            return Err(NonceReuseError::Reused);
+        }
+        Ok(Self::new(key, nonce, buffer, writer)?)
+    }
+
+    /// Constructs a new Writer using an AEAD key, buffer and writer, with the nonce returned by
+    /// `next_nonce`. Passing the same generator to successive Writers lets nonces be derived from
+    /// a counter or other context without building each one up front. The nonce is prefixed to
+    /// the stream as usual, unless the Writer is built
+    /// [`without_nonce_prefix`](Self::without_nonce_prefix)
+    pub fn new_with_nonce_generator<G>(
+        key: &Key<A>,
+        next_nonce: &mut G,
+        buffer: B,
+        writer: W,
+    ) -> Result<Self, InvalidCapacity>
+    where
+        A: NewAead,
+        S: NewStream<A>,
+        G: FnMut() -> Nonce<A, S>,
+    {
+        Self::new(key, &next_nonce(), buffer, writer)
+    }
+
+    /// Constructs a Writer carrying on with a stream from where a
+    /// [`StreamState`](StreamState) exported by [`export_state`](Self::export_state) left off,
+    /// such as after a restart. The inner writer has to continue right after the output written
+    /// so far, and `aead` has to use the key the stream was started with. Options such as
+    /// [`with_padding`](Self::with_padding) or [`with_end_marker`](Self::with_end_marker) have to
+    /// be set again as before, while those which only apply to the start of a stream have no
+    /// effect. A finished stream gives a Writer which accepts no more data.
+    ///
+    /// Fails with [`ChunkIndexError::ChunkIndex`](ChunkIndexError::ChunkIndex) if the stream
+    /// can't hold as many chunks, in which case nothing is written to `writer`
+    pub fn from_state(
+        aead: A,
+        state: &StreamState<A, S>,
+        buffer: B,
+        writer: W,
+    ) -> Result<Self, ChunkIndexError>
+    where
+        A: NewAead,
+        S: NewStream<A>,
+    {
+        let mut resumed = Self::from_aead(aead, &state.nonce, buffer, writer)?;
+        if let Some(encryptor) = resumed.encryptor.as_mut() {
+            if encryptor.skip(state.chunk_index).is_err() {
+                // dropping the Writer must not start a stream over the one being resumed
+                resumed.abandoned = true;
+                return Err(ChunkIndexError::ChunkIndex);
+            }
+        }
+        resumed.chunk_index = state.chunk_index;
+        if state.finished {
+            resumed.encryptor = None;
+            resumed.state = State::Finished;
+        } else if state.chunk_index > 0 {
+            resumed.state = State::Writing;
+        }
+        Ok(resumed)
+    }
+}
+
+impl<A, B, W, S, F> EncryptBufWriter<A, B, W, S, F>
+where
+    A: AeadInPlace,
+    B: CappedBuffer,
+    W: Write,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    /// Constructs a new Writer using an AEAD primitive, a custom [`Framing`](Framing), buffer and
+    /// reader
+    ///
+    /// Only as much of the buffer's capacity is used as the framing's chunk headers can describe,
+    /// see [`Framing::max_chunk_len`](Framing::max_chunk_len)
+    pub fn from_aead_with_framing(
+        aead: A,
+        nonce: &Nonce<A, S>,
+        framing: F,
         mut buffer: B,
         writer: W,
     ) -> Result<Self, InvalidCapacity>
@@ -79,22 +478,57 @@ where
         S: NewStream<A>,
     {
         buffer.truncate(0);
-        let capacity = Self::capacity_for_buffer(&buffer)?;
+        let overhead = <<A as AeadCore>::TagSize as Unsigned>::to_usize();
+        let capacity = Self::capacity_for_buffer(&buffer, &framing, overhead)?;
         Ok(Self {
             encryptor: Some(Encryptor::from_aead(aead, nonce)),
             nonce: nonce.clone(),
-            writer,
+            writer: ManuallyDrop::new(writer),
+            framing,
             buffer,
             capacity,
+            overhead,
             state: State::Init,
+            chunk_index: 0,
+            bytes_written: 0,
+            replaced_bytes: 0,
+            reserved_header: 0,
+            nonce_prefix: true,
+            padded: false,
+            end_marker: false,
+            key_commitment: false,
+            flush_threshold: None,
+            threshold_error: None,
+            associated: AssociatedData::default(),
+            pending: None,
+            stats: Stats::default(),
+            #[cfg(feature = "std")]
+            overwrite: None,
+            #[cfg(feature = "crc-footer")]
+            footer: None,
+            #[cfg(feature = "alloc")]
+            on_drop_error: None,
+            drop_policy: DropPolicy::Ignore,
+            abandoned: false,
+            writer_taken: false,
+            #[cfg(feature = "alloc")]
+            rekey: None,
+            #[cfg(feature = "alloc")]
+            next_encryptor: None,
         })
     }
 
-    fn capacity_for_buffer(buffer: &B) -> Result<usize, InvalidCapacity> {
+    /// The plaintext capacity of each chunk, clamped to what the framing's chunk header can
+    /// describe, leaving room for the `overhead` the encryption adds to each chunk
+    fn capacity_for_buffer(
+        buffer: &B,
+        framing: &F,
+        overhead: usize,
+    ) -> Result<usize, InvalidCapacity> {
         let capacity = buffer
             .capacity()
-            .min(u32::MAX as usize)
-            .checked_sub(<<A as AeadCore>::TagSize as Unsigned>::to_usize())
+            .min(framing.max_chunk_len())
+            .checked_sub(overhead)
             .ok_or(InvalidCapacity)?;
         if capacity < 1 {
             Err(InvalidCapacity)
@@ -103,141 +537,2567 @@ where
         }
     }
 
-    /// Gets a reference to the inner writer
-    pub fn inner(&self) -> &W {
-        &self.writer
+    /// The number of bytes encryption adds to each chunk: the AEAD tag, plus whatever was set
+    /// with [`with_stream_overhead`](Self::with_stream_overhead). The plaintext of a chunk is at
+    /// most the buffer's capacity minus this overhead
+    pub fn chunk_overhead(&self) -> usize {
+        self.overhead
     }
 
-    /// Consumes the Writer and returns the inner writer
-    pub fn into_inner(mut self) -> Result<W, IntoInnerError<Self, W::Error>> {
-        match self.flush_buffer(true) {
-            Ok(()) => {
-                let inner = unsafe { ptr::read(&self.writer) };
-                mem::forget(self);
-                Ok(inner)
+    /// Leaves room in each chunk for `len` bytes which a custom
+    /// [`StreamPrimitive`](StreamPrimitive) appends to the ciphertext on top of the AEAD tag.
+    /// Fails if the buffer is too small to hold any plaintext next to the overhead, or if data
+    /// has already been written. The stream is abandoned then, rather than finalized as the
+    /// Writer is dropped
+    pub fn with_stream_overhead(mut self, len: usize) -> Result<Self, InvalidCapacity> {
+        match self.capacity_with_stream_overhead(len) {
+            Ok((overhead, capacity)) => {
+                self.overhead = overhead;
+                self.capacity = capacity;
+                Ok(self)
+            }
+            Err(err) => {
+                self.abandoned = true;
+                Err(err)
             }
-            Err(err) => Err(IntoInnerError::new(self, err)),
         }
     }
 
-    fn capacity_remaining(&self) -> usize {
-        self.capacity - self.buffer.len()
+    /// The overhead and plaintext capacity of chunks with `len` bytes of stream overhead
+    fn capacity_with_stream_overhead(&self, len: usize) -> Result<(usize, usize), InvalidCapacity> {
+        if !self.buffer.is_empty() || !matches!(self.state, State::Init) {
+            return Err(InvalidCapacity);
+        }
+        let overhead = <<A as AeadCore>::TagSize as Unsigned>::to_usize()
+            .checked_add(len)
+            .ok_or(InvalidCapacity)?;
+        let capacity = Self::capacity_for_buffer(&self.buffer, &self.framing, overhead)?;
+        if self.padded && capacity <= PADDING_HEADER_LEN {
+            return Err(InvalidCapacity);
+        }
+        Ok((overhead, capacity))
     }
 
-    fn flush_buffer(&mut self, last: bool) -> Result<(), Error<W::Error>> {
-        if matches!(self.state, State::Finished) {
-            return Ok(());
-        }
+    /// Sets a hook which is called with the error if finalizing the stream fails when the Writer
+    /// is dropped. `Drop` has no way of returning the error, so without a hook such a failure
+    /// would go unnoticed
+    #[cfg(feature = "alloc")]
+    pub fn on_drop_error(mut self, hook: impl FnMut(&Error<W::Error>) + Send + 'static) -> Self {
+        self.on_drop_error = Some(Box::new(hook));
+        self
+    }
 
-        if last {
-            self.encryptor
-                .take()
-                .ok_or(Error::Aead)?
-                .encrypt_last_in_place(&[], &mut self.buffer)
-                .map_err(|_| Error::Aead)?;
-        } else {
-            self.encryptor
-                .as_mut()
-                .ok_or(Error::Aead)?
-                .encrypt_next_in_place(&[], &mut self.buffer)
-                .map_err(|_| Error::Aead)?;
-        }
+    /// Sets what happens if finalizing the stream fails when the Writer is dropped, after the
+    /// [`on_drop_error`](Self::on_drop_error) hook was called. By default the failure is
+    /// ignored, while [`DropPolicy::Panic`](DropPolicy::Panic) makes sure a stream which was cut
+    /// short doesn't go unnoticed. Finalizing with [`flush_with`](Self::flush_with) or
+    /// [`into_inner`](Self::into_inner) returns the error instead
+    pub fn with_drop_policy(mut self, policy: DropPolicy) -> Self {
+        self.drop_policy = policy;
+        self
+    }
 
-        if matches!(self.state, State::Init) {
-            self.writer.write_all(self.nonce.as_slice())?;
-            self.state = State::Writing;
-        }
+    /// Sets the KDF or ratchet [`rekey`](Self::rekey) derives the AEAD of each new epoch with.
+    /// It is called with the number of the epoch being entered, starting at `1`, and has to
+    /// derive the same AEADs as the one given to
+    /// [`DecryptBufReader::with_rekey`](crate::DecryptBufReader::with_rekey)
+    #[cfg(feature = "alloc")]
+    pub fn with_rekey(mut self, kdf: impl FnMut(u32) -> A + Send + Sync + 'static) -> Self {
+        self.rekey = Some(Rekey::new(kdf));
+        self
+    }
 
-        self.writer
-            .write_all(&(self.buffer.len() as u32).to_be_bytes())?;
-        self.writer.write_all(self.buffer.as_ref())?;
-        if last {
-            self.state = State::Finished;
-        }
+    /// Authenticates the bytes `aad` returns for each chunk as associated data of the chunk, after
+    /// any the stream authenticates itself. It is called with the index of the chunk in the
+    /// stream, starting at `0` and counting the chunks of [`rekey`](Self::rekey) and
+    /// [`with_key_commitment`](Self::with_key_commitment) as well, and covers constant, index and
+    /// context based associated data alike. The stream has to be read with
+    /// [`DecryptBufReader::with_aad_provider`](crate::DecryptBufReader::with_aad_provider) and a
+    /// closure returning the same bytes for each index
+    #[cfg(feature = "alloc")]
+    pub fn with_aad_provider(
+        mut self,
+        aad: impl FnMut(u32) -> Vec<u8> + Send + Sync + 'static,
+    ) -> Self {
+        self.associated.provider = Some(Box::new(aad));
+        self
+    }
 
-        self.buffer.truncate(0);
-        Ok(())
+    /// Omits the stream header carrying the nonce, for when the nonce is communicated out of band.
+    /// The stream then has to be read with
+    /// [`DecryptBufReader::from_aead_with_nonce`](crate::DecryptBufReader::from_aead_with_nonce)
+    pub fn without_nonce_prefix(mut self) -> Self {
+        self.nonce_prefix = false;
+        self
     }
 
-    fn write(&mut self, buf: &[u8]) -> Result<usize, Error<W::Error>> {
-        if matches!(self.state, State::Finished) {
-            return Err(Error::Aead);
+    /// Pads every chunk but the last to the full capacity of the buffer, so that an observer
+    /// only sees uniformly sized chunks. The length of the data is stored in the first 4 bytes of
+    /// each chunk's plaintext, leaving 4 bytes less of the buffer for data. The stream has to be
+    /// read with [`DecryptBufReader::with_padding`](crate::DecryptBufReader::with_padding).
+    /// Fails if the buffer is too small to hold any data next to the length, or if data has
+    /// already been buffered. The stream is abandoned then, rather than finalized as the Writer is
+    /// dropped
+    pub fn with_padding(mut self) -> Result<Self, InvalidCapacity> {
+        if self.padded {
+            return Ok(self);
         }
-        if buf.len() > self.capacity_remaining() {
-            self.flush_buffer(false)?;
+        if self.capacity <= PADDING_HEADER_LEN || !self.buffer.is_empty() {
+            self.abandoned = true;
+            return Err(InvalidCapacity);
         }
-        let bytes_to_write = buf.len().min(self.capacity_remaining());
-        self.buffer
-            .extend_from_slice(&buf[..bytes_to_write])
-            .map_err(|_| Error::Aead)?;
-        Ok(bytes_to_write)
+        self.padded = true;
+        Ok(self)
     }
 
-    fn flush(&mut self) -> Result<(), Error<W::Error>> {
-        self.flush_buffer(true)?;
-        self.writer.flush()?;
-        Ok(())
+    /// Authenticates the total length of the plaintext sealed in all chunks as associated data of
+    /// the last chunk. Every chunk is authenticated on its own, so this gives the reader a second
+    /// guarantee on top of the last chunk flag that it has seen the complete stream. The stream has
+    /// to be read with
+    /// [`DecryptBufReader::with_total_length`](crate::DecryptBufReader::with_total_length)
+    pub fn with_total_length(mut self) -> Self {
+        self.associated.total_length.get_or_insert(0);
+        self
     }
-}
 
-impl<A, B, W, S> Drop for EncryptBufWriter<A, B, W, S>
-where
-    A: AeadInPlace,
-    B: CappedBuffer,
-    W: Write,
-    S: StreamPrimitive<A>,
-    A::NonceSize: Sub<S::NonceOverhead>,
-    NonceSize<A, S>: ArrayLength<u8>,
-{
-    fn drop(&mut self) {
-        let _ = self.flush_buffer(true);
+    /// Authenticates the stream header, such as the nonce and any magic bytes the
+    /// [`Framing`](Framing) writes, as associated data of the first chunk, so that tampering with
+    /// the header fails decryption. The header has to fit into 24 bytes. The stream has to be read
+    /// with [`DecryptBufReader::with_header_aad`](crate::DecryptBufReader::with_header_aad)
+    pub fn with_header_aad(mut self) -> Self {
+        self.associated.header.get_or_insert(Stage::new());
+        self
     }
-}
 
-#[cfg(feature = "std")]
-impl<A, B, W, S> std::io::Write for EncryptBufWriter<A, B, W, S>
-where
-    A: AeadInPlace,
-    B: CappedBuffer,
-    W: Write,
-    W::Error: Into<std::io::Error>,
-    S: StreamPrimitive<A>,
-    A::NonceSize: Sub<S::NonceOverhead>,
-    NonceSize<A, S>: ArrayLength<u8>,
-{
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        Ok(self.write(buf)?)
+    /// Sends `metadata` in the clear right after the stream header, and authenticates it as
+    /// associated data of the first chunk, like the cleartext header of formats such as age. The
+    /// stream has to be read with
+    /// [`DecryptBufReader::with_metadata`](crate::DecryptBufReader::with_metadata). The
+    /// non-blocking `try_*` methods only support metadata which fits along with the stream header
+    /// into 32 bytes
+    #[cfg(feature = "alloc")]
+    pub fn with_metadata(mut self, metadata: impl Into<Vec<u8>>) -> Self {
+        self.associated.metadata = Some(metadata.into());
+        self.associated.metadata_pending = true;
+        self
     }
-    fn flush(&mut self) -> std::io::Result<()> {
-        Ok(self.flush()?)
+
+    /// Ends the stream with an empty chunk header after the last chunk, so that a Reader stops
+    /// right after the stream instead of at the end of the inner reader. This allows storing
+    /// streams back to back, to be read one after the other with
+    /// [`MessageReader`](crate::MessageReader). Readers don't need any configuration for this.
+    /// With a framing marking the last chunk, such as
+    /// [`LastChunkMarkFraming`](crate::LastChunkMarkFraming), the stream already ends there
+    pub fn with_end_marker(mut self) -> Self {
+        self.end_marker = true;
+        self
     }
-}
 
-#[cfg(not(feature = "std"))]
-impl<A, B, W, S> Write for EncryptBufWriter<A, B, W, S>
-where
-    A: AeadInPlace,
-    B: CappedBuffer,
-    W: Write,
-    S: StreamPrimitive<A>,
-    A::NonceSize: Sub<S::NonceOverhead>,
-    NonceSize<A, S>: ArrayLength<u8>,
-{
-    type Error = Error<W::Error>;
-    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
-        Ok(self.write(buf)?)
+    /// Writes out the buffered data as a chunk at the end of any write which leaves at least
+    /// `len` bytes of plaintext buffered, rather than holding it until the buffer is full. This is
+    /// a low-latency mode for interactive streams such as chats or terminals, where `1` sends
+    /// every write as a chunk of its own, trading the overhead of more chunks for data not
+    /// waiting on the buffer to fill. A Reader only decrypts a chunk once the header of the next
+    /// one or the end of the stream follows it, so a chunk is read on the other side along with
+    /// the next write, or with a [`FlushMode::Finalize`](FlushMode::Finalize).
+    ///
+    /// Only applies to the blocking writes. The data of a write is buffered before its chunk is
+    /// written out, so if that fails the write still succeeds and the error is returned by the
+    /// next call instead
+    pub fn with_flush_threshold(mut self, len: usize) -> Self {
+        self.flush_threshold = Some(len);
+        self
     }
-    fn flush(&mut self) -> Result<(), Self::Error> {
-        Ok(self.flush()?)
+
+    /// Starts the stream with a chunk committing to the key, so that it can't be crafted to
+    /// decrypt under more than one key, see [Key commitment](crate#key-commitment). It costs one
+    /// chunk of 32 bytes plus the tag, and has to be checked with
+    /// [`DecryptBufReader::with_key_commitment`](crate::DecryptBufReader::with_key_commitment).
+    /// The non-blocking `try_*` methods can't stage the chunk and fail on such a stream
+    pub fn with_key_commitment(mut self) -> Self {
+        self.key_commitment = true;
+        self
     }
-    fn write_all(&mut self, mut buf: &[u8]) -> Result<(), Self::Error> {
-        while !buf.is_empty() {
-            match self.write(buf) {
-                Ok(0) => return Err(Error::Aead),
-                Ok(n) => buf = &buf[n..],
-                Err(e) => return Err(e),
-            }
+
+    /// Ends the stream with a footer holding a CRC32 and the total length of the encrypted
+    /// chunks, so that corruption can be detected cheaply before decrypting with
+    /// [`DecryptBufReader::check_crc_footer`](crate::DecryptBufReader::check_crc_footer). The
+    /// footer follows an empty chunk header marking the end of the chunks, and has to be read with
+    /// [`DecryptBufReader::with_crc_footer`](crate::DecryptBufReader::with_crc_footer)
+    #[cfg(feature = "crc-footer")]
+    pub fn with_crc_footer(mut self) -> Self {
+        self.footer = Some(Footer::default());
+        self
+    }
+
+    /// Reserves `len` zeroed bytes at the start of the output, ahead of the stream header. The
+    /// region can be filled in once the stream is complete with
+    /// [`finish_with_header`](Self::finish_with_header), or by seeking back to it, which is
+    /// useful for container formats whose header depends on the encrypted contents
+    pub fn with_reserved_header(mut self, len: usize) -> Self {
+        self.reserved_header = len;
+        self
+    }
+
+    /// Finalizes the stream, then seeks back to the region reserved with
+    /// [`with_reserved_header`](Self::with_reserved_header) and fills it with the header returned
+    /// by `header`, which is given the total number of chunks written. The header must not be
+    /// longer than the reserved region, any remaining bytes are left zeroed. Returns the inner
+    /// writer positioned at the end of the stream. Fails once the writer has been swapped out with
+    /// [`replace_writer`](Self::replace_writer), as the reserved region went to the old one
+    #[cfg(feature = "std")]
+    #[allow(clippy::result_large_err)]
+    pub fn finish_with_header<H>(
+        mut self,
+        header: impl FnOnce(u32) -> H,
+    ) -> Result<W, IntoInnerError<Self, W::Error>>
+    where
+        H: AsRef<[u8]>,
+        W: std::io::Seek,
+        W::Error: From<std::io::Error>,
+    {
+        if let Err(err) = self.flush_buffer(true) {
+            return Err(IntoInnerError::new(self, err));
+        }
+        let header = header(self.chunk_index);
+        let header = header.as_ref();
+        if header.len() > self.reserved_header {
+            let err = std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "header is larger than the reserved region",
+            );
+            return Err(IntoInnerError::new(self, Error::Io(err.into())));
+        }
+        if let Err(err) = self.backfill_header(header) {
+            return Err(IntoInnerError::new(self, err));
+        }
+        self.into_inner()
+    }
+
+    #[cfg(feature = "std")]
+    fn backfill_header(&mut self, header: &[u8]) -> Result<(), Error<W::Error>>
+    where
+        W: std::io::Seek,
+        W::Error: From<std::io::Error>,
+    {
+        use std::io::SeekFrom;
+        let end = match self.overwrite.take() {
+            Some(overwrite) => overwrite.end,
+            None => self.writer.stream_position().map_err(W::Error::from)?,
+        };
+        // the reserved region is only in the writer if it hasn't been replaced since
+        let start = match self.replaced_bytes {
+            0 => end.checked_sub(self.bytes_written),
+            _ => None,
+        }
+        .ok_or_else(|| {
+            let err = std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "the reserved region isn't part of the inner writer",
+            );
+            Error::Io(err.into())
+        })?;
+        self.writer
+            .seek(SeekFrom::Start(start))
+            .map_err(W::Error::from)?;
+        self.writer.write_all(header)?;
+        self.writer
+            .seek(SeekFrom::Start(end))
+            .map_err(W::Error::from)?;
+        Ok(())
+    }
+
+    /// Gets a reference to the inner writer
+    pub fn inner(&self) -> &W {
+        &self.writer
+    }
+
+    /// The bytes held in the buffer which haven't been written out yet. This is the plaintext of
+    /// the chunk in progress, except once the chunk has been sealed but writing it out failed,
+    /// in which case it is the chunk's ciphertext, tag included. Writing those bytes out after
+    /// the chunk header completes the stream
+    pub fn buffered_bytes(&self) -> &[u8] {
+        self.buffer.as_ref()
+    }
+
+    /// How many bytes the buffer holds, the length of
+    /// [`buffered_bytes`](Self::buffered_bytes), for diagnosing a stream which stalls
+    pub fn buffer_len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// The most the buffer holds, which is the longest encrypted chunk, tag included, the Writer
+    /// writes. The plaintext of a chunk is this less the
+    /// [`chunk_overhead`](Self::chunk_overhead) and any padding header
+    pub fn buffer_capacity(&self) -> usize {
+        self.buffer.capacity().min(self.framing.max_chunk_len())
+    }
+
+    /// The chunks sealed so far, including one whose writing out is still in progress
+    pub fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// Whether the stream has been finalized and written out in full, after which any further
+    /// write fails
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, State::Finished)
+    }
+
+    /// Exports where the stream stands, for resuming it with [`from_state`](Self::from_state)
+    /// once this Writer is gone. The state holds the nonce and the number of chunks written, but
+    /// no key material.
+    ///
+    /// Only a stream with nothing buffered or waiting to be written out can be resumed, so this
+    /// returns `None` until [`flush_chunk`](Self::flush_chunk) has written out the buffered data.
+    /// It is also `None` once the key was changed with [`rekey`](Self::rekey), which a state
+    /// doesn't cover. Dropping the Writer finalizes the stream, so use
+    /// [`into_state`](Self::into_state) to part with it
+    pub fn export_state(&self) -> Option<StreamState<A, S>> {
+        if !self.buffer.is_empty() || self.pending.is_some() {
+            return None;
+        }
+        #[cfg(feature = "alloc")]
+        if self.rekey.is_some() {
+            return None;
+        }
+        Some(StreamState {
+            nonce: self.nonce.clone(),
+            chunk_index: self.chunk_index,
+            finished: matches!(self.state, State::Finished),
+        })
+    }
+
+    /// Gives up on the stream after a failure part way through, so that dropping the Writer no
+    /// longer finalizes it. A stream which was cut short must not end with a last chunk, or it
+    /// would authenticate as complete
+    #[cfg(feature = "alloc")]
+    pub(crate) fn abandon(&mut self) {
+        self.abandoned = true;
+    }
+
+    /// Consumes the Writer and returns the inner writer
+    // Like `std::io::BufWriter`, the Writer is handed back by value on failure
+    #[allow(clippy::result_large_err)]
+    pub fn into_inner(mut self) -> Result<W, IntoInnerError<Self, W::Error>> {
+        match self.flush_buffer(true) {
+            Ok(_) => Ok(self.take_writer()),
+            Err(err) => Err(IntoInnerError::new(self, err)),
+        }
+    }
+
+    /// Writes out the buffered data as a chunk and consumes the Writer without finalizing the
+    /// stream, returning where the stream stands, as [`export_state`](Self::export_state) would,
+    /// along with the inner writer. Fails with [`Error::Aead`](Error::Aead) once the key was
+    /// changed with [`rekey`](Self::rekey), or while the Writer has been moved back into the
+    /// output by seeking
+    #[allow(clippy::result_large_err)]
+    pub fn into_state(mut self) -> Result<Parted<A, S, W>, IntoInnerError<Self, W::Error>> {
+        if let Err(err) = self
+            .check_not_overwriting()
+            .and_then(|_| self.flush_chunk())
+        {
+            return Err(IntoInnerError::new(self, err));
+        }
+        match self.export_state() {
+            Some(state) => Ok((state, self.take_writer())),
+            None => Err(IntoInnerError::new(self, Error::Aead)),
+        }
+    }
+
+    /// Finalizes the stream as the Writer is dropped, passing any error to the
+    /// [`on_drop_error`](Self::on_drop_error) hook. Returns whether it succeeded
+    fn finalize_on_drop(&mut self) -> bool {
+        let result = self.flush_buffer(true);
+        #[cfg(feature = "alloc")]
+        if let (Err(err), Some(hook)) = (&result, self.on_drop_error.as_mut()) {
+            hook(err);
+        }
+        result.is_ok()
+    }
+
+    /// Takes the inner writer out and drops everything else without finalizing the stream
+    fn take_writer(mut self) -> W {
+        self.writer_taken = true;
+        // safety: `writer_taken` keeps `Drop` from touching the writer again
+        unsafe { ManuallyDrop::take(&mut self.writer) }
+    }
+
+    #[inline]
+    fn capacity_remaining(&self) -> usize {
+        debug_assert!(
+            self.buffer.len() <= self.capacity,
+            "buffered {} bytes past a capacity of {}",
+            self.buffer.len(),
+            self.capacity
+        );
+        self.capacity.saturating_sub(self.buffer.len())
+    }
+
+    /// Makes room for the length field at the start of a padded chunk
+    #[inline]
+    fn start_chunk(&mut self) -> Result<(), Error<W::Error>> {
+        if self.padded && self.buffer.is_empty() {
+            self.buffer
+                .extend_from_slice(&[0; PADDING_HEADER_LEN])
+                .map_err(|_| Error::Aead)?;
+        }
+        Ok(())
+    }
+
+    /// Fills in the length field of a padded chunk and pads it to the full capacity unless it is
+    /// the last one
+    fn pad_chunk(&mut self, last: bool) -> Result<(), Error<W::Error>> {
+        self.start_chunk()?;
+        let len = (self.buffer.len() - PADDING_HEADER_LEN) as u32;
+        self.buffer.as_mut()[..PADDING_HEADER_LEN].copy_from_slice(&len.to_be_bytes());
+        if !last {
+            self.fill_zeroes()?;
+        }
+        Ok(())
+    }
+
+    /// Extends the buffer with zeroes up to its capacity
+    fn fill_zeroes(&mut self) -> Result<(), Error<W::Error>> {
+        let zeroes = [0u8; 32];
+        while self.buffer.len() < self.capacity {
+            let len = (self.capacity - self.buffer.len()).min(zeroes.len());
+            self.buffer
+                .extend_from_slice(&zeroes[..len])
+                .map_err(|_| Error::Aead)?;
+        }
+        Ok(())
+    }
+
+    /// Seals the chunk committing to the key into `out` if the stream starts with one and hasn't
+    /// started yet, returning its length or `0`
+    fn seal_commitment(&mut self, out: &mut [u8]) -> Result<usize, Error<W::Error>> {
+        if !self.key_commitment || !matches!(self.state, State::Init) {
+            return Ok(0);
+        }
+        let mut chunk = SliceBuffer::new(out);
+        chunk
+            .extend_from_slice(&[0; COMMITMENT_LEN])
+            .map_err(|_| Error::Aead)?;
+        self.encryptor
+            .as_mut()
+            .ok_or(Error::Aead)?
+            .encrypt_next_in_place(COMMITMENT_AAD, &mut chunk)
+            .map_err(|_| Error::Aead)?;
+        let len = chunk.len();
+        self.chunk_index = self.chunk_index.wrapping_add(1);
+        self.stats.add_chunk(0, len);
+        Ok(len)
+    }
+
+    /// Pads and encrypts the buffer in place, counting the chunk in the [`Stats`](Stats)
+    fn seal_chunk(&mut self, last: bool) -> Result<(), Error<W::Error>> {
+        // a chunk larger than the capacity would seal, but couldn't be read back
+        if self.buffer.len() > self.capacity {
+            return Err(Error::Aead);
+        }
+        let plaintext = match self.padded {
+            true => self.buffer.len().saturating_sub(PADDING_HEADER_LEN),
+            false => self.buffer.len(),
+        };
+        self.encrypt_buffer(last)?;
+        self.stats.add_chunk(plaintext, self.buffer.len());
+        Ok(())
+    }
+
+    fn encrypt_buffer(&mut self, last: bool) -> Result<(), Error<W::Error>> {
+        if matches!(self.state, State::Init) && self.nonce_prefix {
+            if let Some(header) = self.associated.header.as_mut() {
+                let (framing, nonce) = (&mut self.framing, &self.nonce);
+                header.write_with(|out| framing.write_header(out, nonce.as_slice()))?;
+            }
+        }
+        #[cfg(feature = "alloc")]
+        if let Some(next) = self.next_encryptor.take() {
+            return self
+                .encryptor
+                .replace(next)
+                .ok_or(Error::Aead)?
+                .encrypt_last_in_place(REKEY_AAD, &mut self.buffer)
+                .map_err(|_| Error::Aead);
+        }
+
+        if self.padded {
+            self.pad_chunk(last)?;
+        }
+        let (encryptor, buffer) = (&mut self.encryptor, &mut self.buffer);
+        self.associated
+            .next(self.chunk_index, buffer.len(), last, |aad| {
+                if last {
+                    encryptor
+                        .take()
+                        .ok_or(aead::Error)?
+                        .encrypt_last_in_place(aad, buffer)
+                } else {
+                    encryptor
+                        .as_mut()
+                        .ok_or(aead::Error)?
+                        .encrypt_next_in_place(aad, buffer)
+                }
+            })?
+            .map_err(|_| Error::Aead)
+    }
+
+    /// Fails while the inner writer has been moved back into the output by seeking, where no
+    /// chunk may be written
+    fn check_not_overwriting(&self) -> Result<(), Error<W::Error>> {
+        #[cfg(feature = "std")]
+        if self.overwrite.is_some() {
+            return Err(Error::Aead);
+        }
+        Ok(())
+    }
+
+    /// Whether an empty chunk header follows the last chunk as an end marker. The CRC footer
+    /// already starts with one, and a framing marking the last chunk needs none
+    fn writes_end_marker(&self) -> bool {
+        if self.framing.marks_last_chunk() {
+            return false;
+        }
+        #[cfg(feature = "crc-footer")]
+        if self.footer.is_some() {
+            return false;
+        }
+        self.end_marker
+    }
+
+    /// Records a chunk which has been written out in full and clears the buffer. The inner
+    /// writer is closed after the last chunk
+    fn finish_chunk(&mut self, last: bool, written: usize) -> Result<(), Error<W::Error>> {
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            chunk_index = self.chunk_index,
+            len = self.buffer.len(),
+            last,
+            "encrypted chunk written"
+        );
+        self.bytes_written += written as u64;
+        self.chunk_index = self.chunk_index.wrapping_add(1);
+        self.buffer.truncate(0);
+        if last {
+            self.state = State::Finished;
+            self.writer.close()?;
+        }
+        Ok(())
+    }
+
+    /// Encrypts the buffer and writes it out as a chunk, returning the number of bytes written to
+    /// the inner writer
+    fn flush_buffer(&mut self, last: bool) -> Result<usize, Error<W::Error>> {
+        if let Some(err) = self.threshold_error.take() {
+            return Err(err);
+        }
+        self.drain_pending(true)?;
+        if matches!(self.state, State::Finished) {
+            return Ok(0);
+        }
+        self.check_not_overwriting()?;
+
+        // room for the zeroes and any tag of up to 32 bytes
+        let mut commitment = [0u8; 2 * COMMITMENT_LEN];
+        let commitment_len = self.seal_commitment(&mut commitment)?;
+        self.seal_chunk(last)?;
+
+        let mut written = 0;
+        if matches!(self.state, State::Init) {
+            let zeroes = [0u8; 32];
+            while written < self.reserved_header {
+                let len = (self.reserved_header - written).min(zeroes.len());
+                self.writer.write_all(&zeroes[..len])?;
+                written += len;
+            }
+            if self.nonce_prefix {
+                written += self
+                    .framing
+                    .write_header(&mut *self.writer, self.nonce.as_slice())?;
+            }
+            #[cfg(feature = "alloc")]
+            if let Some(metadata) = self.associated.metadata.as_ref() {
+                self.writer.write_all(&metadata_prefix(metadata)?)?;
+                self.writer.write_all(metadata)?;
+                written += 4 + metadata.len();
+            }
+            if commitment_len > 0 {
+                written += self
+                    .framing
+                    .write_chunk_header(&mut *self.writer, commitment_len)?;
+                self.writer.write_all(&commitment[..commitment_len])?;
+                written += commitment_len;
+            }
+            self.state = State::Writing;
+        }
+
+        written += match last {
+            true => self
+                .framing
+                .write_last_chunk_header(&mut *self.writer, self.buffer.len())?,
+            false => self
+                .framing
+                .write_chunk_header(&mut *self.writer, self.buffer.len())?,
+        };
+        self.writer.write_all(self.buffer.as_ref())?;
+        written += self.buffer.len();
+        #[cfg(feature = "crc-footer")]
+        if let Some(footer) = self.footer.as_mut() {
+            footer.update(self.buffer.as_ref());
+            if last {
+                written += self.framing.write_chunk_header(&mut *self.writer, 0)?;
+                let footer = footer.to_bytes();
+                self.writer.write_all(&footer)?;
+                written += footer.len();
+            }
+        }
+        if last && self.writes_end_marker() {
+            written += self.framing.write_chunk_header(&mut *self.writer, 0)?;
+        }
+        self.finish_chunk(last, written)?;
+        Ok(written)
+    }
+
+    /// Encrypts the buffer and stages the chunk's output to be written by
+    /// [`drain_pending`](Self::drain_pending)
+    fn stage_chunk(&mut self, last: bool) -> Result<(), Error<W::Error>> {
+        self.check_not_overwriting()?;
+        if self.key_commitment && matches!(self.state, State::Init) {
+            return Err(Error::Aead);
+        }
+        self.seal_chunk(last)?;
+
+        let mut pending = Pending {
+            head: Stage::new(),
+            body: 0,
+            last,
+            tail: false,
+        };
+        if matches!(self.state, State::Init) {
+            if self.nonce_prefix {
+                let (framing, nonce) = (&mut self.framing, &self.nonce);
+                pending
+                    .head
+                    .write_with(|out| framing.write_header(out, nonce.as_slice()))?;
+            }
+            #[cfg(feature = "alloc")]
+            if let Some(metadata) = self.associated.metadata.as_ref() {
+                let prefix = metadata_prefix::<W::Error>(metadata)?;
+                pending
+                    .head
+                    .write_with(|out| out.write_all(&prefix).map(|_| 0))?;
+                pending
+                    .head
+                    .write_with(|out| out.write_all(metadata).map(|_| 0))?;
+            }
+            self.state = State::Writing;
+        }
+        let (framing, len) = (&mut self.framing, self.buffer.len());
+        pending.head.write_with(|out| match last {
+            true => framing.write_last_chunk_header(out, len),
+            false => framing.write_chunk_header(out, len),
+        })?;
+        #[cfg(feature = "crc-footer")]
+        if let Some(footer) = self.footer.as_mut() {
+            footer.update(self.buffer.as_ref());
+        }
+        self.pending = Some(pending);
+        Ok(())
+    }
+
+    /// Writes out the staged output of a sealed chunk. Without `blocking` only single writes are
+    /// made, and `false` is returned if the inner writer stops accepting data before the chunk is
+    /// complete. The progress is kept, also when writing fails, so this can be called again
+    fn drain_pending(&mut self, blocking: bool) -> Result<bool, Error<W::Error>> {
+        let end_marker = self.writes_end_marker();
+        let pending = match self.pending.as_mut() {
+            Some(pending) => pending,
+            None => return Ok(true),
+        };
+        // The reserved header comes first in the output, so whatever part of it hasn't been
+        // written yet is still missing from the count of bytes written
+        let mut reserved = match self.chunk_index {
+            0 => self
+                .reserved_header
+                .saturating_sub(self.bytes_written as usize),
+            _ => 0,
+        };
+        let writer = &mut *self.writer;
+        let bytes_written = &mut self.bytes_written;
+        let mut put = |bytes: &[u8]| -> Result<usize, W::Error> {
+            let written = if blocking {
+                writer.write_all(bytes)?;
+                bytes.len()
+            } else {
+                writer.write(bytes)?
+            };
+            *bytes_written += written as u64;
+            Ok(written)
+        };
+
+        let zeroes = [0u8; 32];
+        while reserved > 0 {
+            let written = put(&zeroes[..reserved.min(zeroes.len())])?;
+            if written == 0 {
+                return Ok(false);
+            }
+            reserved -= written;
+        }
+        while !pending.head.remaining().is_empty() {
+            let written = put(pending.head.remaining())?;
+            if written == 0 {
+                return Ok(false);
+            }
+            pending.head.consume(written);
+        }
+        while pending.body < self.buffer.len() {
+            let written = put(&self.buffer.as_ref()[pending.body..])?;
+            if written == 0 {
+                return Ok(false);
+            }
+            pending.body += written;
+        }
+        #[cfg(feature = "crc-footer")]
+        if let (Some(footer), true, false) = (self.footer.as_ref(), pending.last, pending.tail) {
+            let framing = &mut self.framing;
+            pending.head.clear();
+            pending
+                .head
+                .write_with(|out| framing.write_chunk_header(out, 0))?;
+            pending
+                .head
+                .write_with(|out| out.write_all(&footer.to_bytes()).map(|_| 0))?;
+            pending.tail = true;
+        }
+        if pending.last && !pending.tail && end_marker {
+            let framing = &mut self.framing;
+            pending.head.clear();
+            pending
+                .head
+                .write_with(|out| framing.write_chunk_header(out, 0))?;
+            pending.tail = true;
+        }
+        while !pending.head.remaining().is_empty() {
+            let written = put(pending.head.remaining())?;
+            if written == 0 {
+                return Ok(false);
+            }
+            pending.head.consume(written);
+        }
+
+        let last = pending.last;
+        self.pending = None;
+        self.finish_chunk(last, 0)?;
+        Ok(true)
+    }
+
+    /// Writes as much of the chunk in progress as the inner writer accepts without blocking,
+    /// returning whether the chunk is complete
+    fn try_drain_pending(&mut self) -> Result<bool, Error<W::Error>>
+    where
+        W::Error: WouldBlock,
+    {
+        Ok(nonblocking(self.drain_pending(false))?.unwrap_or(false))
+    }
+
+    /// Buffers as much of `buf` as fits without blocking, for driving the Writer from a custom
+    /// event loop over a non-blocking inner writer. Once the buffer is full the chunk is encrypted
+    /// and written out with single writes. If the inner writer would block or accepts nothing,
+    /// [`Progress::Pending`](Progress::Pending) is returned and the rest of the chunk is written
+    /// on the next call to a `try_*` method. Unlike `write`, chunks are always filled to the
+    /// capacity of the buffer.
+    ///
+    /// The blocking methods first finish writing any chunk left in progress, so the two can be
+    /// mixed
+    pub fn try_write(&mut self, buf: &[u8]) -> Result<Progress, Error<W::Error>>
+    where
+        W::Error: WouldBlock,
+    {
+        let mut flushed = false;
+        if self.pending.is_some() {
+            if !self.try_drain_pending()? {
+                return Ok(Progress::Pending);
+            }
+            flushed = true;
+        }
+        if matches!(self.state, State::Finished) {
+            return Err(Error::Aead);
+        }
+        if !buf.is_empty() && self.capacity_remaining() == 0 {
+            self.stage_chunk(false)?;
+            if !self.try_drain_pending()? {
+                return Ok(if flushed {
+                    Progress::Flushed(0)
+                } else {
+                    Progress::Pending
+                });
+            }
+            flushed = true;
+        }
+
+        let mut buffered = 0;
+        if !buf.is_empty() {
+            self.start_chunk()?;
+            buffered = buf.len().min(self.capacity_remaining());
+            self.buffer
+                .extend_from_slice(&buf[..buffered])
+                .map_err(|_| Error::Aead)?;
+        }
+        Ok(if flushed {
+            Progress::Flushed(buffered)
+        } else {
+            Progress::Buffered(buffered)
+        })
+    }
+
+    /// Writes out the buffered data according to `mode` like [`flush_with`](Self::flush_with),
+    /// but without blocking, see [`try_write`](Self::try_write). Returns
+    /// [`Progress::Flushed(0)`](Progress::Flushed) once everything has been written and the inner
+    /// writer flushed, or [`Progress::Buffered(0)`](Progress::Buffered) if there was nothing to
+    /// write. If the inner writer would block before then, including on its flush,
+    /// [`Progress::Pending`](Progress::Pending) is returned and the call should be repeated
+    pub fn try_flush(&mut self, mode: FlushMode) -> Result<Progress, Error<W::Error>>
+    where
+        W::Error: WouldBlock,
+    {
+        let mut flushed = false;
+        if self.pending.is_some() {
+            if !self.try_drain_pending()? {
+                return Ok(Progress::Pending);
+            }
+            flushed = true;
+        }
+        let stage = match mode {
+            FlushMode::Chunk => !self.buffer.is_empty(),
+            FlushMode::Finalize => !matches!(self.state, State::Finished),
+        };
+        if stage {
+            self.stage_chunk(matches!(mode, FlushMode::Finalize))?;
+            if !self.try_drain_pending()? {
+                return Ok(Progress::Pending);
+            }
+            flushed = true;
+        }
+        if nonblocking(self.writer.flush().map_err(Error::Io))?.is_none() {
+            return Ok(Progress::Pending);
+        }
+        Ok(if flushed {
+            Progress::Flushed(0)
+        } else {
+            Progress::Buffered(0)
+        })
+    }
+
+    /// Encrypts the currently buffered data and writes it out as a chunk without finalizing the
+    /// stream. Returns the number of bytes written to the inner writer, which includes the stream
+    /// header on the first chunk. Does nothing if no data is buffered
+    pub fn flush_chunk(&mut self) -> Result<usize, Error<W::Error>> {
+        self.drain_pending(true)?;
+        if self.buffer.is_empty() {
+            return Ok(0);
+        }
+        self.flush_buffer(false)
+    }
+
+    /// The plaintext a chunk holds once the buffered data has been written out
+    pub(crate) fn chunk_room(&self) -> usize {
+        match self.padded {
+            true => self.capacity.saturating_sub(PADDING_HEADER_LEN),
+            false => self.capacity,
+        }
+    }
+
+    /// Writes out the buffered data, followed by `parts` as the plaintext of a chunk of their
+    /// own, for framing records inside the stream. Fails unless they fit into one chunk, see
+    /// [`chunk_room`](Self::chunk_room)
+    pub(crate) fn write_chunk(&mut self, parts: &[&[u8]]) -> Result<usize, Error<W::Error>> {
+        let mut written = self.flush_chunk()?;
+        if matches!(self.state, State::Finished) {
+            return Err(Error::Aead);
+        }
+        if parts.iter().map(|part| part.len()).sum::<usize>() > self.chunk_room() {
+            return Err(Error::Aead);
+        }
+        self.start_chunk()?;
+        for part in parts {
+            self.buffer
+                .extend_from_slice(part)
+                .map_err(|_| Error::Aead)?;
+        }
+        written += self.flush_buffer(false)?;
+        Ok(written)
+    }
+
+    /// Switches the rest of the stream to a new key. The buffered data is written out as a
+    /// chunk, followed by an empty control chunk announcing the change, after which chunks are
+    /// encrypted with the AEAD the KDF set with [`with_rekey`](Self::with_rekey) derives for the
+    /// next epoch, starting over at the first position of the STREAM. Returns the number of
+    /// bytes written to the inner writer. Fails if no KDF was set or the stream was finalized
+    #[cfg(feature = "alloc")]
+    pub fn rekey(&mut self) -> Result<usize, Error<W::Error>>
+    where
+        A: NewAead,
+        S: NewStream<A>,
+    {
+        self.drain_pending(true)?;
+        if matches!(self.state, State::Finished) {
+            return Err(Error::Aead);
+        }
+        if self.rekey.is_none() {
+            return Err(Error::Aead);
+        }
+        let mut written = self.flush_chunk()?;
+        let aead = self.rekey.as_mut().ok_or(Error::Aead)?.next();
+        self.next_encryptor = Some(Encryptor::from_aead(aead, &self.nonce));
+        written += self.flush_buffer(false)?;
+        Ok(written)
+    }
+
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error<W::Error>> {
+        #[cfg(feature = "std")]
+        if let Some(overwrite) = self.overwrite.as_mut() {
+            let len = buf.len().min((overwrite.end - overwrite.position) as usize);
+            let written = self.writer.write(&buf[..len])?;
+            overwrite.position += written as u64;
+            if overwrite.position == overwrite.end {
+                self.overwrite = None;
+            }
+            return Ok(written);
+        }
+        if let Some(err) = self.threshold_error.take() {
+            return Err(err);
+        }
+        // only call out of the hot path when a chunk is still waiting to be written out
+        if self.pending.is_some() {
+            self.drain_pending(true)?;
+        }
+        if matches!(self.state, State::Finished) {
+            return Err(Error::Aead);
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if buf.len() > self.capacity_remaining() {
+            self.flush_buffer(false)?;
+        }
+        self.start_chunk()?;
+        let bytes_to_write = buf.len().min(self.capacity_remaining());
+        self.buffer
+            .extend_from_slice(&buf[..bytes_to_write])
+            .map_err(|_| Error::Aead)?;
+        if let Some(threshold) = self.flush_threshold {
+            let buffered = match self.padded {
+                true => self.buffer.len() - PADDING_HEADER_LEN,
+                false => self.buffer.len(),
+            };
+            if buffered >= threshold {
+                if let Err(err) = self.flush_buffer(false) {
+                    self.threshold_error = Some(err);
+                }
+            }
+        }
+        Ok(bytes_to_write)
+    }
+
+    /// Reads everything from `reader` until it reaches the end and encrypts it, returning the
+    /// number of bytes read. Data is read straight into the buffer with up to a chunk's worth of
+    /// plaintext at a time. The stream is not finalized, so more data can be written afterwards
+    pub fn write_from<R>(&mut self, mut reader: R) -> Result<u64, Error<W::Error>>
+    where
+        R: Read,
+        W::Error: From<R::Error>,
+    {
+        self.check_not_overwriting()?;
+        self.drain_pending(true)?;
+        if matches!(self.state, State::Finished) {
+            return Err(Error::Aead);
+        }
+        let mut total = 0;
+        loop {
+            if self.capacity_remaining() == 0 {
+                self.flush_buffer(false)?;
+            }
+            self.start_chunk()?;
+            let len = self.buffer.len();
+            self.fill_zeroes()?;
+            let read = reader.read(&mut self.buffer.as_mut()[len..]);
+            self.buffer.truncate(len + *read.as_ref().unwrap_or(&0));
+            match read.map_err(W::Error::from)? {
+                0 => break,
+                read => total += read as u64,
+            }
+        }
+        if self.padded && self.buffer.len() == PADDING_HEADER_LEN {
+            self.buffer.truncate(0);
+        }
+        Ok(total)
+    }
+
+    /// Finalizes the stream and flushes the inner writer, like
+    /// [`flush_with(FlushMode::Finalize)`](Self::flush_with), while keeping the Writer around to
+    /// inspect it. Finalizing is idempotent: once the stream is complete, calling this again,
+    /// [`into_inner`](Self::into_inner) or dropping the Writer writes nothing more
+    pub fn finish(&mut self) -> Result<(), Error<W::Error>> {
+        self.flush_with(FlushMode::Finalize)
+    }
+
+    /// Writes out the buffered data according to `mode` and flushes the inner writer
+    pub fn flush_with(&mut self, mode: FlushMode) -> Result<(), Error<W::Error>> {
+        match mode {
+            FlushMode::Chunk => {
+                self.flush_chunk()?;
+            }
+            FlushMode::Finalize => {
+                self.flush_buffer(true)?;
+            }
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Writes out the buffered data as a chunk, even if it isn't full, and flushes the inner
+    /// writer, without ending the stream. This is what flushing through the `Write` traits does,
+    /// under a name which says so: unlike [`finish`](Self::finish) more data can be written
+    /// afterwards, and unlike dropping the Writer the stream isn't finalized. Use it to get
+    /// everything written so far out of the Writer, such as for an append-only log. Flushing a
+    /// `File` doesn't reach the disk, for which `inner().sync_data()` has to be called after.
+    ///
+    /// Readers tailing the stream decrypt a chunk only once the header of the next chunk, or the
+    /// end of the stream, follows it, so the chunk written out last becomes readable with the
+    /// next chunk, see [`Pipe`](crate::Pipe)
+    pub fn sync(&mut self) -> Result<(), Error<W::Error>> {
+        self.flush_with(FlushMode::Chunk)
+    }
+
+    /// Swaps the inner writer for `new` without ending the encrypted stream, returning the old
+    /// one. The buffered data is first written out to the old writer as a chunk, together with
+    /// any chunk still in progress from the non-blocking methods, and the old writer is flushed.
+    /// The stream then continues in `new`, so the outputs of the two writers concatenated in
+    /// order form the complete stream.
+    ///
+    /// Chunks are never split across the swap, as the reader can only find the chunk boundaries
+    /// by following the stream from its start: a chunk whose bytes were divided between two
+    /// outputs which don't end up back to back fails to decrypt, along with everything after it
+    pub fn replace_writer(&mut self, new: W) -> Result<W, Error<W::Error>> {
+        self.flush_with(FlushMode::Chunk)?;
+        self.replaced_bytes = self.bytes_written;
+        Ok(mem::replace(&mut *self.writer, new))
+    }
+
+    fn flush(&mut self) -> Result<(), Error<W::Error>> {
+        self.flush_with(FlushMode::Chunk)
+    }
+}
+
+impl<A, B, W, S, F> IntoInnerError<EncryptBufWriter<A, B, W, S, F>, W::Error>
+where
+    A: AeadInPlace,
+    B: CappedBuffer,
+    W: Write,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    /// The bytes left in the buffer of the Writer which failed to write them out, see
+    /// [`EncryptBufWriter::buffered_bytes`](EncryptBufWriter::buffered_bytes)
+    pub fn buffered_bytes(&self) -> &[u8] {
+        self.writer().buffered_bytes()
+    }
+}
+
+impl<A, B, W, S, F> Drop for EncryptBufWriter<A, B, W, S, F>
+where
+    A: AeadInPlace,
+    B: CappedBuffer,
+    W: Write,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    fn drop(&mut self) {
+        if self.writer_taken {
+            return;
+        }
+        let failed = !self.abandoned && !self.finalize_on_drop();
+        // safety: the writer is still in place, as it wasn't taken
+        unsafe { ManuallyDrop::drop(&mut self.writer) }
+        if failed {
+            match self.drop_policy {
+                DropPolicy::Ignore => {}
+                #[cfg(feature = "std")]
+                DropPolicy::Panic if std::thread::panicking() => {}
+                DropPolicy::Panic => {
+                    panic!("failed to finalize the stream when dropping the Writer")
+                }
+                #[cfg(feature = "std")]
+                DropPolicy::Abort => std::process::abort(),
+                #[cfg(not(feature = "std"))]
+                DropPolicy::Abort => {
+                    panic!("failed to finalize the stream when dropping the Writer")
+                }
+            }
+        }
+    }
+}
+
+impl<A, B, W, S, F> fmt::Debug for EncryptBufWriter<A, B, W, S, F>
+where
+    A: AeadInPlace,
+    B: CappedBuffer,
+    W: Write,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EncryptBufWriter")
+            .field("state", &self.state)
+            .field("chunk_index", &self.chunk_index)
+            .field("capacity", &self.capacity)
+            .field("buffered", &self.buffer.len())
+            .field("nonce", &"<redacted>")
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<A, B, W, S, F> std::io::Write for EncryptBufWriter<A, B, W, S, F>
+where
+    A: AeadInPlace,
+    B: CappedBuffer,
+    W: Write,
+    W::Error: Into<std::io::Error>,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        Ok(self.write(buf)?)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(self.flush()?)
+    }
+}
+
+/// Seeks the inner writer within the output written so far, to overwrite parts of it such as a
+/// header region reserved with
+/// [`with_reserved_header`](EncryptBufWriter::with_reserved_header).
+///
+/// Seeking is only allowed between chunks, when no plaintext is buffered, and only to positions
+/// from the start of the stream, or of what the current writer holds after
+/// [`replace_writer`](EncryptBufWriter::replace_writer), up to the end of what has been written.
+/// Otherwise it fails with
+/// [`ErrorKind::InvalidInput`](std::io::ErrorKind::InvalidInput) rather than corrupting the
+/// chunk in progress. [`SeekFrom::End`](std::io::SeekFrom::End) is relative to the end of the
+/// output written so far.
+///
+/// Until the Writer is back at that end, whatever is written through it overwrites the output as
+/// is without being encrypted, and can't go past the end. Chunks can't be written in the
+/// meantime, so flushing or finalizing fails with [`Error::Aead`](Error::Aead)
+#[cfg(feature = "std")]
+impl<A, B, W, S, F> std::io::Seek for EncryptBufWriter<A, B, W, S, F>
+where
+    A: AeadInPlace,
+    B: CappedBuffer,
+    W: Write + std::io::Seek,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        use std::io::{ErrorKind, SeekFrom};
+        if !self.buffer.is_empty() || self.pending.is_some() {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                "can't seek with a chunk in progress",
+            ));
+        }
+        let (position, end) = match self.overwrite {
+            Some(overwrite) => (overwrite.position, overwrite.end),
+            None => {
+                let end = self.writer.stream_position()?;
+                (end, end)
+            }
+        };
+        let offset = |base: u64, offset: i64| match offset {
+            0.. => base.checked_add(offset as u64),
+            _ => base.checked_sub(offset.unsigned_abs()),
+        };
+        let invalid = |msg| std::io::Error::new(ErrorKind::InvalidInput, msg);
+        // only the output written to the current writer can be sought to
+        let start = end
+            .checked_sub(self.bytes_written - self.replaced_bytes)
+            .ok_or_else(|| invalid("the output written so far isn't part of the inner writer"))?;
+        let target = match pos {
+            SeekFrom::Start(target) => Some(target),
+            SeekFrom::End(n) => offset(end, n),
+            SeekFrom::Current(n) => offset(position, n),
+        }
+        .filter(|target| (start..=end).contains(target))
+        .ok_or_else(|| invalid("can only seek within the output written so far"))?;
+        self.writer.seek(SeekFrom::Start(target))?;
+        self.overwrite = match target {
+            target if target == end => None,
+            position => Some(Overwrite { position, end }),
+        };
+        Ok(target)
+    }
+}
+
+#[cfg(all(feature = "core2", not(feature = "std")))]
+impl<A, B, W, S, F> core2::io::Write for EncryptBufWriter<A, B, W, S, F>
+where
+    A: AeadInPlace,
+    B: CappedBuffer,
+    W: Write,
+    W::Error: Into<core2::io::Error>,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> core2::io::Result<usize> {
+        Ok(self.write(buf)?)
+    }
+    fn flush(&mut self) -> core2::io::Result<()> {
+        Ok(self.flush()?)
+    }
+}
+
+#[cfg(all(feature = "embedded-io", not(any(feature = "std", feature = "core2"))))]
+impl<A, B, W, S, F> embedded_io::ErrorType for EncryptBufWriter<A, B, W, S, F>
+where
+    A: AeadInPlace,
+    B: CappedBuffer,
+    W: Write,
+    W::Error: fmt::Debug,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    type Error = Error<W::Error>;
+}
+
+#[cfg(all(feature = "embedded-io", not(any(feature = "std", feature = "core2"))))]
+impl<A, B, W, S, F> embedded_io::Write for EncryptBufWriter<A, B, W, S, F>
+where
+    A: AeadInPlace,
+    B: CappedBuffer,
+    W: Write,
+    W::Error: fmt::Debug,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        self.write(buf)
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flush()
+    }
+}
+
+#[cfg(not(any(feature = "std", feature = "core2", feature = "embedded-io")))]
+impl<A, B, W, S, F> Write for EncryptBufWriter<A, B, W, S, F>
+where
+    A: AeadInPlace,
+    B: CappedBuffer,
+    W: Write,
+    S: StreamPrimitive<A>,
+    A::NonceSize: Sub<S::NonceOverhead>,
+    NonceSize<A, S>: ArrayLength<u8>,
+    F: Framing,
+{
+    type Error = Error<W::Error>;
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        Ok(self.write(buf)?)
+    }
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(self.flush()?)
+    }
+    fn write_all(&mut self, mut buf: &[u8]) -> Result<(), Self::Error> {
+        while !buf.is_empty() {
+            match self.write(buf) {
+                Ok(0) => return Err(Error::Aead),
+                Ok(n) => buf = &buf[n..],
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+    /// Finalizes the stream, which closes the inner writer in turn
+    fn close(&mut self) -> Result<(), Self::Error> {
+        self.finish()
+    }
+}
+
+#[cfg(feature = "std")]
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::KEY;
+    use crate::{ArrayBuffer, EncryptBE32BufWriter, InvalidStreamState};
+    use aead::stream::{StreamBE32, StreamLE31};
+    use chacha20poly1305::ChaCha20Poly1305;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    /// A writer which accepts a limited amount of bytes before failing
+    #[derive(Debug)]
+    struct LimitedWriter(usize);
+
+    impl std::io::Write for LimitedWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.0 == 0 {
+                return Err(std::io::ErrorKind::WriteZero.into());
+            }
+            let written = buf.len().min(self.0);
+            self.0 -= written;
+            Ok(written)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn on_drop_error() {
+        let errors = Arc::new(AtomicUsize::new(0));
+        let hook_errors = errors.clone();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            LimitedWriter(8),
+        )
+        .unwrap()
+        .on_drop_error(move |err| {
+            assert!(matches!(err, Error::Io(_)));
+            hook_errors.fetch_add(1, Ordering::SeqCst);
+        });
+        std::io::Write::write_all(&mut writer, b"hello world!").unwrap();
+        drop(writer);
+        assert_eq!(errors.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn flush_chunk() {
+        let tag_len = 16;
+        let nonce_len = 7;
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            &mut ciphertext,
+        )
+        .unwrap();
+        std::io::Write::write_all(&mut writer, b"hello world!").unwrap();
+        assert_eq!(writer.flush_chunk().unwrap(), nonce_len + 4 + 12 + tag_len);
+        assert_eq!(writer.flush_chunk().unwrap(), 0);
+        std::io::Write::write_all(&mut writer, b"hello").unwrap();
+        assert_eq!(writer.flush_chunk().unwrap(), 4 + 5 + tag_len);
+        drop(writer);
+        assert_eq!(
+            ciphertext.len(),
+            nonce_len + 2 * 4 + 17 + 2 * tag_len + 4 + tag_len
+        );
+    }
+
+    #[test]
+    fn capacity_too_small_for_tag() {
+        let new = |buffer| {
+            EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+                KEY.into(),
+                &Default::default(),
+                buffer,
+                Vec::new(),
+            )
+            .err()
+        };
+        assert!(matches!(new(Vec::with_capacity(8)), Some(InvalidCapacity)));
+        assert!(matches!(new(Vec::with_capacity(16)), Some(InvalidCapacity)));
+        assert!(new(Vec::with_capacity(17)).is_none());
+    }
+
+    #[test]
+    fn debug_redacts_nonce() {
+        let nonce = [0xabu8; 7];
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            nonce.as_ref().into(),
+            ArrayBuffer::<128>::new(),
+            Vec::new(),
+        )
+        .unwrap();
+        std::io::Write::write_all(&mut writer, b"hello world!").unwrap();
+        writer.flush_chunk().unwrap();
+        let debug = format!("{:?}", writer);
+        assert!(debug.contains("state: Writing"));
+        assert!(debug.contains("chunk_index: 1"));
+        assert!(debug.contains("<redacted>"));
+        assert!(!debug.contains("171"));
+    }
+
+    #[test]
+    fn reserved_header() {
+        let plaintext = [0x42u8; 300];
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            std::io::Cursor::new(Vec::new()),
+        )
+        .unwrap()
+        .with_reserved_header(16);
+        std::io::Write::write_all(&mut writer, &plaintext).unwrap();
+        let mut chunk_count = 0;
+        let ciphertext = writer
+            .finish_with_header(|chunks| {
+                chunk_count = chunks;
+                let mut header = *b"HDR1\0\0\0\0";
+                header[4..].copy_from_slice(&chunks.to_be_bytes());
+                header
+            })
+            .unwrap()
+            .into_inner();
+
+        assert!(chunk_count >= 3);
+        assert_eq!(&ciphertext[..4], b"HDR1");
+        assert_eq!(&ciphertext[4..8], &chunk_count.to_be_bytes());
+        assert_eq!(&ciphertext[8..16], &[0; 8]);
+
+        let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<256>::new(),
+            &ciphertext[16..],
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, plaintext);
+    }
+
+    #[test]
+    fn padding() {
+        let plaintext = [0x42u8; 300];
+
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<80>::new(),
+            &mut ciphertext,
+        )
+        .unwrap()
+        .with_padding()
+        .unwrap();
+        for part in plaintext.chunks(7) {
+            std::io::Write::write_all(&mut writer, part).unwrap();
+            writer.flush_chunk().unwrap();
+        }
+        std::io::Write::flush(&mut writer).unwrap();
+        drop(writer);
+
+        let mut chunks = Vec::new();
+        let mut rest = &ciphertext[7..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            chunks.push(len);
+            rest = &rest[4 + len..];
+        }
+        let (last, chunks) = chunks.split_last().unwrap();
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|len| *len == 80));
+        assert!(*last < 80);
+
+        let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<80>::new(),
+            ciphertext.as_slice(),
+        )
+        .unwrap()
+        .with_padding();
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, plaintext);
+    }
+
+    #[test]
+    fn flush_modes() {
+        let tag_len = 16;
+        let nonce_len = 7;
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            &mut ciphertext,
+        )
+        .unwrap();
+
+        std::io::Write::write_all(&mut writer, b"hello").unwrap();
+        std::io::Write::flush(&mut writer).unwrap();
+        assert_eq!(writer.inner().len(), nonce_len + 4 + 5 + tag_len);
+        writer.flush_with(FlushMode::Chunk).unwrap();
+        assert_eq!(writer.inner().len(), nonce_len + 4 + 5 + tag_len);
+
+        std::io::Write::write_all(&mut writer, b" world!").unwrap();
+        writer.flush_with(FlushMode::Finalize).unwrap();
+        assert_eq!(writer.inner().len(), nonce_len + 2 * (4 + tag_len) + 5 + 7);
+        assert!(std::io::Write::write_all(&mut writer, b"more").is_err());
+        drop(writer);
+
+        let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<128>::new(),
+            ciphertext.as_slice(),
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, b"hello world!");
+    }
+
+    #[test]
+    fn write_from() {
+        let plaintext = (0..1000).map(|i| i as u8).collect::<Vec<_>>();
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            &mut ciphertext,
+        )
+        .unwrap();
+        assert_eq!(writer.write_from(plaintext.as_slice()).unwrap(), 1000);
+        assert!(writer.chunk_index > 1);
+        drop(writer);
+
+        let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<128>::new(),
+            ciphertext.as_slice(),
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, plaintext);
+    }
+
+    #[test]
+    fn total_length() {
+        let plaintext = [0x42u8; 300];
+
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<80>::new(),
+            &mut ciphertext,
+        )
+        .unwrap()
+        .with_total_length();
+        std::io::Write::write_all(&mut writer, &plaintext).unwrap();
+        writer.flush_with(FlushMode::Finalize).unwrap();
+        drop(writer);
+
+        let decrypt = |ciphertext: &[u8], total_length: bool| {
+            let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+                KEY.into(),
+                ArrayBuffer::<80>::new(),
+                ciphertext,
+            )
+            .unwrap();
+            if total_length {
+                reader = reader.with_total_length();
+            }
+            let mut out = Vec::new();
+            std::io::Read::read_to_end(&mut reader, &mut out).map(|_| out)
+        };
+        assert_eq!(decrypt(&ciphertext, true).unwrap(), plaintext);
+        assert!(decrypt(&ciphertext, false).is_err());
+
+        // Cut the stream after the second chunk
+        let mut end = 7;
+        for _ in 0..2 {
+            let len = &ciphertext[end..end + 4];
+            end += 4 + u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        }
+        assert!(end < ciphertext.len());
+        assert!(decrypt(&ciphertext[..end], true).is_err());
+    }
+
+    #[test]
+    fn nonce_generator() {
+        let mut counter = 0u64;
+        let mut next_nonce = || {
+            counter += 1;
+            Nonce::<ChaCha20Poly1305, StreamBE32<_>>::clone_from_slice(&counter.to_be_bytes()[1..])
+        };
+
+        let mut streams = Vec::new();
+        for message in [&b"first"[..], b"second"] {
+            let mut ciphertext = Vec::new();
+            let mut writer =
+                EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new_with_nonce_generator(
+                    KEY.into(),
+                    &mut next_nonce,
+                    ArrayBuffer::<64>::new(),
+                    &mut ciphertext,
+                )
+                .unwrap();
+            std::io::Write::write_all(&mut writer, message).unwrap();
+            drop(writer);
+            streams.push(ciphertext);
+        }
+        assert_eq!(&streams[0][..7], &[0, 0, 0, 0, 0, 0, 1]);
+        assert_eq!(&streams[1][..7], &[0, 0, 0, 0, 0, 0, 2]);
+
+        let mut out = Vec::new();
+        let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<64>::new(),
+            streams[1].as_slice(),
+        )
+        .unwrap();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, b"second");
+
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new_with_nonce_generator(
+            KEY.into(),
+            &mut next_nonce,
+            ArrayBuffer::<64>::new(),
+            &mut ciphertext,
+        )
+        .unwrap()
+        .without_nonce_prefix();
+        std::io::Write::write_all(&mut writer, b"third").unwrap();
+        drop(writer);
+
+        let mut out = Vec::new();
+        let mut reader = crate::DecryptBufReader::<_, _, _, StreamBE32<_>>::from_aead_with_nonce(
+            ChaCha20Poly1305::new(KEY.into()),
+            [0, 0, 0, 0, 0, 0, 3][..].into(),
+            ArrayBuffer::<64>::new(),
+            ciphertext.as_slice(),
+        )
+        .unwrap();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, b"third");
+    }
+
+    /// A STREAM which appends the chunk's position after the tag, adding 4 bytes of overhead
+    struct PositionedStream(StreamBE32<ChaCha20Poly1305>);
+
+    impl aead::stream::NewStream<ChaCha20Poly1305> for PositionedStream {
+        fn from_aead(aead: ChaCha20Poly1305, nonce: &Nonce<ChaCha20Poly1305, Self>) -> Self {
+            Self(StreamBE32::from_aead(aead, nonce))
+        }
+    }
+
+    impl StreamPrimitive<ChaCha20Poly1305> for PositionedStream {
+        type NonceOverhead = aead::consts::U5;
+        type Counter = u32;
+        const COUNTER_INCR: u32 = 1;
+        const COUNTER_MAX: u32 = u32::MAX;
+
+        fn encrypt_in_place(
+            &self,
+            position: u32,
+            last_block: bool,
+            associated_data: &[u8],
+            buffer: &mut dyn aead::Buffer,
+        ) -> aead::Result<()> {
+            self.0
+                .encrypt_in_place(position, last_block, associated_data, buffer)?;
+            buffer.extend_from_slice(&position.to_be_bytes())
+        }
+
+        fn decrypt_in_place(
+            &self,
+            position: u32,
+            last_block: bool,
+            associated_data: &[u8],
+            buffer: &mut dyn aead::Buffer,
+        ) -> aead::Result<()> {
+            let len = buffer.len().checked_sub(4).ok_or(aead::Error)?;
+            if buffer.as_ref()[len..] != position.to_be_bytes() {
+                return Err(aead::Error);
+            }
+            buffer.truncate(len);
+            self.0
+                .decrypt_in_place(position, last_block, associated_data, buffer)
+        }
+    }
+
+    #[test]
+    fn stream_overhead() {
+        let plaintext = [0x42u8; 100];
+        fn new_writer<W: Write>(
+            ciphertext: W,
+        ) -> EncryptBufWriter<ChaCha20Poly1305, ArrayBuffer<64>, W, PositionedStream> {
+            EncryptBufWriter::new(
+                KEY.into(),
+                &Default::default(),
+                ArrayBuffer::<64>::new(),
+                ciphertext,
+            )
+            .unwrap()
+        }
+
+        let mut ciphertext = Vec::new();
+        let mut writer = new_writer(&mut ciphertext);
+        assert_eq!(writer.chunk_overhead(), 16);
+        assert!(std::io::Write::write_all(&mut writer, &plaintext).is_err());
+        drop(writer);
+
+        let mut ciphertext = Vec::new();
+        let mut writer = new_writer(&mut ciphertext)
+            .with_stream_overhead(4)
+            .unwrap()
+            .with_total_length();
+        assert_eq!(writer.chunk_overhead(), 20);
+        for part in plaintext.chunks(44) {
+            std::io::Write::write_all(&mut writer, part).unwrap();
+        }
+        writer.flush_with(FlushMode::Finalize).unwrap();
+        drop(writer);
+
+        let mut chunks = Vec::new();
+        let mut rest = &ciphertext[7..];
+        while !rest.is_empty() {
+            let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+            chunks.push(len);
+            rest = &rest[4 + len..];
+        }
+        assert_eq!(chunks, [64, 64, 12 + 20]);
+
+        // the Reader takes the overhead off the chunks for the total length
+        let mut reader = crate::DecryptBufReader::<_, _, _, PositionedStream>::new(
+            KEY.into(),
+            ArrayBuffer::<64>::new(),
+            ciphertext.as_slice(),
+        )
+        .unwrap()
+        .with_stream_overhead(4)
+        .unwrap()
+        .with_total_length();
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, plaintext);
+
+        // a Writer dropped along with the error leaves the inner writer untouched
+        let mut ciphertext = Vec::new();
+        assert!(new_writer(&mut ciphertext)
+            .with_stream_overhead(48)
+            .is_err());
+        assert!(ciphertext.is_empty());
+        let mut reader = crate::DecryptBufReader::<_, _, _, PositionedStream>::new(
+            KEY.into(),
+            ArrayBuffer::<64>::new(),
+            &b""[..],
+        )
+        .unwrap();
+        assert_eq!(reader.chunk_overhead(), 16);
+        reader = reader.with_stream_overhead(4).unwrap();
+        assert_eq!(reader.chunk_overhead(), 20);
+        assert!(reader.with_stream_overhead(48).is_err());
+    }
+
+    #[test]
+    fn write_through_mut_ref() {
+        fn write_greeting(mut writer: impl std::io::Write) -> std::io::Result<()> {
+            writer.write_all(b"hello ")?;
+            writer.write_all(b"world")
+        }
+
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<64>::new(),
+            &mut ciphertext,
+        )
+        .unwrap();
+        write_greeting(&mut writer).unwrap();
+        write_greeting(&mut writer).unwrap();
+        writer.flush_with(FlushMode::Finalize).unwrap();
+        drop(writer);
+
+        let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<64>::new(),
+            ciphertext.as_slice(),
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, b"hello worldhello world");
+    }
+
+    #[test]
+    fn is_finished() {
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<64>::new(),
+            &mut ciphertext,
+        )
+        .unwrap();
+        assert!(!writer.is_finished());
+        std::io::Write::write_all(&mut writer, b"hello").unwrap();
+        writer.flush_with(FlushMode::Chunk).unwrap();
+        assert!(!writer.is_finished());
+        writer.flush_with(FlushMode::Finalize).unwrap();
+        assert!(writer.is_finished());
+        assert!(std::io::Write::write(&mut writer, b"more").is_err());
+    }
+
+    #[test]
+    fn replace_writer() {
+        let plaintext = (0..300u32).map(|i| i as u8).collect::<Vec<_>>();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<64>::new(),
+            Vec::new(),
+        )
+        .unwrap();
+        std::io::Write::write_all(&mut writer, &plaintext[..100]).unwrap();
+        let first = writer.replace_writer(Vec::new()).unwrap();
+        std::io::Write::write_all(&mut writer, &plaintext[100..]).unwrap();
+        let second = writer.into_inner().unwrap();
+        assert!(!first.is_empty() && !second.is_empty());
+
+        let ciphertext = [first, second].concat();
+        let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<64>::new(),
+            ciphertext.as_slice(),
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, plaintext);
+    }
+
+    #[test]
+    fn buffered_bytes_after_failure() {
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            LimitedWriter(0),
+        )
+        .unwrap();
+        std::io::Write::write_all(&mut writer, b"hello world!").unwrap();
+        assert_eq!(writer.buffered_bytes(), b"hello world!");
+
+        let err = writer.into_inner().unwrap_err();
+        assert!(matches!(err.error(), Error::Io(_)));
+        // the final chunk was sealed before writing it failed
+        let sealed = err.buffered_bytes().to_vec();
+        assert_eq!(sealed.len(), 12 + 16);
+
+        // nothing reached the inner writer, so the stream can be salvaged by writing out the
+        // header, the chunk header and the sealed chunk elsewhere
+        let mut ciphertext = vec![0u8; 7];
+        ciphertext.extend_from_slice(&(sealed.len() as u32).to_be_bytes());
+        ciphertext.extend_from_slice(&sealed);
+        let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<128>::new(),
+            ciphertext.as_slice(),
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, b"hello world!");
+    }
+
+    #[test]
+    fn finish_is_idempotent() {
+        let errors = Arc::new(AtomicUsize::new(0));
+        let hook_errors = errors.clone();
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<64>::new(),
+            &mut ciphertext,
+        )
+        .unwrap()
+        .on_drop_error(move |_| {
+            hook_errors.fetch_add(1, Ordering::SeqCst);
+        });
+        std::io::Write::write_all(&mut writer, b"hello world!").unwrap();
+        writer.finish().unwrap();
+        let len = writer.inner().len();
+        writer.finish().unwrap();
+        writer.flush_with(FlushMode::Finalize).unwrap();
+        assert_eq!(writer.inner().len(), len);
+        drop(writer);
+        assert_eq!(ciphertext.len(), len);
+        assert_eq!(errors.load(Ordering::SeqCst), 0);
+
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<64>::new(),
+            Vec::new(),
+        )
+        .unwrap();
+        std::io::Write::write_all(&mut writer, b"hello world!").unwrap();
+        writer.finish().unwrap();
+        assert_eq!(writer.into_inner().unwrap(), ciphertext);
+    }
+
+    #[test]
+    fn end_marker() {
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<64>::new(),
+            Vec::new(),
+        )
+        .unwrap()
+        .with_end_marker();
+        std::io::Write::write_all(&mut writer, b"hello").unwrap();
+        let mut ciphertext = writer.into_inner().unwrap();
+        assert!(ciphertext.ends_with(&[0; 4]));
+
+        // the reader stops at the end marker, leaving whatever follows unread
+        ciphertext.extend_from_slice(b"next");
+        let mut rest = ciphertext.as_slice();
+        let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<64>::new(),
+            &mut rest,
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        drop(reader);
+        assert_eq!(out, b"hello");
+        assert_eq!(rest, b"next");
+    }
+
+    #[test]
+    fn seek_to_backfill_header() {
+        use std::io::{Seek, SeekFrom};
+
+        let plaintext = [0x42u8; 300];
+        // the stream is written after the start of an outer format
+        let mut inner = std::io::Cursor::new(b"outer".to_vec());
+        inner.set_position(5);
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            inner,
+        )
+        .unwrap()
+        .with_reserved_header(8);
+        std::io::Write::write_all(&mut writer, &plaintext[..100]).unwrap();
+        // the chunk in progress can't be left behind
+        assert!(writer.seek(SeekFrom::Start(5)).is_err());
+        writer.flush_with(FlushMode::Chunk).unwrap();
+
+        // only the output of this stream can be overwritten
+        assert!(writer.seek(SeekFrom::Start(4)).is_err());
+        assert!(writer.seek(SeekFrom::End(1)).is_err());
+        let end = writer.stream_position().unwrap();
+        assert_eq!(writer.seek(SeekFrom::Start(5)).unwrap(), 5);
+        std::io::Write::write_all(&mut writer, b"HDR1").unwrap();
+        assert_eq!(writer.stream_position().unwrap(), 9);
+        // no chunks can be written while overwriting
+        assert!(matches!(
+            writer.flush_with(FlushMode::Finalize),
+            Err(Error::Aead)
+        ));
+        assert_eq!(writer.seek(SeekFrom::End(0)).unwrap(), end);
+        std::io::Write::write_all(&mut writer, &plaintext[100..]).unwrap();
+        writer.flush_with(FlushMode::Finalize).unwrap();
+
+        // and after finalizing, overwriting past the end fails
+        let output = writer.inner().get_ref();
+        let tail = [&output[output.len() - 2..], b"more"].concat();
+        writer.seek(SeekFrom::End(-2)).unwrap();
+        assert!(std::io::Write::write_all(&mut writer, &tail).is_err());
+        writer.seek(SeekFrom::End(0)).unwrap();
+        let ciphertext = writer.into_inner().unwrap().into_inner();
+
+        assert_eq!(&ciphertext[..13], b"outerHDR1\0\0\0\0");
+        let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<256>::new(),
+            &ciphertext[13..],
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, plaintext);
+    }
+
+    #[test]
+    fn seek_after_replace_writer() {
+        use std::io::{Cursor, Seek, SeekFrom};
+
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            Cursor::new(Vec::new()),
+        )
+        .unwrap()
+        .with_reserved_header(8);
+        std::io::Write::write_all(&mut writer, &[0x42; 100]).unwrap();
+        writer.replace_writer(Cursor::new(Vec::new())).unwrap();
+        // nothing has been written to the new writer yet
+        assert_eq!(writer.seek(SeekFrom::End(0)).unwrap(), 0);
+        assert!(writer.seek(SeekFrom::End(-1)).is_err());
+
+        std::io::Write::write_all(&mut writer, &[0x42; 200]).unwrap();
+        writer.flush_with(FlushMode::Finalize).unwrap();
+        let end = writer.stream_position().unwrap();
+        assert_eq!(end, writer.inner().get_ref().len() as u64);
+        assert_eq!(writer.seek(SeekFrom::Start(0)).unwrap(), 0);
+        assert_eq!(writer.seek(SeekFrom::End(0)).unwrap(), end);
+
+        // the reserved region went to the replaced writer
+        let err = writer.finish_with_header(|_| *b"HDR1").unwrap_err();
+        assert!(matches!(err.error(), Error::Io(_)));
+    }
+
+    #[test]
+    fn stats() {
+        let plaintext = [0x42u8; 300];
+        for padded in [false, true] {
+            let mut ciphertext = Vec::new();
+            let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+                KEY.into(),
+                &Default::default(),
+                ArrayBuffer::<128>::new(),
+                &mut ciphertext,
+            )
+            .unwrap();
+            if padded {
+                writer = writer.with_padding().unwrap();
+            }
+            assert_eq!(writer.stats(), Stats::default());
+            for byte in plaintext.chunks(1) {
+                std::io::Write::write_all(&mut writer, byte).unwrap();
+            }
+            writer.flush_with(FlushMode::Finalize).unwrap();
+            let stats = writer.stats();
+            drop(writer);
+
+            // 112 bytes of plaintext per chunk, or 108 next to the padding length
+            let expected = match padded {
+                false => Stats {
+                    chunks: 3,
+                    plaintext_bytes: 300,
+                    ciphertext_bytes: 300 + 3 * 16,
+                },
+                true => Stats {
+                    chunks: 3,
+                    plaintext_bytes: 300,
+                    ciphertext_bytes: 2 * 128 + (4 + 84 + 16),
+                },
+            };
+            assert_eq!(stats, expected);
+
+            let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+                KEY.into(),
+                ArrayBuffer::<256>::new(),
+                ciphertext.as_slice(),
+            )
+            .unwrap();
+            if padded {
+                reader = reader.with_padding();
+            }
+            assert_eq!(reader.stats(), Stats::default());
+            let mut out = Vec::new();
+            std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+            assert_eq!(reader.stats(), expected);
+        }
+    }
+
+    #[test]
+    fn sync() {
+        let pipe = crate::Pipe::new();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            pipe.clone(),
+        )
+        .unwrap();
+        let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<128>::new(),
+            pipe.clone(),
+        )
+        .unwrap();
+        let plaintext = (0..=255u8).cycle().take(600).collect::<Vec<_>>();
+
+        // two full chunks go out as they fill, the rest only with the sync
+        for chunk in plaintext[..300].chunks(112) {
+            std::io::Write::write_all(&mut writer, chunk).unwrap();
+        }
+        assert_eq!(pipe.len(), 7 + 2 * (4 + 128));
+        writer.sync().unwrap();
+        assert_eq!(pipe.len(), 7 + 2 * (4 + 128) + 4 + 76 + 16);
+        assert_eq!(writer.buffered_bytes(), b"");
+
+        // everything but the chunk written last can be read, as the next header follows it
+        let mut out = vec![0u8; 224];
+        std::io::Read::read_exact(&mut reader, &mut out).unwrap();
+        assert_eq!(out, plaintext[..224]);
+
+        // the stream goes on after the sync and the short chunk decrypts with the rest
+        std::io::Write::write_all(&mut writer, &plaintext[300..]).unwrap();
+        writer.finish().unwrap();
+        std::io::Read::read_to_end(&mut reader, &mut out).unwrap();
+        assert_eq!(out, plaintext);
+    }
+
+    #[test]
+    fn resume_from_state() {
+        let plaintext: Vec<u8> = (0..500).map(|i| i as u8).collect();
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &[0x42; 7].into(),
+            ArrayBuffer::<128>::new(),
+            &mut ciphertext,
+        )
+        .unwrap();
+        for chunk in plaintext[..200].chunks(112) {
+            std::io::Write::write_all(&mut writer, chunk).unwrap();
+        }
+        assert!(writer.export_state().is_none());
+        // the process goes away without finalizing the stream
+        let (state, _) = writer.into_state().unwrap();
+        let bytes = state.to_bytes();
+
+        let state = StreamState::<ChaCha20Poly1305, StreamBE32<_>>::from_bytes(&bytes).unwrap();
+        assert_eq!(state.nonce.as_slice(), &[0x42; 7]);
+        assert_eq!(state.chunk_index, 2);
+        assert!(!state.finished);
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::from_state(
+            ChaCha20Poly1305::new(KEY.into()),
+            &state,
+            ArrayBuffer::<128>::new(),
+            &mut ciphertext,
+        )
+        .unwrap();
+        std::io::Write::write_all(&mut writer, &plaintext[200..]).unwrap();
+        writer.flush_with(FlushMode::Finalize).unwrap();
+        assert!(writer.export_state().unwrap().finished);
+        drop(writer);
+
+        let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<128>::new(),
+            &ciphertext[..],
+        )
+        .unwrap();
+        assert_eq!(reader.decrypt_all().unwrap(), plaintext);
+
+        assert_eq!(
+            StreamState::<ChaCha20Poly1305, StreamBE32<_>>::from_bytes(&bytes[1..]),
+            Err(InvalidStreamState)
+        );
+        let mut bytes = bytes;
+        *bytes.last_mut().unwrap() = 2;
+        assert_eq!(
+            StreamState::<ChaCha20Poly1305, StreamBE32<_>>::from_bytes(&bytes),
+            Err(InvalidStreamState)
+        );
+
+        // a chunk index past the end of the stream fails without writing anything
+        let state = StreamState::<ChaCha20Poly1305, StreamLE31<_>> {
+            nonce: Default::default(),
+            chunk_index: u32::MAX,
+            finished: false,
+        };
+        let mut ciphertext = Vec::new();
+        let resumed = EncryptBufWriter::<_, _, _, StreamLE31<_>>::from_state(
+            ChaCha20Poly1305::new(KEY.into()),
+            &state,
+            ArrayBuffer::<128>::new(),
+            &mut ciphertext,
+        );
+        assert_eq!(resumed.err(), Some(ChunkIndexError::ChunkIndex));
+        assert!(ciphertext.is_empty());
+    }
+
+    #[test]
+    fn flush_threshold() {
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            &mut ciphertext,
+        )
+        .unwrap()
+        .with_flush_threshold(1);
+        for message in [&b"hi"[..], b"there", b"!"] {
+            std::io::Write::write_all(&mut writer, message).unwrap();
+        }
+        // every write went out as a chunk of its own, each with its header and tag
+        assert_eq!(writer.stats().chunks, 3);
+        assert_eq!(writer.inner().len(), 7 + 3 * (4 + 16) + 8);
+        writer.flush_with(FlushMode::Finalize).unwrap();
+        drop(writer);
+
+        let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<128>::new(),
+            &ciphertext[..],
+        )
+        .unwrap();
+        assert_eq!(reader.decrypt_all().unwrap(), b"hithere!");
+
+        // below the threshold data is held back as usual
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            Vec::new(),
+        )
+        .unwrap()
+        .with_flush_threshold(8);
+        std::io::Write::write_all(&mut writer, b"hi").unwrap();
+        assert_eq!(writer.stats().chunks, 0);
+        std::io::Write::write_all(&mut writer, b"there!").unwrap();
+        assert_eq!(writer.stats().chunks, 1);
+
+        // the data is taken even if its chunk fails to go out, and the next call fails instead
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            LimitedWriter(8),
+        )
+        .unwrap()
+        .with_flush_threshold(1);
+        assert_eq!(std::io::Write::write(&mut writer, b"hi").unwrap(), 2);
+        assert!(std::io::Write::write(&mut writer, b"there").is_err());
+        writer.abandon();
+    }
+
+    #[test]
+    fn key_from_slice() {
+        let key: &[u8] = KEY;
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::from_key_slice(
+            key,
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            &mut ciphertext,
+        )
+        .unwrap();
+        std::io::Write::write_all(&mut writer, b"hello world").unwrap();
+        drop(writer);
+
+        let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::from_key_slice(
+            key,
+            ArrayBuffer::<128>::new(),
+            &ciphertext[..],
+        )
+        .unwrap();
+        assert_eq!(reader.decrypt_all().unwrap(), b"hello world");
+
+        for len in [0, 16, 31, 33] {
+            let key = [0x42; 33];
+            assert!(matches!(
+                EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::from_key_slice(
+                    &key[..len],
+                    &Default::default(),
+                    ArrayBuffer::<128>::new(),
+                    Vec::new(),
+                ),
+                Err(KeySliceError::KeyLength)
+            ));
+            assert!(matches!(
+                crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::from_key_slice(
+                    &key[..len],
+                    ArrayBuffer::<128>::new(),
+                    &ciphertext[..],
+                ),
+                Err(KeySliceError::KeyLength)
+            ));
+        }
+    }
+
+    fn round_trip<const N: usize>(plaintext: &[u8], padded: bool) -> Stats {
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<N>::new(),
+            &mut ciphertext,
+        )
+        .unwrap();
+        if padded {
+            writer = writer.with_padding().unwrap();
+        }
+        // a byte at a time, so that every chunk is filled
+        for byte in plaintext.chunks(1) {
+            std::io::Write::write_all(&mut writer, byte).unwrap();
+        }
+        std::io::Write::write_all(&mut writer, &[]).unwrap();
+        writer.flush_with(FlushMode::Finalize).unwrap();
+        let stats = writer.stats();
+        drop(writer);
+
+        let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<N>::new(),
+            &ciphertext[..],
+        )
+        .unwrap();
+        if padded {
+            reader = reader.with_padding();
+        }
+        assert_eq!(reader.decrypt_all().unwrap(), plaintext);
+        stats
+    }
+
+    #[test]
+    fn capacity_boundaries() {
+        let plaintext = [0x42; 97];
+        // 48 bytes of plaintext fit next to the tag
+        for (len, chunks) in [(0, 1), (47, 1), (48, 1), (49, 2), (96, 2), (97, 3)] {
+            assert_eq!(round_trip::<64>(&plaintext[..len], false).chunks, chunks);
+        }
+        for (len, chunks) in [(44, 1), (45, 2), (88, 2), (89, 3)] {
+            assert_eq!(round_trip::<64>(&plaintext[..len], true).chunks, chunks);
+        }
+
+        // the smallest padded buffer holds a single byte next to the length
+        assert_eq!(round_trip::<21>(&plaintext[..3], true).chunks, 3);
+        // a smaller one fails, and the Writer dropped along with the error writes nothing
+        let mut ciphertext = Vec::new();
+        assert!(EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<20>::new(),
+            &mut ciphertext,
+        )
+        .unwrap()
+        .with_padding()
+        .is_err());
+        assert!(ciphertext.is_empty());
+    }
+
+    #[test]
+    fn aad_provider() {
+        fn xor_index(index: u32) -> Vec<u8> {
+            let mut aad = b"base associated data".to_vec();
+            for (byte, index) in aad.iter_mut().zip(index.to_be_bytes()) {
+                *byte ^= index;
+            }
+            aad
+        }
+        let plaintext = [0x42; 300];
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            &mut ciphertext,
+        )
+        .unwrap()
+        .with_aad_provider(xor_index);
+        std::io::Write::write_all(&mut writer, &plaintext).unwrap();
+        writer.flush_with(FlushMode::Finalize).unwrap();
+        drop(writer);
+
+        let decrypt = |ciphertext: &[u8], aad: fn(u32) -> Vec<u8>| {
+            crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+                KEY.into(),
+                ArrayBuffer::<128>::new(),
+                ciphertext,
+            )
+            .unwrap()
+            .with_aad_provider(aad)
+            .decrypt_all()
+        };
+        assert_eq!(decrypt(&ciphertext, xor_index).unwrap(), plaintext);
+
+        // the same associated data for every chunk doesn't match
+        assert!(decrypt(&ciphertext, |_| xor_index(0)).is_err());
+        // nor does tampering with a chunk
+        let mut tampered = ciphertext.clone();
+        tampered[20] ^= 1;
+        assert!(decrypt(&tampered, xor_index).is_err());
+        // nor reading without the associated data
+        assert!(crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<128>::new(),
+            &ciphertext[..],
+        )
+        .unwrap()
+        .decrypt_all()
+        .is_err());
+    }
+
+    #[test]
+    fn into_inner_drops_aad_provider() {
+        let captured = Arc::new(AtomicUsize::new(0));
+        let calls = captured.clone();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            Vec::new(),
+        )
+        .unwrap()
+        .with_aad_provider(move |_| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Vec::new()
+        });
+        for chunk in [0x42; 300].chunks(112) {
+            std::io::Write::write_all(&mut writer, chunk).unwrap();
+        }
+        assert_eq!(Arc::strong_count(&captured), 2);
+        writer.into_inner().unwrap();
+        assert_eq!(captured.load(Ordering::SeqCst), 3);
+        assert_eq!(Arc::strong_count(&captured), 1);
+    }
+
+    #[test]
+    fn into_inner_drops_metadata() {
+        // the metadata is dropped along with the rest of the associated data, which the provider
+        // holding on to `captured` tells apart from it being leaked
+        let captured = Arc::new(());
+        let held = captured.clone();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            Vec::new(),
+        )
+        .unwrap()
+        .with_metadata(&b"recipient: alice"[..])
+        .with_aad_provider(move |_| {
+            let _ = &held;
+            Vec::new()
+        });
+        std::io::Write::write_all(&mut writer, &[0x42; 300]).unwrap();
+        let ciphertext = writer.into_inner().unwrap();
+        assert_eq!(Arc::strong_count(&captured), 1);
+
+        let mut reader = crate::DecryptBE32BufReader::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            ArrayBuffer::<128>::new(),
+            &ciphertext[..],
+        )
+        .unwrap()
+        .with_metadata(64)
+        .with_aad_provider(|_| Vec::new());
+        assert_eq!(reader.metadata().unwrap(), b"recipient: alice");
+        assert_eq!(reader.decrypt_all().unwrap(), [0x42; 300]);
+    }
+
+    #[test]
+    fn buffer_fill_level() {
+        let mut ciphertext = Vec::new();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            &mut ciphertext,
+        )
+        .unwrap();
+        assert_eq!(writer.buffer_capacity(), 128);
+        assert_eq!(writer.buffer_len(), 0);
+        std::io::Write::write_all(&mut writer, &[0x42; 100]).unwrap();
+        assert_eq!(writer.buffer_len(), 100);
+        // a write which doesn't fit writes out the buffered data and starts on the next chunk
+        std::io::Write::write_all(&mut writer, &[0x42; 20]).unwrap();
+        assert_eq!(writer.buffer_len(), 20);
+        writer.flush_with(FlushMode::Finalize).unwrap();
+        assert_eq!(writer.buffer_len(), 0);
+        assert_eq!(writer.buffer_capacity(), 128);
+    }
+
+    #[test]
+    fn drop_policy_panic() {
+        let errors = Arc::new(AtomicUsize::new(0));
+        let hook_errors = errors.clone();
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            LimitedWriter(8),
+        )
+        .unwrap()
+        .on_drop_error(move |_| {
+            hook_errors.fetch_add(1, Ordering::SeqCst);
+        })
+        .with_drop_policy(DropPolicy::Panic);
+        std::io::Write::write_all(&mut writer, b"hello world!").unwrap();
+        let dropped = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| drop(writer)));
+        assert!(dropped.is_err());
+        assert_eq!(errors.load(Ordering::SeqCst), 1);
+
+        // dropping panics only if finalizing fails
+        let mut writer = EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+            KEY.into(),
+            &Default::default(),
+            ArrayBuffer::<128>::new(),
+            Vec::new(),
+        )
+        .unwrap()
+        .with_drop_policy(DropPolicy::Panic);
+        std::io::Write::write_all(&mut writer, b"hello world!").unwrap();
+        drop(writer);
+    }
+
+    #[test]
+    fn predicted_len_matches_writer() {
+        for &chunk_size in &[1, 16, 100, 128] {
+            for &len in &[0, 1, 15, 16, 17, 100, 128, 300, 1000] {
+                let mut ciphertext = Vec::new();
+                let mut writer = crate::EncryptBE32BufWriter::<ChaCha20Poly1305, _, _>::new(
+                    KEY.into(),
+                    &Default::default(),
+                    Vec::with_capacity(chunk_size + 16),
+                    &mut ciphertext,
+                )
+                .unwrap();
+                std::io::Write::write_all(&mut writer, &vec![0x42; len]).unwrap();
+                writer.flush_with(crate::FlushMode::Finalize).unwrap();
+                drop(writer);
+                assert_eq!(
+                    predicted_ciphertext_len::<ChaCha20Poly1305, StreamBE32<_>>(len, chunk_size),
+                    ciphertext.len(),
+                    "{} bytes in chunks of {}",
+                    len,
+                    chunk_size
+                );
+            }
         }
-        Ok(())
     }
 }